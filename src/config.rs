@@ -1,9 +1,16 @@
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use std::time::Duration;
 
 use serde::Deserialize;
 
-use crate::recorder::VideoCodec;
+use crate::recorder::{
+    ArchiveFormat, ArchiveOptions, ArchiveOutput, AudioOptions, EncryptionOptions, FrameRateMode,
+    LowLatencyOptions, Output, OverlayOptions, Packaging, PrivacyMask, PushOnlyOutput, PushTarget,
+    RecorderError, Rendition, ResolutionChange, SegmentFormat, SnapshotOptions, StreamSelection,
+    ThumbnailOptions, TimelapseOptions, VideoCodec, thumbnail_master_path,
+};
+use crate::rtsp_server::RtspServerConfig;
+use crate::server::ServerConfig;
 
 /// Top-level configuration describing one or more RTSP-to-HLS capture jobs.
 #[derive(Deserialize)]
@@ -11,18 +18,109 @@ pub struct AppConfig {
     /// Collection of recordings that should be executed by the application.
     #[serde(default)]
     pub recordings: Vec<RecordingConfig>,
+    /// Optional built-in HTTP server publishing the recorded playlists and segments.
+    #[serde(default)]
+    pub server: Option<ServerConfig>,
+    /// Optional RTSP server republishing every recorded camera, so each camera keeps a single
+    /// upstream connection no matter how many viewers there are.
+    #[serde(default)]
+    pub rtsp_server: Option<RtspServerConfig>,
 }
 
 /// Parameters for an individual RTSP recording job.
 #[derive(Deserialize)]
 pub struct RecordingConfig {
+    /// Optional human-readable camera name. Defaults to the RTSP host when omitted.
+    #[serde(default)]
+    pub name: Option<String>,
     /// Network location of the RTSP source, including credentials if required.
     pub rtsp_url: String,
     /// Optional wall-clock duration (seconds) to limit how long the recorder runs.
     #[serde(default)]
     pub duration_seconds: Option<u64>,
+    /// Which of the source's video and audio streams are recorded. Defaults to all of them.
+    #[serde(default)]
+    pub streams: StreamSelection,
     /// Parameters that control details of the generated HLS output.
     pub hls: HlsConfig,
+    /// Optional burned-in camera name and wall-clock timestamp.
+    #[serde(default)]
+    pub overlay: Option<OverlayOptions>,
+    /// Polygonal regions that are blanked or pixelated before encoding.
+    #[serde(default)]
+    pub privacy_masks: Vec<PrivacyMask>,
+    /// Optional cap on the stored frame rate; excess frames are dropped.
+    #[serde(default)]
+    pub max_fps: Option<f64>,
+    /// Optional constant output frame rate; frames are dropped or duplicated to match it.
+    /// Takes precedence over `max_fps` when both are set.
+    #[serde(default)]
+    pub target_fps: Option<f64>,
+    /// `rescale` (default) keeps the starting resolution when the camera switches mid-stream;
+    /// `reinitialize` re-opens the encoders at the new resolution and marks a discontinuity; it
+    /// needs every transcoded output to be MPEG-TS.
+    #[serde(default)]
+    pub on_resolution_change: ResolutionChange,
+    /// RTMP, SRT or UDP targets restreaming the recording while it is written locally.
+    /// With renditions, the first rendition is forwarded.
+    #[serde(default)]
+    pub push: Vec<PushTarget>,
+    /// Further outputs recorded from the same RTSP connection and decoder, e.g. an MP4 archive
+    /// next to a low-bitrate live stream. Each picks its own codecs.
+    #[serde(default)]
+    pub outputs: Vec<OutputConfig>,
+    /// Optional JPEG snapshots written to `latest.jpg` every few seconds or on request.
+    #[serde(default)]
+    pub snapshot: Option<SnapshotOptions>,
+    /// Optional timelapse of the recording, rendered by the `timelapse` command or nightly.
+    #[serde(default)]
+    pub timelapse: Option<TimelapseOptions>,
+}
+
+/// An additional output of a recording, selected by its `type`.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum OutputConfig {
+    /// Another HLS, DASH or CMAF presentation, configured like the recording's `hls` block.
+    Hls(HlsConfig),
+    /// Long-term MP4 or Matroska archive files, optionally rotated by time.
+    Archive(ArchiveConfig),
+    /// Restreaming targets fed without writing anything locally.
+    Push(PushConfig),
+}
+
+/// Output block for long-term archive files.
+#[derive(Deserialize)]
+pub struct ArchiveConfig {
+    /// Destination path of the archive. When rotating it may hold `strftime` conversions such as
+    /// `%Y%m%d-%H%M`; otherwise the start time is appended to the file stem, e.g.
+    /// `camera_20240501T120000Z.mp4` for a single archive.
+    pub path: String,
+    /// Container: `mp4` (default, fragmented) or `mkv`.
+    #[serde(default)]
+    pub format: ArchiveFormat,
+    /// Optional rotation interval in minutes, aligned to the local clock; `60` rotates on the hour.
+    #[serde(default)]
+    pub rotate_minutes: Option<u32>,
+    /// Video codec of the archive; `copy` keeps the camera's own stream.
+    #[serde(default = "default_video_codec")]
+    pub video_codec: VideoCodec,
+    /// Audio handling: `copy` (default), `aac`, `opus`, or `none` to drop audio.
+    #[serde(default)]
+    pub audio: AudioOptions,
+}
+
+/// Output block for push targets with their own codec choice.
+#[derive(Deserialize)]
+pub struct PushConfig {
+    /// RTMP, SRT or UDP targets receiving the stream.
+    pub targets: Vec<PushTarget>,
+    /// Video codec sent to the targets; `copy` forwards the camera's own stream.
+    #[serde(default = "default_video_codec")]
+    pub video_codec: VideoCodec,
+    /// Audio handling: `copy` (default), `aac`, `opus`, or `none` to drop audio.
+    #[serde(default)]
+    pub audio: AudioOptions,
 }
 
 /// Nested configuration block for HLS muxer options.
@@ -36,12 +134,87 @@ pub struct HlsConfig {
     /// Optional clamp for how many segment URIs remain in the sliding playlist window.
     #[serde(default)]
     pub playlist_size: Option<u32>,
-    /// Optional custom segment filename pattern. Supports FFmpeg printf-style counters.
+    /// Optional custom segment filename pattern. Supports FFmpeg printf-style counters and the
+    /// `{session}` and `{wallclock}` placeholders.
     #[serde(default)]
     pub segment_filename: Option<String>,
     /// Desired codec for the encoded video stream within the HLS segments.
     #[serde(default = "default_video_codec")]
     pub video_codec: VideoCodec,
+    /// Audio handling: `copy` (default), `aac`, `opus`, or `none` to drop audio.
+    #[serde(default)]
+    pub audio: AudioOptions,
+    /// Optional adaptive bitrate ladder. When non-empty, `playlist_path` becomes a master
+    /// playlist and each rendition gets its own media playlist next to it.
+    #[serde(default)]
+    pub renditions: Vec<Rendition>,
+    /// Optional Low-Latency HLS mode with fMP4 partial segments, e.g. `{ "part_duration_seconds": 0.5 }`.
+    #[serde(default)]
+    pub low_latency: Option<LowLatencyOptions>,
+    /// Manifest format: `hls` (default), `dash`, or `cmaf` for DASH plus HLS over shared segments.
+    /// With `dash` and `cmaf`, `playlist_path` names the `.mpd` manifest.
+    #[serde(default)]
+    pub packaging: Packaging,
+    /// Segment container: `mpegts` or `fmp4`. Defaults to `fmp4` for H.265 and `mpegts` otherwise.
+    #[serde(default)]
+    pub segment_format: Option<SegmentFormat>,
+    /// Optional AES-128 segment encryption with periodic key rotation.
+    #[serde(default)]
+    pub encryption: Option<EncryptionOptions>,
+    /// Tag segments with `EXT-X-PROGRAM-DATE-TIME`, taken from the camera's RTCP sender
+    /// reports when available and the local clock otherwise.
+    #[serde(default)]
+    pub program_date_time: bool,
+    /// Extend the playlist of a previous run instead of overwriting it. Default segment names
+    /// gain the session start time, e.g. `stream_20240501T120000Z_00042.ts`.
+    #[serde(default)]
+    pub append: bool,
+    /// Optional preview thumbnails for scrubbing, packed into sprite sheets and listed in a
+    /// WebVTT track referenced from the playlist.
+    #[serde(default)]
+    pub thumbnails: Option<ThumbnailOptions>,
+}
+
+impl AppConfig {
+    /// Rejects settings that are valid on their own but unsafe or inconsistent together, such as
+    /// encryption keys the HTTP server would publish without authentication.
+    pub fn validate(&self) -> Result<(), RecorderError> {
+        // Thumbnail cues are timed from the start of the recording, so the playlist has to keep
+        // every segment for them to line up.
+        for output in self
+            .recordings
+            .iter()
+            .flat_map(RecordingConfig::hls_outputs)
+        {
+            if output.thumbnails.is_some() && output.playlist_size != Some(0) {
+                return Err(RecorderError::InvalidOutput(format!(
+                    "thumbnails of {} need \"playlist_size\": 0, as their cues are timed from the start of the recording",
+                    output.playlist_path.display()
+                )));
+            }
+        }
+        let Some(server) = &self.server else {
+            return Ok(());
+        };
+        let root = normalize_path(Path::new(&server.root));
+        for output in self
+            .recordings
+            .iter()
+            .flat_map(RecordingConfig::hls_outputs)
+        {
+            let Some(encryption) = &output.encryption else {
+                continue;
+            };
+            if normalize_path(&encryption.key_dir).starts_with(&root) {
+                return Err(RecorderError::InvalidOutput(format!(
+                    "key_dir {} lies under the HTTP server root {}, which would serve its keys to anyone",
+                    encryption.key_dir.display(),
+                    server.root
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 impl RecordingConfig {
@@ -50,16 +223,203 @@ impl RecordingConfig {
         self.duration_seconds.map(Duration::from_secs)
     }
 
+    /// Returns the configured camera name, falling back to the RTSP host without credentials.
+    pub fn camera_name(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+
+        let without_scheme = self
+            .rtsp_url
+            .split_once("://")
+            .map_or(self.rtsp_url.as_str(), |(_, rest)| rest);
+        let authority = without_scheme.split('/').next().unwrap_or(without_scheme);
+        let host = authority
+            .rsplit_once('@')
+            .map_or(authority, |(_, host)| host);
+        host.to_string()
+    }
+
+    /// Collects the frame processing applied between decoding and encoding.
+    pub fn video_processing(&self) -> crate::recorder::VideoProcessing {
+        crate::recorder::VideoProcessing {
+            camera_name: self.camera_name(),
+            overlay: self.overlay.clone(),
+            privacy_masks: self.privacy_masks.clone(),
+            frame_rate: self.frame_rate_mode(),
+            resolution_change: self.on_resolution_change,
+            snapshot: self.snapshot.clone(),
+        }
+    }
+
+    /// Resolves `target_fps` / `max_fps` into a frame-rate conversion mode.
+    pub fn frame_rate_mode(&self) -> Option<FrameRateMode> {
+        self.target_fps
+            .map(FrameRateMode::Target)
+            .or(self.max_fps.map(FrameRateMode::Max))
+    }
+
     /// Converts the configuration into an `HlsOutput` suitable for the recorder.
     pub fn hls_output(&self) -> crate::recorder::HlsOutput {
+        self.hls.output(&self.push)
+    }
+
+    /// Expands the configuration into the outputs to record: one per rendition, or the single
+    /// playlist when no ladder is configured, followed by the additional `outputs`.
+    pub fn recorded_outputs(&self) -> Vec<Output> {
+        let mut outputs: Vec<Output> = self
+            .hls
+            .outputs(&self.push)
+            .into_iter()
+            .map(Output::Hls)
+            .collect();
+        for output in &self.outputs {
+            match output {
+                OutputConfig::Hls(hls) => {
+                    outputs.extend(hls.outputs(&[]).into_iter().map(Output::Hls))
+                }
+                OutputConfig::Archive(archive) => outputs.push(Output::Archive(archive.output())),
+                OutputConfig::Push(push) => outputs.push(Output::Push(push.output())),
+            }
+        }
+        outputs
+    }
+
+    /// The HLS, DASH and CMAF presentations among the recorded outputs.
+    pub fn hls_outputs(&self) -> Vec<crate::recorder::HlsOutput> {
+        self.recorded_outputs()
+            .into_iter()
+            .filter_map(|output| match output {
+                Output::Hls(hls) => Some(hls),
+                Output::Archive(_) | Output::Push(_) => None,
+            })
+            .collect()
+    }
+
+    /// Media playlist of the first regular HLS output, whose wall-clock index stands for the
+    /// whole recording.
+    pub fn indexed_playlist_path(&self) -> Option<PathBuf> {
+        self.hls_outputs()
+            .into_iter()
+            .find(|output| output.packaging == Packaging::Hls)
+            .map(|output| output.playlist_path)
+    }
+
+    /// Location of the master playlist, present when HLS renditions or thumbnails are configured.
+    ///
+    /// DASH and CMAF renditions each keep their own manifest.
+    pub fn master_playlist_path(&self) -> Option<PathBuf> {
+        self.hls.master_playlist_path()
+    }
+}
+
+impl HlsConfig {
+    /// Converts the block into an `HlsOutput` restreamed to `push`.
+    pub fn output(&self, push: &[PushTarget]) -> crate::recorder::HlsOutput {
         crate::recorder::HlsOutput {
-            playlist_path: PathBuf::from(&self.hls.playlist_path),
-            segment_duration: self.hls.segment_duration_seconds,
-            playlist_size: self.hls.playlist_size,
-            segment_filename: self.hls.segment_filename.clone(),
-            video_codec: self.hls.video_codec,
+            playlist_path: PathBuf::from(&self.playlist_path),
+            segment_duration: self.segment_duration_seconds,
+            playlist_size: self.playlist_size,
+            segment_filename: self.segment_filename.clone(),
+            video_codec: self.video_codec,
+            audio: self.audio,
+            width: None,
+            height: None,
+            video_bitrate: None,
+            low_latency: self.low_latency,
+            packaging: self.packaging,
+            segment_format: self.segment_format,
+            encryption: self.encryption.clone(),
+            program_date_time: self.program_date_time,
+            push: push.to_vec(),
+            master_playlist: None,
+            append: self.append,
+            thumbnails: self.thumbnails.clone(),
+        }
+    }
+
+    /// One output per rendition, or the single playlist when no ladder is configured.
+    pub fn outputs(&self, push: &[PushTarget]) -> Vec<crate::recorder::HlsOutput> {
+        let base = self.output(push);
+        let master_playlist = self.master_playlist_path();
+        if self.renditions.is_empty() {
+            return vec![crate::recorder::HlsOutput {
+                master_playlist,
+                ..base
+            }];
+        }
+        let mut outputs: Vec<_> = self
+            .renditions
+            .iter()
+            .map(|rendition| crate::recorder::HlsOutput {
+                master_playlist: master_playlist.clone(),
+                ..base.rendition(rendition)
+            })
+            .collect();
+        // Push targets receive a single copy of the recording, and renditions share one
+        // thumbnails track.
+        for output in outputs.iter_mut().skip(1) {
+            output.push.clear();
+            output.thumbnails = None;
+        }
+        outputs
+    }
+
+    /// Location of the master playlist: the `playlist_path` when HLS renditions are configured,
+    /// or `<playlist>_master.m3u8` announcing the thumbnails track of a single playlist.
+    pub fn master_playlist_path(&self) -> Option<PathBuf> {
+        if self.packaging != Packaging::Hls {
+            return None;
+        }
+        if !self.renditions.is_empty() {
+            return Some(PathBuf::from(&self.playlist_path));
+        }
+        self.thumbnails
+            .as_ref()
+            .map(|_| thumbnail_master_path(Path::new(&self.playlist_path)))
+    }
+}
+
+impl ArchiveConfig {
+    /// Converts the block into an archive output written to `path`.
+    pub fn output(&self) -> ArchiveOutput {
+        ArchiveOutput {
+            path: PathBuf::from(&self.path),
+            video_codec: self.video_codec,
+            audio: self.audio,
+            options: ArchiveOptions {
+                format: self.format,
+                rotate_minutes: self.rotate_minutes,
+            },
+        }
+    }
+}
+
+impl PushConfig {
+    /// Converts the block into a push-only output.
+    pub fn output(&self) -> PushOnlyOutput {
+        PushOnlyOutput {
+            targets: self.targets.clone(),
+            video_codec: self.video_codec,
+            audio: self.audio,
+        }
+    }
+}
+
+/// `path` made absolute against the working directory, with `.` and `..` resolved lexically.
+fn normalize_path(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
         }
     }
+    normalized
 }
 
 fn default_video_codec() -> VideoCodec {
@@ -1,5 +1,12 @@
 pub mod config;
 pub mod recorder;
+pub mod rtsp_server;
+pub mod server;
 
-pub use config::{AppConfig, HlsConfig, RecordingConfig};
-pub use recorder::{HlsOutput, RecorderError, RtspRecorder, VideoCodec, derive_segment_template};
+pub use config::{AppConfig, ArchiveConfig, HlsConfig, OutputConfig, PushConfig, RecordingConfig};
+pub use recorder::{
+    ArchiveOutput, AudioMode, AudioOptions, FrameRateMode, HlsOutput, MaskStyle, Output,
+    OverlayOptions, OverlayPosition, Packaging, PrivacyMask, PushOnlyOutput, PushTarget,
+    RecorderError, Rendition, ResolutionChange, RtspRecorder, StreamSelection, StreamSelector,
+    VideoCodec, VideoProcessing, derive_segment_template,
+};
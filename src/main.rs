@@ -1,13 +1,34 @@
-use otnvr::config::AppConfig;
-use otnvr::recorder::RtspRecorder;
+use otnvr::config::{AppConfig, RecordingConfig};
+use otnvr::recorder::{
+    RtspRecorder, SnapshotHub, TimelapseOptions, next_daily_run, now_epoch_seconds,
+    parse_time_of_day, parse_window_time, playlist_coverage, previous_day, render_timelapse,
+    timelapse_file_name,
+};
+use otnvr::rtsp_server::{self, RestreamHub, camera_id};
+use otnvr::server;
+use serde_json::json;
 use std::fs;
+use std::path::Path;
 use std::process;
+use std::thread;
+use std::time::Duration;
+
+/// Gaps of at least this many seconds are reported as events unless `--gap-threshold` is given.
+const DEFAULT_GAP_THRESHOLD: f64 = 60.0;
 
 fn main() {
     let mut args = std::env::args();
     let app = args.next().unwrap_or_else(|| "rtsp-recorder".to_string());
 
     let config_path = match args.next() {
+        Some(command) if command == "coverage" => {
+            coverage(&app, args.collect());
+            return;
+        }
+        Some(command) if command == "timelapse" => {
+            timelapse(&app, args.collect());
+            return;
+        }
         Some(path) => path,
         None => {
             print_usage(&app);
@@ -15,7 +36,108 @@ fn main() {
         }
     };
 
-    let config_contents = match fs::read_to_string(&config_path) {
+    let config = load_config(&config_path);
+
+    if config.recordings.is_empty() {
+        eprintln!("No recordings defined in configuration.");
+        process::exit(1);
+    }
+
+    let mut recorder = match RtspRecorder::new() {
+        Ok(recorder) => recorder,
+        Err(error) => {
+            eprintln!("Failed to initialize FFmpeg recorder: {error}");
+            process::exit(1);
+        }
+    };
+
+    if let Some(server_config) = config.server.clone() {
+        let snapshots = SnapshotHub::new();
+        recorder = recorder.with_snapshots(snapshots.clone());
+        thread::spawn(move || {
+            if let Err(error) = server::serve(&server_config, snapshots) {
+                eprintln!("HTTP server stopped: {error}");
+            }
+        });
+    }
+
+    if let Some(rtsp_config) = config.rtsp_server.clone() {
+        let hub = RestreamHub::new();
+        recorder = recorder.with_restream(hub.clone());
+        thread::spawn(move || {
+            if let Err(error) = rtsp_server::serve(&rtsp_config, hub) {
+                eprintln!("RTSP server stopped: {error}");
+            }
+        });
+    }
+
+    schedule_timelapses(&config);
+
+    // Every camera records on its own thread, sharing the RTSP and HTTP server hubs.
+    let recorder = &recorder;
+    let failures = thread::scope(|scope| {
+        let handles: Vec<_> = config
+            .recordings
+            .iter()
+            .enumerate()
+            .map(|(index, recording)| scope.spawn(move || record(recorder, index, recording)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or(false))
+            .filter(|finished| !finished)
+            .count()
+    });
+    if failures > 0 {
+        process::exit(1);
+    }
+}
+
+/// Records one configured camera, returning whether it finished without error.
+fn record(recorder: &RtspRecorder, index: usize, recording: &RecordingConfig) -> bool {
+    let duration_limit = recording.duration();
+    let duration_summary = duration_limit
+        .as_ref()
+        .map(|d| format!(" (captured for {} seconds)", d.as_secs()));
+    let outputs = recording.recorded_outputs();
+    let playlist_path = recording
+        .master_playlist_path()
+        .unwrap_or_else(|| recording.hls_outputs()[0].playlist_path.clone());
+    let processing = recording.video_processing();
+
+    println!(
+        "Recording {}: capturing {} -> {}",
+        index + 1,
+        recording.rtsp_url,
+        playlist_path.display()
+    );
+
+    if let Err(error) = recorder.record(
+        &recording.rtsp_url,
+        &outputs,
+        &processing,
+        &recording.streams,
+        duration_limit,
+    ) {
+        eprintln!(
+            "Failed to record RTSP stream for {}: {error}",
+            recording.rtsp_url
+        );
+        return false;
+    }
+
+    println!(
+        "Recording {} complete: playlist at {}{}",
+        index + 1,
+        playlist_path.display(),
+        duration_summary.unwrap_or_default()
+    );
+    true
+}
+
+/// Reads and parses the configuration file, exiting with a message when that fails.
+fn load_config(config_path: &str) -> AppConfig {
+    let config_contents = match fs::read_to_string(config_path) {
         Ok(contents) => contents,
         Err(error) => {
             eprintln!("Failed to read config file {config_path}: {error}");
@@ -31,51 +153,221 @@ fn main() {
             process::exit(1);
         }
     };
+    if let Err(error) = config.validate() {
+        eprintln!("Invalid configuration: {error}");
+        process::exit(1);
+    }
+    config
+}
 
-    if config.recordings.is_empty() {
-        eprintln!("No recordings defined in configuration.");
+/// `coverage` subcommand: prints the recording coverage of every camera as JSON.
+fn coverage(app: &str, args: Vec<String>) {
+    let mut config_path = None;
+    let mut start = None;
+    let mut end = None;
+    let mut gap_threshold = DEFAULT_GAP_THRESHOLD;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| match args.next() {
+            Some(value) => value,
+            None => {
+                eprintln!("Missing value for {name}");
+                process::exit(1);
+            }
+        };
+        match arg.as_str() {
+            "--from" | "--to" => {
+                let raw = value(&arg);
+                let Some(time) = parse_window_time(&raw) else {
+                    eprintln!("Invalid time for {arg}: {raw}");
+                    process::exit(1);
+                };
+                if arg == "--from" {
+                    start = Some(time);
+                } else {
+                    end = Some(time);
+                }
+            }
+            "--gap-threshold" => {
+                let raw = value(&arg);
+                gap_threshold = match raw.parse() {
+                    Ok(seconds) => seconds,
+                    Err(_) => {
+                        eprintln!("Invalid gap threshold: {raw}");
+                        process::exit(1);
+                    }
+                };
+            }
+            _ if config_path.is_none() && !arg.starts_with("--") => config_path = Some(arg),
+            _ => {
+                print_usage(app);
+                process::exit(1);
+            }
+        }
+    }
+    let Some(config_path) = config_path else {
+        print_usage(app);
         process::exit(1);
+    };
+    let config = load_config(&config_path);
+    // A camera that is down right now shows as a gap running up to the present.
+    let end = end.unwrap_or_else(now_epoch_seconds);
+
+    let mut cameras = Vec::new();
+    for recording in &config.recordings {
+        // Every HLS output of a recording shares its coverage; the first one stands for all.
+        let Some(playlist_path) = recording.indexed_playlist_path() else {
+            continue;
+        };
+        let report = match playlist_coverage(&playlist_path, start, Some(end), gap_threshold) {
+            Ok(report) => report,
+            Err(error) => {
+                eprintln!("No segment index for {}: {error}", playlist_path.display());
+                None
+            }
+        };
+        cameras.push(json!({
+            "camera": recording.camera_name(),
+            "playlist": playlist_path,
+            "coverage": report,
+        }));
     }
 
-    let recorder = match RtspRecorder::new() {
-        Ok(recorder) => recorder,
+    match serde_json::to_string_pretty(&cameras) {
+        Ok(json) => println!("{json}"),
         Err(error) => {
-            eprintln!("Failed to initialize FFmpeg recorder: {error}");
+            eprintln!("Failed to encode coverage report: {error}");
             process::exit(1);
         }
+    }
+}
+
+/// `timelapse` subcommand: renders the timelapse of every camera with a `timelapse` block,
+/// covering the previous UTC day unless `--from` and `--to` are given.
+fn timelapse(app: &str, args: Vec<String>) {
+    let mut config_path = None;
+    let mut camera = None;
+    let (mut start, mut end) = previous_day(now_epoch_seconds());
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| match args.next() {
+            Some(value) => value,
+            None => {
+                eprintln!("Missing value for {name}");
+                process::exit(1);
+            }
+        };
+        match arg.as_str() {
+            "--from" | "--to" => {
+                let raw = value(&arg);
+                let Some(time) = parse_window_time(&raw) else {
+                    eprintln!("Invalid time for {arg}: {raw}");
+                    process::exit(1);
+                };
+                if arg == "--from" {
+                    start = time;
+                } else {
+                    end = time;
+                }
+            }
+            "--camera" => camera = Some(value(&arg)),
+            _ if config_path.is_none() && !arg.starts_with("--") => config_path = Some(arg),
+            _ => {
+                print_usage(app);
+                process::exit(1);
+            }
+        }
+    }
+    let Some(config_path) = config_path else {
+        print_usage(app);
+        process::exit(1);
     };
+    if end <= start {
+        eprintln!("--to must be later than --from");
+        process::exit(1);
+    }
+    let config = load_config(&config_path);
 
-    for (index, recording) in config.recordings.iter().enumerate() {
-        let duration_limit = recording.duration();
-        let duration_summary = duration_limit
-            .as_ref()
-            .map(|d| format!(" (captured for {} seconds)", d.as_secs()));
-        let hls_output = recording.hls_output();
-
-        println!(
-            "Recording {}: capturing {} -> {}",
-            index + 1,
-            recording.rtsp_url,
-            hls_output.playlist_path.display()
-        );
+    let mut failed = false;
+    for recording in &config.recordings {
+        let id = camera_id(&recording.camera_name());
+        if camera.as_ref().is_some_and(|camera| *camera != id) {
+            continue;
+        }
+        let (Some(options), Some(playlist_path)) =
+            (&recording.timelapse, recording.indexed_playlist_path())
+        else {
+            continue;
+        };
+        failed |= !render_camera_timelapse(&id, &playlist_path, start, end, options);
+    }
+    if failed {
+        process::exit(1);
+    }
+}
 
-        if let Err(error) = recorder.record(&recording.rtsp_url, &hls_output, duration_limit) {
-            eprintln!(
-                "Failed to record RTSP stream for {}: {error}",
-                recording.rtsp_url
+/// Renders the previous UTC day's timelapse of every recording with `nightly_at` set, in the
+/// background at that time of day, for as long as the recorder runs.
+fn schedule_timelapses(config: &AppConfig) {
+    for recording in &config.recordings {
+        let Some(options) = recording.timelapse.clone() else {
+            continue;
+        };
+        let Some(nightly_at) = options.nightly_at.as_deref() else {
+            continue;
+        };
+        let Some(time_of_day) = parse_time_of_day(nightly_at) else {
+            eprintln!("Invalid timelapse nightly_at {nightly_at}; expected HH:MM in UTC");
+            continue;
+        };
+        let id = camera_id(&recording.camera_name());
+        let Some(playlist_path) = recording.indexed_playlist_path() else {
+            eprintln!("Timelapse of {id} needs a regular HLS output");
+            continue;
+        };
+        thread::spawn(move || {
+            loop {
+                let now = now_epoch_seconds();
+                let run = next_daily_run(now, time_of_day);
+                thread::sleep(Duration::from_secs_f64(run - now));
+                let (start, end) = previous_day(run);
+                render_camera_timelapse(&id, &playlist_path, start, end, &options);
+            }
+        });
+    }
+}
+
+/// Renders one camera's timelapse into its `dir`, reporting the outcome. Returns whether it
+/// succeeded.
+fn render_camera_timelapse(
+    camera: &str,
+    playlist_path: &Path,
+    start: f64,
+    end: f64,
+    options: &TimelapseOptions,
+) -> bool {
+    let output_path = options.dir.join(timelapse_file_name(camera, start, end));
+    match render_timelapse(playlist_path, start, end, options, &output_path) {
+        Ok(frames) => {
+            println!(
+                "Timelapse of {camera}: {frames} frames written to {}",
+                output_path.display()
             );
-            process::exit(1);
+            true
+        }
+        Err(error) => {
+            eprintln!("Failed to render timelapse of {camera}: {error}");
+            false
         }
-
-        println!(
-            "Recording {} complete: playlist at {}{}",
-            index + 1,
-            hls_output.playlist_path.display(),
-            duration_summary.unwrap_or_default()
-        );
     }
 }
 
 fn print_usage(app: &str) {
     eprintln!("Usage: {app} <config-file>");
+    eprintln!(
+        "       {app} coverage <config-file> [--from <time>] [--to <time>] [--gap-threshold <seconds>]"
+    );
+    eprintln!("       {app} timelapse <config-file> [--from <time>] [--to <time>] [--camera <id>]");
 }
@@ -0,0 +1,116 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+/// Container of long-term archive files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveFormat {
+    /// Fragmented MP4, written one fragment per keyframe.
+    #[default]
+    Mp4,
+    /// Matroska, written in clusters of at most a second.
+    Mkv,
+}
+
+impl ArchiveFormat {
+    /// Name of the FFmpeg muxer writing this container.
+    pub fn muxer(self) -> &'static str {
+        match self {
+            ArchiveFormat::Mp4 => "mp4",
+            ArchiveFormat::Mkv => "matroska",
+        }
+    }
+
+    /// Conventional file extension of this container.
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Mp4 => "mp4",
+            ArchiveFormat::Mkv => "mkv",
+        }
+    }
+
+    /// Muxer options that leave a file playable up to its last complete fragment or cluster
+    /// when the process dies without writing a trailer.
+    pub fn crash_safe_options(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            ArchiveFormat::Mp4 => &[("movflags", "frag_keyframe+empty_moov+default_base_moof")],
+            ArchiveFormat::Mkv => &[("cluster_time_limit", "1000")],
+        }
+    }
+}
+
+/// Container and rotation of an archive output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ArchiveOptions {
+    /// Container of the archive files.
+    pub format: ArchiveFormat,
+    /// Starts a new file every this many minutes, aligned to the local clock; one file when unset.
+    pub rotate_minutes: Option<u32>,
+}
+
+impl ArchiveOptions {
+    /// Name of the FFmpeg muxer opened for the archive: `segment` when rotating.
+    pub fn muxer(&self) -> &'static str {
+        match self.rotate_minutes {
+            Some(_) => "segment",
+            None => self.format.muxer(),
+        }
+    }
+
+    /// Options passed to the muxer when the header is written.
+    ///
+    /// Rotation uses the `segment` muxer, cutting at the first keyframe after each multiple of
+    /// the interval since midnight, so a 60-minute interval rotates on the hour.
+    pub fn muxer_options(&self) -> Vec<(&'static str, String)> {
+        let crash_safe = self.format.crash_safe_options();
+        let mut options = vec![("flush_packets", "1".to_string())];
+        match self.rotate_minutes {
+            Some(minutes) => {
+                let format_options: Vec<String> = crash_safe
+                    .iter()
+                    .map(|(name, value)| format!("{name}={value}"))
+                    .collect();
+                options.extend([
+                    ("segment_format", self.format.muxer().to_string()),
+                    ("segment_format_options", format_options.join(":")),
+                    ("segment_time", (u64::from(minutes) * 60).to_string()),
+                    ("segment_atclocktime", "1".to_string()),
+                    ("strftime", "1".to_string()),
+                    ("reset_timestamps", "1".to_string()),
+                ]);
+            }
+            None => options.extend(
+                crash_safe
+                    .iter()
+                    .map(|(name, value)| (*name, value.to_string())),
+            ),
+        }
+        options
+    }
+
+    /// File name, or `strftime` pattern when rotating, that the archive is written to.
+    ///
+    /// A single archive gets `_<session>` appended to its stem, e.g. `camera_20240501T120000Z.mp4`,
+    /// so a restart never overwrites the previous one. Rotated archives whose path has no `%`
+    /// conversion get `_%Y%m%d-%H%M%S` instead, so every file is named after the local time it
+    /// starts at.
+    pub fn file_template(&self, path: &Path, session: &str) -> String {
+        let rotating = self.rotate_minutes.is_some();
+        if rotating && path.to_string_lossy().contains('%') {
+            return path.to_string_lossy().to_string();
+        }
+        let stem = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("archive");
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or(self.format.extension());
+        let suffix = if rotating { "%Y%m%d-%H%M%S" } else { session };
+        path.with_file_name(format!("{stem}_{suffix}.{extension}"))
+            .to_string_lossy()
+            .to_string()
+    }
+}
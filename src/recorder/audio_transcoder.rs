@@ -0,0 +1,209 @@
+use ffmpeg_next::{
+    ChannelLayout, Dictionary, Packet, Rational, Rescale, codec, decoder, encoder, format, frame,
+};
+
+use super::filter_graph::FilterChain;
+use super::master_playlist::audio_codec_string;
+use super::output_muxer::OutputMuxer;
+use super::{AudioMode, AudioOptions, RecorderError};
+
+/// Decodes an input audio stream, resamples it, and re-encodes it to AAC or Opus.
+pub(crate) struct AudioTranscoder {
+    decoder: decoder::Audio,
+    encoder: encoder::Audio,
+    filter: FilterChain,
+    /// Time base of the frames leaving the resampling chain.
+    filter_time_base: Rational,
+    encoder_time_base: Rational,
+    ost_index: usize,
+    bitrate: u32,
+}
+
+impl AudioTranscoder {
+    pub(crate) fn new(
+        ist: &format::stream::Stream,
+        muxer: &mut OutputMuxer,
+        options: &AudioOptions,
+    ) -> Result<Self, RecorderError> {
+        let ost_index = muxer.stream_count();
+        let octx = muxer.context();
+        let global_header = octx.format().flags().contains(format::Flags::GLOBAL_HEADER);
+        let decoder = ffmpeg_next::codec::context::Context::from_parameters(ist.parameters())?
+            .decoder()
+            .audio()?;
+
+        let encoder_codec = match options.mode {
+            AudioMode::Aac => encoder::find(codec::Id::AAC),
+            AudioMode::Opus => {
+                encoder::find_by_name("libopus").or_else(|| encoder::find(codec::Id::OPUS))
+            }
+            AudioMode::Copy | AudioMode::Disabled => None,
+        }
+        .ok_or(RecorderError::UnsupportedAudioCodec(options.mode))?;
+        let capabilities = encoder_codec.audio()?;
+
+        let sample_rate = pick_sample_rate(
+            options.sample_rate.unwrap_or(decoder.rate()),
+            capabilities.rates(),
+        );
+        let channels = options
+            .channels
+            .unwrap_or_else(|| decoder.channels().clamp(1, 2));
+        let channel_layout = ChannelLayout::default(i32::from(channels));
+        let sample_format = capabilities
+            .formats()
+            .and_then(|mut formats| formats.next())
+            .ok_or(RecorderError::UnsupportedAudioCodec(options.mode))?;
+        let bitrate = options.bitrate.unwrap_or(match options.mode {
+            AudioMode::Opus => 48_000,
+            _ => 96_000,
+        });
+
+        let mut ost = octx.add_stream(Some(encoder_codec))?;
+        let mut encoder_context =
+            ffmpeg_next::codec::context::Context::new_with_codec(encoder_codec)
+                .encoder()
+                .audio()?;
+
+        encoder_context.set_rate(sample_rate as i32);
+        encoder_context.set_channel_layout(channel_layout);
+        encoder_context.set_format(sample_format);
+        encoder_context.set_bit_rate(bitrate as usize);
+        encoder_context.set_time_base((1, sample_rate as i32));
+        ost.set_time_base((1, sample_rate as i32));
+
+        if global_header {
+            encoder_context.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+
+        let mut encoder_options = Dictionary::new();
+        // FFmpeg's native Opus encoder is still flagged experimental.
+        encoder_options.set("strict", "experimental");
+        let opened_encoder = encoder_context.open_with(encoder_options)?;
+        ost.set_parameters(&opened_encoder);
+
+        let chain = [(
+            "aformat",
+            format!(
+                "sample_fmts={}:sample_rates={}:channel_layouts={}",
+                sample_format.name(),
+                sample_rate,
+                channel_layout_name(channels)
+            ),
+        )];
+        let mut filter = FilterChain::audio(&decoder, ist.time_base(), &chain)?;
+
+        if !encoder_codec
+            .capabilities()
+            .contains(codec::capabilities::Capabilities::VARIABLE_FRAME_SIZE)
+        {
+            filter.set_frame_size(opened_encoder.frame_size())?;
+        }
+        let filter_time_base = filter.output_time_base()?;
+
+        Ok(Self {
+            decoder,
+            encoder: opened_encoder,
+            filter,
+            filter_time_base,
+            encoder_time_base: Rational(1, sample_rate as i32),
+            ost_index,
+            bitrate,
+        })
+    }
+
+    /// Target bitrate of the encoded stream, in bits per second.
+    pub(crate) fn bitrate(&self) -> u32 {
+        self.bitrate
+    }
+
+    /// Codec string advertised for the encoded stream in a master playlist.
+    pub(crate) fn codec_string(&self) -> Option<String> {
+        audio_codec_string(self.encoder.id().name(), 0)
+    }
+
+    pub(crate) fn send_packet_to_decoder(&mut self, packet: &Packet) -> Result<(), RecorderError> {
+        self.decoder.send_packet(packet)?;
+        Ok(())
+    }
+
+    pub(crate) fn send_eof_to_decoder(&mut self) -> Result<(), RecorderError> {
+        self.decoder.send_eof()?;
+        Ok(())
+    }
+
+    pub(crate) fn send_eof_to_encoder(&mut self) -> Result<(), RecorderError> {
+        self.encoder.send_eof()?;
+        Ok(())
+    }
+
+    pub(crate) fn receive_and_process_decoded_frames(
+        &mut self,
+        muxer: &mut OutputMuxer,
+    ) -> Result<(), RecorderError> {
+        let mut decoded = frame::Audio::empty();
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            let timestamp = decoded.timestamp();
+            decoded.set_pts(timestamp);
+            self.filter.push(&decoded)?;
+            self.receive_and_process_filtered_frames(muxer)?;
+        }
+        Ok(())
+    }
+
+    /// Drains samples buffered inside the resampler once the decoder has been flushed.
+    pub(crate) fn flush_filter(&mut self, muxer: &mut OutputMuxer) -> Result<(), RecorderError> {
+        self.filter.flush()?;
+        self.receive_and_process_filtered_frames(muxer)
+    }
+
+    pub(crate) fn receive_and_process_encoded_packets(
+        &mut self,
+        muxer: &mut OutputMuxer,
+    ) -> Result<(), RecorderError> {
+        let mut encoded = Packet::empty();
+        while self.encoder.receive_packet(&mut encoded).is_ok() {
+            muxer.write(&mut encoded, self.ost_index, self.encoder_time_base)?;
+        }
+        Ok(())
+    }
+
+    fn receive_and_process_filtered_frames(
+        &mut self,
+        muxer: &mut OutputMuxer,
+    ) -> Result<(), RecorderError> {
+        let mut filtered = frame::Audio::empty();
+        while self.filter.pull(&mut filtered)? {
+            let pts = filtered.pts();
+            filtered
+                .set_pts(pts.map(|pts| pts.rescale(self.filter_time_base, self.encoder_time_base)));
+            self.encoder.send_frame(&filtered)?;
+            self.receive_and_process_encoded_packets(muxer)?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps `requested` when the encoder accepts it, otherwise falls back to 48 kHz or the first supported rate.
+fn pick_sample_rate(requested: u32, supported: Option<codec::audio::RateIter>) -> u32 {
+    let Some(supported) = supported else {
+        return requested;
+    };
+    let rates: Vec<u32> = supported.map(|rate| rate as u32).collect();
+    if rates.is_empty() || rates.contains(&requested) {
+        requested
+    } else if rates.contains(&48_000) {
+        48_000
+    } else {
+        rates[0]
+    }
+}
+
+/// Channel layout name understood by the `aformat` filter.
+fn channel_layout_name(channels: u16) -> String {
+    match channels {
+        1 => "mono".to_string(),
+        2 => "stereo".to_string(),
+        count => format!("{count}c"),
+    }
+}
@@ -0,0 +1,200 @@
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::vod_playlist::GAP_TOLERANCE;
+use super::{SegmentRecord, format_program_date_time, read_segment_index, segment_index_path};
+
+const SECONDS_PER_HOUR: f64 = 3600.0;
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// A stretch of the report window without recorded segments.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Gap {
+    /// Wall-clock start, in seconds since the Unix epoch.
+    pub start: f64,
+    /// Wall-clock end, in seconds since the Unix epoch.
+    pub end: f64,
+    /// Length in seconds.
+    pub duration: f64,
+    /// Whether the gap lasts at least the report's event threshold, e.g. a camera outage.
+    pub event: bool,
+}
+
+/// Coverage of one UTC calendar day, limited to the part inside the report window.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DayCoverage {
+    /// Date as `YYYY-MM-DD`.
+    pub date: String,
+    /// Seconds of the day covered by segments.
+    pub recorded_seconds: f64,
+    /// Share of the day's part of the window covered by segments, from 0 to 100.
+    pub uptime_percent: f64,
+    /// Hours, 0 to 23, that were recorded only in part.
+    pub partial_hours: Vec<u32>,
+    /// Hours, 0 to 23, without any recording.
+    pub missing_hours: Vec<u32>,
+}
+
+/// Recording coverage of a window, computed from a wall-clock segment index.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CoverageReport {
+    /// Start of the report window, in seconds since the Unix epoch.
+    pub start: f64,
+    /// End of the report window, in seconds since the Unix epoch.
+    pub end: f64,
+    /// Seconds of the window covered by segments.
+    pub recorded_seconds: f64,
+    /// Share of the window covered by segments, from 0 to 100.
+    pub uptime_percent: f64,
+    /// Every stretch without segments, in wall-clock order.
+    pub gaps: Vec<Gap>,
+    /// Per-day breakdown in UTC.
+    pub days: Vec<DayCoverage>,
+}
+
+impl CoverageReport {
+    /// Gaps that last at least the event threshold.
+    pub fn events(&self) -> impl Iterator<Item = &Gap> {
+        self.gaps.iter().filter(|gap| gap.event)
+    }
+}
+
+/// Computes the coverage of `records` over `start..end`, in seconds since the Unix epoch.
+///
+/// A missing boundary defaults to the first segment's start or the last segment's end. Segments
+/// less than half a second apart count as continuous; longer gaps are listed, and those of at
+/// least `gap_threshold` seconds are flagged as events. Returns `None` without records or for an
+/// empty window.
+pub fn coverage_report(
+    records: &[SegmentRecord],
+    start: Option<f64>,
+    end: Option<f64>,
+    gap_threshold: f64,
+) -> Option<CoverageReport> {
+    let intervals = recorded_intervals(records);
+    let start = start.unwrap_or(intervals.first()?.0);
+    let end = end.unwrap_or(intervals.last()?.1);
+    if end <= start {
+        return None;
+    }
+
+    let mut gaps = Vec::new();
+    let mut cursor = start;
+    for &(interval_start, interval_end) in &intervals {
+        if interval_end <= start || interval_start >= end {
+            continue;
+        }
+        if interval_start - cursor > GAP_TOLERANCE {
+            gaps.push(gap(cursor, interval_start, gap_threshold));
+        }
+        cursor = cursor.max(interval_end);
+    }
+    if end - cursor > GAP_TOLERANCE {
+        gaps.push(gap(cursor, end, gap_threshold));
+    }
+
+    let mut days = Vec::new();
+    let mut day_start = (start / SECONDS_PER_DAY).floor() * SECONDS_PER_DAY;
+    while day_start < end {
+        days.push(day_coverage(&intervals, day_start, start, end));
+        day_start += SECONDS_PER_DAY;
+    }
+
+    let recorded_seconds = covered_seconds(&intervals, start, end);
+    Some(CoverageReport {
+        start,
+        end,
+        recorded_seconds,
+        uptime_percent: percent(recorded_seconds, end - start),
+        gaps,
+        days,
+    })
+}
+
+/// Coverage of the recording whose media playlist is at `playlist_path`, from its wall-clock
+/// index. Returns `None` when the index holds no segments in the window.
+pub fn playlist_coverage(
+    playlist_path: &Path,
+    start: Option<f64>,
+    end: Option<f64>,
+    gap_threshold: f64,
+) -> io::Result<Option<CoverageReport>> {
+    let records = read_segment_index(&segment_index_path(playlist_path))?;
+    Ok(coverage_report(&records, start, end, gap_threshold))
+}
+
+/// Segment spans in wall-clock order, with touching or overlapping spans merged.
+fn recorded_intervals(records: &[SegmentRecord]) -> Vec<(f64, f64)> {
+    let mut spans: Vec<(f64, f64)> = records
+        .iter()
+        .map(|record| (record.start, record.end()))
+        .collect();
+    spans.sort_by(|left, right| left.0.total_cmp(&right.0));
+
+    let mut merged: Vec<(f64, f64)> = Vec::with_capacity(spans.len());
+    for (start, end) in spans {
+        match merged.last_mut() {
+            Some(last) if start - last.1 <= GAP_TOLERANCE => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+/// Seconds of `start..end` covered by `intervals`.
+fn covered_seconds(intervals: &[(f64, f64)], start: f64, end: f64) -> f64 {
+    intervals
+        .iter()
+        .map(|&(interval_start, interval_end)| {
+            (interval_end.min(end) - interval_start.max(start)).max(0.0)
+        })
+        .sum()
+}
+
+fn day_coverage(intervals: &[(f64, f64)], day_start: f64, start: f64, end: f64) -> DayCoverage {
+    let span_start = day_start.max(start);
+    let span_end = (day_start + SECONDS_PER_DAY).min(end);
+    let recorded_seconds = covered_seconds(intervals, span_start, span_end);
+
+    let mut partial_hours = Vec::new();
+    let mut missing_hours = Vec::new();
+    for hour in 0..24 {
+        let hour_start = (day_start + f64::from(hour) * SECONDS_PER_HOUR).max(start);
+        let hour_end = (day_start + f64::from(hour + 1) * SECONDS_PER_HOUR).min(end);
+        if hour_end <= hour_start {
+            continue;
+        }
+        let covered = covered_seconds(intervals, hour_start, hour_end);
+        if covered <= GAP_TOLERANCE {
+            missing_hours.push(hour);
+        } else if hour_end - hour_start - covered > GAP_TOLERANCE {
+            partial_hours.push(hour);
+        }
+    }
+
+    DayCoverage {
+        date: format_program_date_time(day_start)[..10].to_string(),
+        recorded_seconds,
+        uptime_percent: percent(recorded_seconds, span_end - span_start),
+        partial_hours,
+        missing_hours,
+    }
+}
+
+fn gap(start: f64, end: f64, gap_threshold: f64) -> Gap {
+    Gap {
+        start,
+        end,
+        duration: end - start,
+        event: end - start >= gap_threshold,
+    }
+}
+
+fn percent(part: f64, whole: f64) -> f64 {
+    if whole <= 0.0 {
+        return 0.0;
+    }
+    (part / whole * 100.0).min(100.0)
+}
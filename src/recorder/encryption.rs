@@ -0,0 +1,168 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use ffmpeg_next::{Error as FfmpegError, ffi};
+use serde::Deserialize;
+
+use super::{HlsOutput, RecorderError};
+
+/// AES-128 segment encryption settings for an HLS output.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct EncryptionOptions {
+    /// Directory holding the generated keys and key info file. Must lie outside the HTTP
+    /// server's `root`.
+    pub key_dir: PathBuf,
+    /// URI prefix written into `EXT-X-KEY`, e.g. `/keys/camera-one`; the key file name is appended.
+    pub key_uri_prefix: String,
+    /// Generate a fresh key every N segments. Keeps one key for the whole recording when unset.
+    #[serde(default)]
+    pub rotate_every_segments: Option<u32>,
+}
+
+/// Name of the key file for rotation period `index`, `<playlist>_<index>.key`.
+pub fn key_file_name(stem: &str, index: u64) -> String {
+    format!("{stem}_{index:05}.key")
+}
+
+/// Contents of an FFmpeg key info file: key URI, key path and an optional hex IV, one per line.
+pub fn key_info_contents(key_uri: &str, key_path: &Path, iv: Option<&str>) -> String {
+    let mut contents = format!("{key_uri}\n{}\n", key_path.display());
+    if let Some(iv) = iv {
+        contents.push_str(iv);
+        contents.push('\n');
+    }
+    contents
+}
+
+/// Rotation period of `segment`, counted from the first segment of the recording.
+pub fn rotation_index(segment: u64, rotate_every_segments: Option<u32>) -> u64 {
+    match rotate_every_segments {
+        Some(segments) if segments > 0 => segment / u64::from(segments),
+        _ => 0,
+    }
+}
+
+/// Writes keys and keeps the key info file pointing at the key of the next segment's rotation
+/// period.
+///
+/// The `hls` muxer re-reads the key info file at every segment start when `periodic_rekey` is
+/// set, within the same write that completes the previous segment. The key of a segment is
+/// therefore prepared as soon as the segment before it starts.
+pub(crate) struct KeyRotator {
+    options: EncryptionOptions,
+    stem: String,
+    key_info_path: PathBuf,
+    /// Number of the first key of this recording, after any keys an earlier one left behind.
+    first_key: u64,
+    current: Option<u64>,
+}
+
+impl KeyRotator {
+    /// Creates the protected key directory and the first key.
+    pub(crate) fn new(
+        hls_output: &HlsOutput,
+        options: &EncryptionOptions,
+    ) -> Result<Self, RecorderError> {
+        fs::create_dir_all(&options.key_dir)?;
+        restrict_permissions(&options.key_dir, 0o700)?;
+
+        let stem = hls_output
+            .playlist_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("stream")
+            .to_string();
+        let key_info_path = options.key_dir.join(format!("{stem}.keyinfo"));
+        // Segments of an interrupted or appended recording still need their keys.
+        let first_key = fs::read_dir(&options.key_dir)?
+            .filter_map(|entry| {
+                let name = entry.ok()?.file_name().into_string().ok()?;
+                let index = name.strip_prefix(&stem)?.strip_prefix('_')?;
+                index.strip_suffix(".key")?.parse::<u64>().ok()
+            })
+            .max()
+            .map_or(0, |last| last + 1);
+
+        let mut rotator = Self {
+            options: options.clone(),
+            stem,
+            key_info_path,
+            first_key,
+            current: None,
+        };
+        rotator.prepare(0)?;
+        Ok(rotator)
+    }
+
+    /// Path handed to the muxer's `hls_key_info_file` option.
+    pub(crate) fn key_info_path(&self) -> &Path {
+        &self.key_info_path
+    }
+
+    /// Whether keys change during the recording.
+    pub(crate) fn rotates(&self) -> bool {
+        self.options
+            .rotate_every_segments
+            .is_some_and(|segments| segments > 0)
+    }
+
+    /// Points the key info file at the key of `segment`, counted from the first segment of this
+    /// recording, generating the key when `segment` starts a new rotation period.
+    pub(crate) fn prepare(&mut self, segment: u64) -> Result<(), RecorderError> {
+        let index = rotation_index(segment, self.options.rotate_every_segments);
+        if self.current == Some(index) {
+            return Ok(());
+        }
+
+        let file_name = key_file_name(&self.stem, self.first_key + index);
+        let key_path = self.options.key_dir.join(&file_name);
+        write_key(&key_path, &random_key()?)?;
+
+        let key_uri = format!(
+            "{}/{file_name}",
+            self.options.key_uri_prefix.trim_end_matches('/')
+        );
+        let mut temporary = self.key_info_path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, key_info_contents(&key_uri, &key_path, None))?;
+        fs::rename(&temporary, &self.key_info_path)?;
+
+        self.current = Some(index);
+        Ok(())
+    }
+}
+
+/// 16 bytes from FFmpeg's cryptographically secure random source.
+fn random_key() -> Result<[u8; 16], RecorderError> {
+    let mut key = [0u8; 16];
+    let result = unsafe { ffi::av_random_bytes(key.as_mut_ptr(), key.len()) };
+    if result < 0 {
+        return Err(FfmpegError::from(result).into());
+    }
+    Ok(key)
+}
+
+/// Creates the key file at `path` readable by its owner only from the start, so the key is never
+/// exposed under looser permissions.
+fn write_key(path: &Path, key: &[u8]) -> Result<(), RecorderError> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(key)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &Path, mode: u32) -> Result<(), RecorderError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &Path, _mode: u32) -> Result<(), RecorderError> {
+    Ok(())
+}
@@ -1,9 +1,11 @@
 use std::error::Error;
 use std::fmt;
+use std::path::PathBuf;
 
 use ffmpeg_next::Error as FfmpegError;
+use ffmpeg_next::format::Pixel;
 
-use super::VideoCodec;
+use super::{AudioMode, VideoCodec};
 
 /// Errors that can surface while preparing or recording an RTSP stream to HLS.
 #[derive(Debug)]
@@ -18,6 +20,20 @@ pub enum RecorderError {
     Io(std::io::Error),
     /// Requested video codec is unavailable or unsupported by the current FFmpeg build.
     UnsupportedVideoCodec(VideoCodec),
+    /// Requested audio encoder is unavailable in the current FFmpeg build.
+    UnsupportedAudioCodec(AudioMode),
+    /// A stream selector did not match any stream of the input.
+    StreamNotFound(String),
+    /// A filter required by the configured processing is missing from the FFmpeg build.
+    MissingFilter(String),
+    /// Decoded frames use a pixel layout that in-place frame processing cannot edit.
+    UnsupportedPixelFormat(Pixel),
+    /// Video copy mode was requested together with processing that needs decoded frames.
+    TranscodeRequired(&'static str),
+    /// The output configuration combines options that cannot work together.
+    InvalidOutput(String),
+    /// The recording with this playlist holds no footage in the requested window.
+    NoFootage(PathBuf),
 }
 
 impl fmt::Display for RecorderError {
@@ -34,6 +50,32 @@ impl fmt::Display for RecorderError {
             RecorderError::UnsupportedVideoCodec(codec) => {
                 write!(f, "unsupported video codec requested: {:?}", codec)
             }
+            RecorderError::UnsupportedAudioCodec(mode) => {
+                write!(f, "unsupported audio codec requested: {:?}", mode)
+            }
+            RecorderError::StreamNotFound(description) => {
+                write!(f, "input has no {description}")
+            }
+            RecorderError::MissingFilter(name) => {
+                write!(f, "FFmpeg build does not provide the {name} filter")
+            }
+            RecorderError::UnsupportedPixelFormat(pixel) => {
+                write!(
+                    f,
+                    "unsupported pixel format for frame processing: {:?}",
+                    pixel
+                )
+            }
+            RecorderError::TranscodeRequired(feature) => {
+                write!(
+                    f,
+                    "{feature} requires transcoding; video copy mode is not allowed"
+                )
+            }
+            RecorderError::InvalidOutput(reason) => write!(f, "invalid output: {reason}"),
+            RecorderError::NoFootage(playlist) => {
+                write!(f, "no footage of {} in the window", playlist.display())
+            }
         }
     }
 }
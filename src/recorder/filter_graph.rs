@@ -0,0 +1,114 @@
+use ffmpeg_next::format::Pixel;
+use ffmpeg_next::{ChannelLayout, Error as FfmpegError, Frame, Rational, decoder, ffi, filter};
+
+use super::RecorderError;
+
+/// Linear chain of FFmpeg filters fed by a buffer source and drained through a buffer sink.
+pub(crate) struct FilterChain {
+    graph: filter::Graph,
+}
+
+impl FilterChain {
+    /// Builds a video chain for `width` x `height` frames in `format` with timestamps in `time_base`.
+    ///
+    /// Each entry of `chain` is a filter name and its option string; filters are linked in order.
+    pub(crate) fn video(
+        (width, height): (u32, u32),
+        format: Pixel,
+        aspect_ratio: Rational,
+        time_base: Rational,
+        chain: &[(&str, String)],
+    ) -> Result<Self, RecorderError> {
+        let buffer_args = format!(
+            "video_size={width}x{height}:pix_fmt={}:time_base={}:pixel_aspect={}/{}",
+            ffi::AVPixelFormat::from(format) as i32,
+            time_base,
+            aspect_ratio.numerator().max(0),
+            aspect_ratio.denominator().max(1)
+        );
+
+        Self::build(("buffer", buffer_args), chain, "buffersink")
+    }
+
+    /// Builds an audio chain for frames produced by `decoder` with timestamps in `time_base`.
+    pub(crate) fn audio(
+        decoder: &decoder::Audio,
+        time_base: Rational,
+        chain: &[(&str, String)],
+    ) -> Result<Self, RecorderError> {
+        // Some decoders (G.711 among them) leave the layout unspecified; assume the default order.
+        let layout = match decoder.channel_layout() {
+            layout if layout.bits() != 0 => layout,
+            _ => ChannelLayout::default(i32::from(decoder.channels())),
+        };
+        let buffer_args = format!(
+            "time_base={}:sample_rate={}:sample_fmt={}:channel_layout=0x{:x}",
+            time_base,
+            decoder.rate(),
+            decoder.format().name(),
+            layout.bits()
+        );
+
+        Self::build(("abuffer", buffer_args), chain, "abuffersink")
+    }
+
+    fn build(
+        source: (&str, String),
+        chain: &[(&str, String)],
+        sink: &str,
+    ) -> Result<Self, RecorderError> {
+        let mut graph = filter::Graph::new();
+        let mut previous = graph.add(&find_filter(source.0)?, "in", &source.1)?;
+
+        for (index, (name, args)) in chain.iter().enumerate() {
+            let mut next = graph.add(&find_filter(name)?, &format!("{name}_{index}"), args)?;
+            previous.link(0, &mut next, 0);
+            previous = next;
+        }
+
+        let mut sink = graph.add(&find_filter(sink)?, "out", "")?;
+        previous.link(0, &mut sink, 0);
+
+        graph.validate()?;
+
+        Ok(Self { graph })
+    }
+
+    /// Queues a decoded frame for filtering.
+    pub(crate) fn push(&mut self, frame: &Frame) -> Result<(), RecorderError> {
+        self.endpoint("in")?.source().add(frame)?;
+        Ok(())
+    }
+
+    /// Signals end of stream so buffered frames can be drained.
+    pub(crate) fn flush(&mut self) -> Result<(), RecorderError> {
+        self.endpoint("in")?.source().flush()?;
+        Ok(())
+    }
+
+    /// Retrieves the next filtered frame, returning `false` once no frame is ready.
+    pub(crate) fn pull(&mut self, frame: &mut Frame) -> Result<bool, RecorderError> {
+        Ok(self.endpoint("out")?.sink().frame(frame).is_ok())
+    }
+
+    /// Forces the sink to emit frames of exactly `samples` samples, as fixed-frame audio encoders require.
+    pub(crate) fn set_frame_size(&mut self, samples: u32) -> Result<(), RecorderError> {
+        self.endpoint("out")?.sink().set_frame_size(samples);
+        Ok(())
+    }
+
+    /// Time base of the frames leaving the chain.
+    pub(crate) fn output_time_base(&mut self) -> Result<Rational, RecorderError> {
+        Ok(self.endpoint("out")?.sink().time_base())
+    }
+
+    fn endpoint(&mut self, name: &str) -> Result<filter::Context, RecorderError> {
+        self.graph
+            .get(name)
+            .ok_or(RecorderError::Ffmpeg(FfmpegError::FilterNotFound))
+    }
+}
+
+fn find_filter(name: &str) -> Result<filter::Filter, RecorderError> {
+    filter::find(name).ok_or_else(|| RecorderError::MissingFilter(name.to_string()))
+}
@@ -0,0 +1,82 @@
+use ffmpeg_next::Rational;
+
+/// How decoded frames are thinned out or padded before encoding.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameRateMode {
+    /// Drop frames so the output never exceeds the given rate; surviving frames keep their timestamps.
+    Max(f64),
+    /// Produce a constant rate, dropping or duplicating frames onto a fixed timestamp grid.
+    Target(f64),
+}
+
+impl FrameRateMode {
+    /// Frames per second produced (or capped) by this mode.
+    pub fn fps(self) -> f64 {
+        match self {
+            FrameRateMode::Max(fps) | FrameRateMode::Target(fps) => fps,
+        }
+    }
+}
+
+/// Maps decoded frame timestamps onto a reduced or constant output frame rate.
+///
+/// Timestamps are expressed in the stream time base and stay in it, so the encoder and
+/// muxer keep their configuration while segment durations follow the rewritten PTS.
+pub struct FrameRateConverter {
+    mode: FrameRateMode,
+    time_base: Rational,
+    origin: Option<i64>,
+    last_slot: Option<i64>,
+}
+
+impl FrameRateConverter {
+    pub fn new(mode: FrameRateMode, time_base: Rational) -> Self {
+        Self {
+            mode,
+            time_base,
+            origin: None,
+            last_slot: None,
+        }
+    }
+
+    /// Returns the timestamps at which a frame decoded at `pts` should be encoded.
+    ///
+    /// An empty result means the frame is dropped; more than one entry means it is duplicated.
+    pub fn timestamps(&mut self, pts: i64) -> Vec<i64> {
+        let fps = self.mode.fps();
+        if fps <= 0.0 {
+            return vec![pts];
+        }
+
+        let origin = *self.origin.get_or_insert(pts);
+        let seconds = (pts - origin) as f64 * f64::from(self.time_base);
+        let slot = match self.mode {
+            // A frame only opens a new slot once its interval has fully elapsed.
+            FrameRateMode::Max(_) => (seconds * fps + 1e-6).floor() as i64,
+            // Constant-rate output snaps each frame to the nearest grid position.
+            FrameRateMode::Target(_) => (seconds * fps).round() as i64,
+        };
+
+        let first_slot = match self.last_slot {
+            Some(last) if slot <= last => return Vec::new(),
+            // Pad short hiccups, but never fabricate footage across a stall longer than a second.
+            Some(last) if (slot - last) as f64 > fps.ceil() => slot,
+            Some(last) => last + 1,
+            None => slot,
+        };
+        self.last_slot = Some(slot);
+
+        match self.mode {
+            FrameRateMode::Max(_) => vec![pts],
+            FrameRateMode::Target(_) => (first_slot..=slot)
+                .map(|slot| origin + self.slot_offset(slot, fps))
+                .collect(),
+        }
+    }
+
+    /// Offset of the given output slot from the first frame, in stream time base units.
+    fn slot_offset(&self, slot: i64, fps: f64) -> i64 {
+        let seconds = slot as f64 / fps;
+        (seconds / f64::from(self.time_base)).round() as i64
+    }
+}
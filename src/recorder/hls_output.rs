@@ -2,7 +2,11 @@ use std::path::PathBuf;
 
 use serde::Deserialize;
 
-/// Supported video codecs for transcoding HLS output.
+use super::{
+    EncryptionOptions, LowLatencyOptions, PushTarget, SESSION_PLACEHOLDER, ThumbnailOptions,
+};
+
+/// Supported video codecs for HLS output, or stream copy of the source video.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum VideoCodec {
@@ -10,6 +14,8 @@ pub enum VideoCodec {
     H264,
     /// Encode video streams using the H.265 (HEVC) codec.
     H265,
+    /// Pass the camera's video stream through untouched, without decoding or re-encoding.
+    Copy,
 }
 
 impl VideoCodec {
@@ -19,10 +25,76 @@ impl VideoCodec {
     }
 }
 
+/// How audio tracks are carried into the HLS output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioMode {
+    /// Remux the camera's audio untouched.
+    #[default]
+    Copy,
+    /// Transcode audio to AAC-LC, the codec every HLS player supports.
+    Aac,
+    /// Transcode audio to Opus.
+    Opus,
+    /// Drop audio tracks entirely.
+    #[serde(rename = "none")]
+    Disabled,
+}
+
+/// Audio handling for an output; sample rate, channels and bitrate only apply when transcoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub struct AudioOptions {
+    /// Copy, transcode, or drop the audio tracks.
+    #[serde(default)]
+    pub mode: AudioMode,
+    /// Output sample rate in Hz. Defaults to the source rate when the encoder supports it.
+    #[serde(default)]
+    pub sample_rate: Option<u32>,
+    /// Output channel count. Defaults to the source channel count, capped at stereo.
+    #[serde(default)]
+    pub channels: Option<u16>,
+    /// Target bitrate in bits per second.
+    #[serde(default)]
+    pub bitrate: Option<u32>,
+}
+
+/// Container used for HLS media segments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SegmentFormat {
+    /// MPEG transport stream (`.ts`) segments.
+    Mpegts,
+    /// Fragmented MP4 (`.m4s`) segments with a shared init segment.
+    Fmp4,
+}
+
+impl SegmentFormat {
+    /// File extensions accepted for segments of this format; the first one is the default.
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            SegmentFormat::Mpegts => &["ts"],
+            SegmentFormat::Fmp4 => &["m4s", "mp4"],
+        }
+    }
+}
+
+/// Manifest format written for an output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Packaging {
+    /// HLS playlist with MPEG-TS or fMP4 segments, written by FFmpeg's `hls` muxer.
+    #[default]
+    Hls,
+    /// MPEG-DASH manifest (`.mpd`) with fMP4 segments, written by FFmpeg's `dash` muxer.
+    Dash,
+    /// One set of fMP4 segments described by both a DASH manifest and HLS playlists.
+    Cmaf,
+}
+
 /// Describes where and how the HLS playlist and transport stream segments should be written.
 #[derive(Debug, Clone)]
 pub struct HlsOutput {
-    /// Destination path for the `.m3u8` playlist to be generated by FFmpeg.
+    /// Destination path for the `.m3u8` playlist, or the `.mpd` manifest for DASH and CMAF.
     pub playlist_path: PathBuf,
     /// Optional segment length, in seconds. Controls the `hls_time` muxer option.
     pub segment_duration: Option<u32>,
@@ -32,4 +104,151 @@ pub struct HlsOutput {
     pub segment_filename: Option<String>,
     /// Target codec for the encoded video elementary stream inside the HLS segments.
     pub video_codec: VideoCodec,
+    /// Handling of audio tracks: copy, transcode, or drop.
+    pub audio: AudioOptions,
+    /// Optional output width in pixels. Derived from `height` and the source aspect when unset.
+    pub width: Option<u32>,
+    /// Optional output height in pixels. Derived from `width` and the source aspect when unset.
+    pub height: Option<u32>,
+    /// Optional target video bitrate in bits per second; constant-quality encoding is used when unset.
+    pub video_bitrate: Option<u32>,
+    /// Optional Low-Latency HLS mode publishing fMP4 partial segments.
+    pub low_latency: Option<LowLatencyOptions>,
+    /// Manifest format: HLS, DASH, or both over shared CMAF segments.
+    pub packaging: Packaging,
+    /// Requested segment container. Defaults to fMP4 for H.265 and MPEG-TS otherwise.
+    pub segment_format: Option<SegmentFormat>,
+    /// Optional AES-128 encryption of the segments.
+    pub encryption: Option<EncryptionOptions>,
+    /// Whether HLS playlists date each segment with `EXT-X-PROGRAM-DATE-TIME`.
+    pub program_date_time: bool,
+    /// Live restreaming targets fed with the packets written to this output.
+    pub push: Vec<PushTarget>,
+    /// HLS master playlist listing this output as a variant stream, for rendition ladders.
+    pub master_playlist: Option<PathBuf>,
+    /// Whether a finished playlist is extended instead of overwritten, and default segment names
+    /// carry the session start time.
+    pub append: bool,
+    /// Optional preview thumbnails in sprite sheets, indexed by a WebVTT track.
+    pub thumbnails: Option<ThumbnailOptions>,
+}
+
+/// One entry of an adaptive bitrate ladder, encoded from the shared decoded input.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Rendition {
+    /// Short identifier appended to the rendition's playlist and segment names, e.g. `720p`.
+    pub name: String,
+    /// Output width in pixels. Keeps the source aspect ratio when only `height` is given.
+    #[serde(default)]
+    pub width: Option<u32>,
+    /// Output height in pixels. Keeps the source aspect ratio when only `width` is given.
+    #[serde(default)]
+    pub height: Option<u32>,
+    /// Peak video bitrate in bits per second; advertised as part of the master playlist BANDWIDTH.
+    pub bitrate: u32,
+    /// Codec of this rendition. `copy` passes the source through and ignores the size.
+    #[serde(default = "VideoCodec::default")]
+    pub video_codec: VideoCodec,
+}
+
+impl HlsOutput {
+    /// Container the segments are actually written in.
+    ///
+    /// Low-Latency HLS, DASH and CMAF only work with fMP4; otherwise an explicit
+    /// `segment_format` wins over the codec default.
+    pub fn effective_segment_format(&self) -> SegmentFormat {
+        if self.low_latency.is_some() || self.packaging != Packaging::Hls {
+            return SegmentFormat::Fmp4;
+        }
+        self.segment_format.unwrap_or(match self.video_codec {
+            VideoCodec::H265 => SegmentFormat::Fmp4,
+            VideoCodec::H264 | VideoCodec::Copy => SegmentFormat::Mpegts,
+        })
+    }
+
+    /// Whether segments are fragmented MP4 rather than MPEG-TS.
+    pub fn uses_fmp4(&self) -> bool {
+        self.effective_segment_format() == SegmentFormat::Fmp4
+    }
+
+    /// Derives the output for one rendition, writing `<playlist>_<name>.<ext>` next to this playlist.
+    ///
+    /// Rendition segments always use the default naming so renditions never overwrite each other.
+    pub fn rendition(&self, rendition: &Rendition) -> HlsOutput {
+        let stem = self
+            .playlist_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("stream");
+        let extension = self
+            .playlist_path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or("m3u8");
+        let playlist_path = self
+            .playlist_path
+            .with_file_name(format!("{stem}_{}.{extension}", rendition.name));
+
+        HlsOutput {
+            playlist_path,
+            segment_filename: None,
+            video_codec: rendition.video_codec,
+            width: rendition.width,
+            height: rendition.height,
+            video_bitrate: Some(rendition.bitrate),
+            ..self.clone()
+        }
+    }
+
+    /// Resolves the encoded picture size for a source of `source_width` x `source_height`.
+    ///
+    /// A missing dimension follows the source aspect ratio; results are rounded to even values
+    /// because 4:2:0 encoders reject odd sizes.
+    pub fn output_size(&self, source_width: u32, source_height: u32) -> (u32, u32) {
+        let scale = |value: u32, numerator: u32, denominator: u32| {
+            (u64::from(value) * u64::from(numerator) / u64::from(denominator.max(1))) as u32
+        };
+        let (width, height) = match (self.width, self.height) {
+            (Some(width), Some(height)) => (width, height),
+            (Some(width), None) => (width, scale(source_height, width, source_width)),
+            (None, Some(height)) => (scale(source_width, height, source_height), height),
+            (None, None) => return (source_width, source_height),
+        };
+        ((width & !1).max(2), (height & !1).max(2))
+    }
+}
+
+/// Init and media segment name templates for the `dash` muxer, relative to the manifest.
+///
+/// Names are prefixed with the manifest stem so several manifests can share a directory;
+/// `segment_filename` does not apply to DASH.
+pub fn derive_dash_segment_names(hls_output: &HlsOutput) -> (String, String) {
+    let stem = hls_output
+        .playlist_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("stream");
+
+    (
+        format!("{stem}_init_$RepresentationID$.m4s"),
+        format!("{stem}_$RepresentationID$_$Number%05d$.m4s"),
+    )
+}
+
+/// File name of the fMP4 init segment, `<playlist>_init.mp4`, written next to the segments.
+///
+/// A session that `continues` an earlier playlist writes `<playlist>_{session}_init.mp4` instead,
+/// with [`SESSION_PLACEHOLDER`] left unexpanded, so the earlier segments keep the init segment
+/// they were encoded against.
+pub fn derive_init_segment_name(hls_output: &HlsOutput, continues: bool) -> String {
+    let stem = hls_output
+        .playlist_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("segment");
+    if continues {
+        format!("{stem}_{SESSION_PLACEHOLDER}_init.mp4")
+    } else {
+        format!("{stem}_init.mp4")
+    }
 }
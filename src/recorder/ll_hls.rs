@@ -0,0 +1,561 @@
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::time::Duration;
+
+use ffmpeg_next::{Dictionary, Error as FfmpegError, ffi, format};
+use serde::Deserialize;
+
+use super::master_playlist::variant_uri;
+use super::program_date_time::{format_program_date_time, now_epoch_seconds};
+use super::segment_index::{SegmentIndexWriter, SegmentRecord};
+use super::{
+    HlsOutput, RecorderError, SESSION_PLACEHOLDER, derive_init_segment_name,
+    derive_segment_template, session_name,
+};
+
+/// Low-Latency HLS settings for an output.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct LowLatencyOptions {
+    /// Target duration of each partial segment, in seconds.
+    #[serde(default = "default_part_duration")]
+    pub part_duration_seconds: f64,
+}
+
+impl Default for LowLatencyOptions {
+    fn default() -> Self {
+        Self {
+            part_duration_seconds: default_part_duration(),
+        }
+    }
+}
+
+fn default_part_duration() -> f64 {
+    0.5
+}
+
+/// Byte range of a segment file holding one fMP4 fragment.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PartialSegment {
+    /// Media duration of the part, in seconds.
+    pub duration: f64,
+    /// Offset of the fragment within its segment file.
+    pub offset: u64,
+    /// Length of the fragment in bytes.
+    pub length: u64,
+    /// Whether the part starts with a keyframe.
+    pub independent: bool,
+}
+
+/// A media segment together with the parts it was published as.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LlHlsSegment {
+    /// URI of the segment file, relative to the playlist.
+    pub uri: String,
+    /// Total duration in seconds; only meaningful once the segment is complete.
+    pub duration: f64,
+    /// Parts published so far, in order.
+    pub parts: Vec<PartialSegment>,
+    /// Wall-clock time of the first sample, in seconds since the Unix epoch.
+    pub program_date_time: Option<f64>,
+    /// Whether the segment follows a timestamp discontinuity.
+    pub discontinuity: bool,
+}
+
+/// Sliding-window LL-HLS media playlist with partial segments and a preload hint.
+#[derive(Debug, Clone)]
+pub struct LlHlsPlaylist {
+    init_uri: String,
+    target_duration: f64,
+    part_target: f64,
+    window: usize,
+    media_sequence: u64,
+    discontinuity_sequence: u64,
+    segments: VecDeque<LlHlsSegment>,
+    current: Option<LlHlsSegment>,
+    ended: bool,
+}
+
+impl LlHlsPlaylist {
+    /// Creates an empty playlist keeping at most `window` complete segments.
+    pub fn new(init_uri: &str, target_duration: f64, part_target: f64, window: usize) -> Self {
+        Self {
+            init_uri: init_uri.to_string(),
+            target_duration,
+            part_target,
+            window: window.max(1),
+            media_sequence: 0,
+            discontinuity_sequence: 0,
+            segments: VecDeque::new(),
+            current: None,
+            ended: false,
+        }
+    }
+
+    /// Opens a new in-progress segment stored at `uri`.
+    pub fn start_segment(&mut self, uri: &str) {
+        self.current = Some(LlHlsSegment {
+            uri: uri.to_string(),
+            duration: 0.0,
+            parts: Vec::new(),
+            program_date_time: None,
+            discontinuity: false,
+        });
+    }
+
+    /// Marks the in-progress segment as following a timestamp discontinuity.
+    pub fn set_discontinuity(&mut self) {
+        if let Some(current) = self.current.as_mut() {
+            current.discontinuity = true;
+        }
+    }
+
+    /// Dates the in-progress segment with the wall-clock time of its first sample.
+    pub fn set_program_date_time(&mut self, epoch_seconds: f64) {
+        if let Some(current) = self.current.as_mut() {
+            current.program_date_time = Some(epoch_seconds);
+        }
+    }
+
+    /// Publishes a part of the in-progress segment.
+    pub fn add_part(&mut self, part: PartialSegment) {
+        if let Some(current) = self.current.as_mut() {
+            current.duration += part.duration;
+            current.parts.push(part);
+        }
+    }
+
+    /// Completes the in-progress segment and slides the window forward.
+    pub fn finish_segment(&mut self) {
+        let Some(segment) = self.current.take() else {
+            return;
+        };
+        if segment.parts.is_empty() {
+            return;
+        }
+        self.segments.push_back(segment);
+        while self.segments.len() > self.window {
+            if let Some(dropped) = self.segments.pop_front()
+                && dropped.discontinuity
+            {
+                self.discontinuity_sequence += 1;
+            }
+            self.media_sequence += 1;
+        }
+    }
+
+    /// Completes the last segment and marks the playlist as ended.
+    pub fn end(&mut self) {
+        self.finish_segment();
+        self.ended = true;
+    }
+
+    /// Renders the playlist, listing parts for segments within three target durations of the live edge.
+    pub fn render(&self) -> String {
+        let longest = self
+            .segments
+            .iter()
+            .map(|segment| segment.duration)
+            .fold(self.target_duration, f64::max);
+        let target_duration = longest.round().max(1.0) as u64;
+
+        let mut playlist = String::from("#EXTM3U\n#EXT-X-VERSION:9\n");
+        let _ = writeln!(playlist, "#EXT-X-TARGETDURATION:{target_duration}");
+        let _ = writeln!(
+            playlist,
+            "#EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK={:.3}",
+            self.part_target * 3.0
+        );
+        let _ = writeln!(
+            playlist,
+            "#EXT-X-PART-INF:PART-TARGET={:.3}",
+            self.part_target
+        );
+        let _ = writeln!(playlist, "#EXT-X-MEDIA-SEQUENCE:{}", self.media_sequence);
+        if self.discontinuity_sequence > 0 {
+            let _ = writeln!(
+                playlist,
+                "#EXT-X-DISCONTINUITY-SEQUENCE:{}",
+                self.discontinuity_sequence
+            );
+        }
+        let _ = writeln!(playlist, "#EXT-X-MAP:URI=\"{}\"", self.init_uri);
+
+        let mut tail = self
+            .current
+            .as_ref()
+            .map_or(0.0, |segment| segment.duration);
+        let mut with_parts = 0;
+        for segment in self.segments.iter().rev() {
+            if tail >= target_duration as f64 * 3.0 {
+                break;
+            }
+            tail += segment.duration;
+            with_parts += 1;
+        }
+        let first_with_parts = self.segments.len() - with_parts;
+
+        for (index, segment) in self.segments.iter().enumerate() {
+            write_segment_tags(&mut playlist, segment);
+            if index >= first_with_parts {
+                write_parts(&mut playlist, segment);
+            }
+            let _ = write!(
+                playlist,
+                "#EXTINF:{:.3},\n{}\n",
+                segment.duration, segment.uri
+            );
+        }
+
+        if self.ended {
+            playlist.push_str("#EXT-X-ENDLIST\n");
+        } else if let Some(current) = &self.current {
+            write_segment_tags(&mut playlist, current);
+            write_parts(&mut playlist, current);
+            let next_offset = current
+                .parts
+                .last()
+                .map_or(0, |part| part.offset + part.length);
+            let _ = writeln!(
+                playlist,
+                "#EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"{}\",BYTERANGE-START={next_offset}",
+                current.uri
+            );
+        }
+
+        playlist
+    }
+}
+
+fn write_segment_tags(playlist: &mut String, segment: &LlHlsSegment) {
+    if segment.discontinuity {
+        playlist.push_str("#EXT-X-DISCONTINUITY\n");
+    }
+    if let Some(epoch) = segment.program_date_time {
+        let _ = writeln!(
+            playlist,
+            "#EXT-X-PROGRAM-DATE-TIME:{}",
+            format_program_date_time(epoch)
+        );
+    }
+}
+
+fn write_parts(playlist: &mut String, segment: &LlHlsSegment) {
+    for part in &segment.parts {
+        let _ = write!(
+            playlist,
+            "#EXT-X-PART:DURATION={:.3},URI=\"{}\",BYTERANGE=\"{}@{}\"",
+            part.duration, segment.uri, part.length, part.offset
+        );
+        if part.independent {
+            playlist.push_str(",INDEPENDENT=YES");
+        }
+        playlist.push('\n');
+    }
+}
+
+/// URI of the segment an LL-HLS playlist announces in its `EXT-X-PRELOAD-HINT`, i.e. the one
+/// still being written.
+pub fn preload_hint_uri(playlist: &str) -> Option<&str> {
+    playlist.lines().find_map(|line| {
+        let attributes = line.strip_prefix("#EXT-X-PRELOAD-HINT:")?;
+        let start = attributes.find("URI=\"")? + "URI=\"".len();
+        let end = start + attributes[start..].find('"')?;
+        Some(&attributes[start..end])
+    })
+}
+
+/// Checks whether an LL-HLS playlist already contains part `part` of media sequence `msn`.
+///
+/// Without a part number the whole segment must be complete. Ended playlists satisfy every request.
+pub fn playlist_has_part(playlist: &str, msn: u64, part: Option<u64>) -> bool {
+    let mut media_sequence = 0;
+    let mut complete = 0;
+    let mut trailing_parts = 0;
+    for line in playlist.lines() {
+        if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            media_sequence = value.trim().parse().unwrap_or(0);
+        } else if line.starts_with("#EXTINF:") {
+            complete += 1;
+            trailing_parts = 0;
+        } else if line.starts_with("#EXT-X-PART:") {
+            trailing_parts += 1;
+        } else if line.starts_with("#EXT-X-ENDLIST") {
+            return true;
+        }
+    }
+
+    let in_progress = media_sequence + complete;
+    match part {
+        _ if msn < in_progress => true,
+        Some(part) => msn == in_progress && part < trailing_parts,
+        None => false,
+    }
+}
+
+/// How long a blocking playlist request may wait: three target durations, per the LL-HLS spec.
+pub fn blocking_reload_timeout(playlist: &str) -> Duration {
+    let target = playlist
+        .lines()
+        .find_map(|line| line.strip_prefix("#EXT-X-TARGETDURATION:"))
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .unwrap_or(6);
+    Duration::from_secs(target * 3)
+}
+
+/// Expands the `%d` / `%0Nd` counter of an FFmpeg-style segment template.
+pub fn segment_file_name(template: &str, index: u64) -> String {
+    let Some(start) = template.find('%') else {
+        return template.to_string();
+    };
+    let rest = &template[start + 1..];
+    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    if rest.as_bytes().get(digits) != Some(&b'd') {
+        return template.to_string();
+    }
+    let width = rest[..digits].trim_start_matches('0').parse().unwrap_or(0);
+    format!(
+        "{}{index:0width$}{}",
+        &template[..start],
+        &rest[digits + 1..]
+    )
+}
+
+/// Writes fMP4 fragments as LL-HLS parts, switching segment files at keyframes.
+///
+/// FFmpeg's `hls` muxer cannot emit partial segments, so the output is driven through the `mp4`
+/// muxer in custom-fragment mode: every part is one flushed fragment, addressed by byte range.
+pub(crate) struct LowLatencyPackager {
+    playlist: LlHlsPlaylist,
+    playlist_path: PathBuf,
+    segment_template: String,
+    segment_index: u64,
+    target_duration: f64,
+    part_duration: f64,
+    segment_start: Option<f64>,
+    part_start: Option<f64>,
+    part_offset: u64,
+    part_independent: bool,
+    last_seconds: f64,
+    /// Whether segments carry `EXT-X-PROGRAM-DATE-TIME`.
+    program_date_time: bool,
+    index: SegmentIndexWriter,
+    segment_uri: String,
+    segment_epoch: f64,
+    segment_discontinuity: bool,
+    /// Set by a timestamp discontinuity; tags the next segment.
+    pending_discontinuity: bool,
+}
+
+impl LowLatencyPackager {
+    /// Opens the fragmented MP4 muxer; the header lands in the init segment next to the playlist.
+    pub(crate) fn open(
+        hls_output: &HlsOutput,
+        options: &LowLatencyOptions,
+    ) -> Result<(format::context::Output, Self), RecorderError> {
+        let playlist_path = hls_output.playlist_path.clone();
+        let init_path = playlist_path.with_file_name(derive_init_segment_name(hls_output, false));
+
+        let mut muxer_options = Dictionary::new();
+        muxer_options.set(
+            "movflags",
+            "frag_custom+empty_moov+default_base_moof+skip_trailer",
+        );
+        let octx = format::output_as_with(&init_path, "mp4", muxer_options)?;
+
+        let target_duration = f64::from(hls_output.segment_duration.unwrap_or(2));
+        let playlist = LlHlsPlaylist::new(
+            &variant_uri(&playlist_path, &init_path),
+            target_duration,
+            options.part_duration_seconds,
+            hls_output.playlist_size.unwrap_or(5) as usize,
+        );
+
+        let packager = Self {
+            playlist,
+            playlist_path,
+            segment_template: derive_segment_template(hls_output)
+                .replace(SESSION_PLACEHOLDER, &session_name(now_epoch_seconds())),
+            segment_index: 0,
+            target_duration,
+            part_duration: options.part_duration_seconds,
+            segment_start: None,
+            part_start: None,
+            part_offset: 0,
+            part_independent: true,
+            last_seconds: 0.0,
+            program_date_time: hls_output.program_date_time,
+            index: SegmentIndexWriter::create(&hls_output.playlist_path)?,
+            segment_uri: String::new(),
+            segment_epoch: 0.0,
+            segment_discontinuity: false,
+            pending_discontinuity: false,
+        };
+        Ok((octx, packager))
+    }
+
+    /// Moves output from the init segment to the first media segment once the header is written.
+    pub(crate) fn start(
+        &mut self,
+        octx: &mut format::context::Output,
+    ) -> Result<(), RecorderError> {
+        self.open_segment(octx)?;
+        self.write_playlist()
+    }
+
+    /// Cuts parts and segments ahead of a packet at `seconds` on the output timeline.
+    ///
+    /// Only video packets drive the cuts so every segment opens on a keyframe. `epoch_seconds`,
+    /// the packet's wall-clock time, dates and indexes the segments it opens.
+    pub(crate) fn before_packet(
+        &mut self,
+        octx: &mut format::context::Output,
+        seconds: f64,
+        epoch_seconds: f64,
+        is_video: bool,
+        is_key: bool,
+    ) -> Result<(), RecorderError> {
+        if !is_video {
+            return Ok(());
+        }
+        self.last_seconds = self.last_seconds.max(seconds);
+
+        let (Some(segment_start), Some(part_start)) = (self.segment_start, self.part_start) else {
+            self.segment_start = Some(seconds);
+            self.part_start = Some(seconds);
+            self.part_independent = is_key;
+            self.date_segment(epoch_seconds);
+            return Ok(());
+        };
+
+        if is_key && seconds - segment_start >= self.target_duration {
+            self.cut_part(octx, seconds)?;
+            self.playlist.finish_segment();
+            self.index_segment(seconds - segment_start)?;
+            self.segment_index += 1;
+            self.open_segment(octx)?;
+            self.segment_start = Some(seconds);
+            self.date_segment(epoch_seconds);
+            self.write_playlist()?;
+        } else if seconds - part_start >= self.part_duration {
+            self.cut_part(octx, seconds)?;
+            self.write_playlist()?;
+        } else {
+            return Ok(());
+        }
+
+        self.part_start = Some(seconds);
+        self.part_independent = is_key;
+        Ok(())
+    }
+
+    /// Publishes the last part and ends the playlist.
+    pub(crate) fn finish(
+        &mut self,
+        octx: &mut format::context::Output,
+    ) -> Result<(), RecorderError> {
+        self.cut_part(octx, self.last_seconds)?;
+        self.playlist.end();
+        if let Some(segment_start) = self.segment_start {
+            self.index_segment(self.last_seconds - segment_start)?;
+        }
+        self.write_playlist()
+    }
+
+    /// Tags the segment opened after the current one as following a timestamp discontinuity.
+    pub(crate) fn mark_discontinuity(&mut self) {
+        self.pending_discontinuity = true;
+    }
+
+    fn date_segment(&mut self, epoch_seconds: f64) {
+        self.segment_epoch = epoch_seconds;
+        self.segment_discontinuity = std::mem::take(&mut self.pending_discontinuity);
+        if self.segment_discontinuity {
+            self.playlist.set_discontinuity();
+        }
+        if self.program_date_time {
+            self.playlist.set_program_date_time(epoch_seconds);
+        }
+    }
+
+    fn index_segment(&mut self, duration: f64) -> Result<(), RecorderError> {
+        self.index.append(&SegmentRecord {
+            uri: self.segment_uri.clone(),
+            sequence: self.segment_index,
+            start: self.segment_epoch,
+            duration,
+            discontinuity: self.segment_discontinuity,
+        })?;
+        Ok(())
+    }
+
+    fn cut_part(
+        &mut self,
+        octx: &mut format::context::Output,
+        seconds: f64,
+    ) -> Result<(), RecorderError> {
+        let offset = unsafe {
+            let context = octx.as_mut_ptr();
+            // A null packet asks the mp4 muxer to flush the pending fragment.
+            let result = ffi::av_write_frame(context, ptr::null_mut());
+            if result < 0 {
+                return Err(FfmpegError::from(result).into());
+            }
+            ffi::avio_flush((*context).pb);
+            ffi::avio_tell((*context).pb) as u64
+        };
+
+        let length = offset.saturating_sub(self.part_offset);
+        if length > 0 {
+            self.playlist.add_part(PartialSegment {
+                duration: (seconds - self.part_start.unwrap_or(seconds)).max(0.0),
+                offset: self.part_offset,
+                length,
+                independent: self.part_independent,
+            });
+        }
+        self.part_offset = offset;
+        Ok(())
+    }
+
+    /// Points the muxer's I/O at a fresh segment file.
+    fn open_segment(&mut self, octx: &mut format::context::Output) -> Result<(), RecorderError> {
+        let path = PathBuf::from(segment_file_name(
+            &self.segment_template,
+            self.segment_index,
+        ));
+        let c_path = CString::new(path.to_string_lossy().as_bytes())
+            .map_err(|_| FfmpegError::InvalidData)?;
+
+        unsafe {
+            let context = octx.as_mut_ptr();
+            ffi::avio_closep(&mut (*context).pb);
+            let result = ffi::avio_open(&mut (*context).pb, c_path.as_ptr(), ffi::AVIO_FLAG_WRITE);
+            if result < 0 {
+                return Err(FfmpegError::from(result).into());
+            }
+        }
+
+        self.segment_uri = variant_uri(&self.playlist_path, &path);
+        self.playlist.start_segment(&self.segment_uri);
+        self.part_offset = 0;
+        Ok(())
+    }
+
+    /// Replaces the playlist atomically so blocked readers never observe a partial write.
+    fn write_playlist(&self) -> Result<(), RecorderError> {
+        write_atomically(&self.playlist_path, self.playlist.render().as_bytes())
+    }
+}
+
+/// Writes `contents` next to `path` and renames it into place.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), RecorderError> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+/// One `EXT-X-STREAM-INF` entry of an HLS master playlist.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariantStream {
+    /// URI of the rendition's media playlist, relative to the master playlist.
+    pub uri: String,
+    /// Peak bitrate of the rendition in bits per second, audio included.
+    pub bandwidth: u64,
+    /// Encoded picture size, when the rendition carries video.
+    pub resolution: Option<(u32, u32)>,
+    /// RFC 6381 codec strings, e.g. `avc1.64001f` and `mp4a.40.2`.
+    pub codecs: Vec<String>,
+}
+
+/// Renders an HLS master playlist listing `variants` in the given order.
+pub fn master_playlist(variants: &[VariantStream]) -> String {
+    let mut playlist = String::from("#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-INDEPENDENT-SEGMENTS\n");
+
+    for variant in variants {
+        let mut attributes = format!("BANDWIDTH={}", variant.bandwidth);
+        if let Some((width, height)) = variant.resolution {
+            let _ = write!(attributes, ",RESOLUTION={width}x{height}");
+        }
+        if !variant.codecs.is_empty() {
+            let _ = write!(attributes, ",CODECS=\"{}\"", variant.codecs.join(","));
+        }
+        let _ = write!(
+            playlist,
+            "#EXT-X-STREAM-INF:{attributes}\n{}\n",
+            variant.uri
+        );
+    }
+
+    playlist
+}
+
+/// Expresses `playlist` relative to the directory of `master`, falling back to the full path.
+pub fn variant_uri(master: &Path, playlist: &Path) -> String {
+    let base = master.parent().unwrap_or_else(|| Path::new(""));
+    playlist
+        .strip_prefix(base)
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| playlist.to_path_buf())
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Derives the `avc1` codec string from H.264 extradata in either `avcC` or Annex B form.
+pub fn h264_codec_string(extradata: &[u8]) -> Option<String> {
+    let sps = if extradata.first() == Some(&1) && extradata.len() >= 4 {
+        // avcC: configurationVersion followed by profile, compatibility and level.
+        &extradata[1..4]
+    } else {
+        let nal =
+            annexb_nal_units(extradata).find(|nal| nal.first().map(|h| h & 0x1f) == Some(7))?;
+        nal.get(1..4)?
+    };
+
+    Some(format!("avc1.{:02x}{:02x}{:02x}", sps[0], sps[1], sps[2]))
+}
+
+/// Derives the `hvc1` codec string from the SPS found in Annex B H.265 extradata.
+pub fn hevc_codec_string(extradata: &[u8]) -> Option<String> {
+    let nal =
+        annexb_nal_units(extradata).find(|nal| nal.first().map(|h| (h >> 1) & 0x3f) == Some(33))?;
+    let rbsp = strip_emulation_prevention(nal.get(2..)?);
+
+    // Skip sps_video_parameter_set_id, sps_max_sub_layers_minus1 and the nesting flag (one byte),
+    // then read general_profile_tier_level: 1 + 4 + 6 + 1 bytes.
+    let ptl = rbsp.get(1..13)?;
+    let profile_space = ptl[0] >> 6;
+    let tier = if ptl[0] & 0x20 != 0 { 'H' } else { 'L' };
+    let profile_idc = ptl[0] & 0x1f;
+    let compatibility = u32::from_be_bytes([ptl[1], ptl[2], ptl[3], ptl[4]]).reverse_bits();
+    let constraints = &ptl[5..11];
+    let level_idc = ptl[11];
+
+    let prefix = match profile_space {
+        1 => "A",
+        2 => "B",
+        3 => "C",
+        _ => "",
+    };
+    let mut codec = format!("hvc1.{prefix}{profile_idc}.{compatibility:X}.{tier}{level_idc}");
+
+    let significant = constraints
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map_or(0, |i| i + 1);
+    for byte in &constraints[..significant.max(1)] {
+        let _ = write!(codec, ".{byte:X}");
+    }
+
+    Some(codec)
+}
+
+/// Codec string for an audio stream, given its FFmpeg codec name and profile.
+///
+/// Returns `None` for codecs HLS has no registered identifier for, such as G.711.
+pub fn audio_codec_string(codec: &str, profile: i32) -> Option<String> {
+    let codec = match codec {
+        "aac" => match profile {
+            // FFmpeg profile numbers are the MPEG-4 audio object type minus one.
+            4 => "mp4a.40.5",
+            28 => "mp4a.40.29",
+            _ => "mp4a.40.2",
+        },
+        "mp3" => "mp4a.40.34",
+        "opus" => "opus",
+        "ac3" => "ac-3",
+        "eac3" => "ec-3",
+        _ => return None,
+    };
+    Some(codec.to_string())
+}
+
+/// Splits Annex B data on `00 00 01` start codes.
+pub(crate) fn annexb_nal_units(data: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut starts = Vec::new();
+    let mut index = 0;
+    while index + 3 <= data.len() {
+        if data[index..index + 3] == [0, 0, 1] {
+            starts.push(index + 3);
+            index += 3;
+        } else {
+            index += 1;
+        }
+    }
+
+    let ends: Vec<usize> = starts
+        .iter()
+        .skip(1)
+        .map(|start| {
+            // Trim the start code, including the leading zero of a four-byte start code.
+            let mut end = start - 3;
+            while end > 0 && data[end - 1] == 0 {
+                end -= 1;
+            }
+            end
+        })
+        .chain(std::iter::once(data.len()))
+        .collect();
+
+    starts
+        .into_iter()
+        .zip(ends)
+        .map(move |(start, end)| &data[start..end.max(start)])
+}
+
+/// Removes `00 00 03` emulation prevention bytes from a NAL unit payload.
+fn strip_emulation_prevention(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &byte in data {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        output.push(byte);
+    }
+    output
+}
@@ -1,7 +1,88 @@
+mod archive;
+mod audio_transcoder;
+mod coverage;
+mod encryption;
 mod error;
+mod filter_graph;
+mod frame_rate;
 mod hls_output;
+mod ll_hls;
+mod master_playlist;
+mod output;
+mod output_muxer;
+mod overlay;
+mod privacy_mask;
+mod processing;
+mod program_date_time;
+mod push_output;
+mod recovery;
 mod rtsp_recorder;
+mod segment_index;
+mod snapshot;
+mod source_clock;
+mod stream_selection;
+mod thumbnails;
+mod timelapse;
+mod timestamp_sanitizer;
+mod video_transcoder;
+mod vod_playlist;
 
+pub use archive::{ArchiveFormat, ArchiveOptions};
+pub use coverage::{CoverageReport, DayCoverage, Gap, coverage_report, playlist_coverage};
+pub use encryption::{EncryptionOptions, key_file_name, key_info_contents, rotation_index};
 pub use error::RecorderError;
-pub use hls_output::{HlsOutput, VideoCodec};
-pub use rtsp_recorder::{RtspRecorder, derive_segment_template};
+pub use frame_rate::{FrameRateConverter, FrameRateMode};
+pub use hls_output::{
+    AudioMode, AudioOptions, HlsOutput, Packaging, Rendition, SegmentFormat, VideoCodec,
+    derive_dash_segment_names, derive_init_segment_name,
+};
+pub use ll_hls::{
+    LlHlsPlaylist, LlHlsSegment, LowLatencyOptions, PartialSegment, blocking_reload_timeout,
+    playlist_has_part, preload_hint_uri, segment_file_name,
+};
+pub(crate) use master_playlist::annexb_nal_units;
+pub use master_playlist::{
+    VariantStream, audio_codec_string, h264_codec_string, hevc_codec_string, master_playlist,
+    variant_uri,
+};
+pub use output::{ArchiveOutput, Output, PushOnlyOutput};
+pub use overlay::{OverlayClock, OverlayOptions, OverlayPosition, overlay_text};
+pub use privacy_mask::{MaskBitmap, MaskStyle, PrivacyMask, rgb_to_yuv};
+pub use processing::{ResolutionChange, VideoProcessing};
+pub use program_date_time::{
+    WallClock, first_program_date_time, format_program_date_time, now_epoch_seconds,
+    parse_program_date_time, rewrite_program_date_times,
+};
+pub use push_output::{PushOutput, PushProtocol, PushTarget, parameter_set_extradata};
+pub use recovery::{
+    Recovery, SegmentRepair, fmp4_playable_length, next_segment_uri, recover_playlist,
+    reopen_playlist, repair_segment, split_playlist, ts_playable_length, ts_timestamp_span,
+};
+pub use rtsp_recorder::{
+    RtspRecorder, SESSION_PLACEHOLDER, WALLCLOCK_PLACEHOLDER, derive_segment_template,
+    session_name, wallclock_segment_template,
+};
+pub use segment_index::{
+    PlaylistEntry, SegmentRecord, insert_discontinuities, locate_segment, media_playlist_entries,
+    read_segment_index, segment_index_path,
+};
+pub use snapshot::{
+    SnapshotHub, SnapshotOptions, SnapshotSchedule, SnapshotSlot, snapshot_archive_name,
+    snapshot_size,
+};
+pub use source_clock::{ClockSource, SourceClock, producer_reference_time};
+pub use stream_selection::{StreamInfo, StreamSelection, StreamSelector};
+pub use thumbnails::{
+    THUMBNAILS_DATA_ID, ThumbnailCell, ThumbnailGrid, ThumbnailOptions, insert_thumbnail_reference,
+    next_sprite_sheet, sprite_sheet_name, thumbnail_master_path, thumbnail_track_path,
+    vtt_timestamp,
+};
+pub use timelapse::{
+    TimelapseOptions, TimelapseSample, next_daily_run, parse_time_of_day, previous_day,
+    render_timelapse, timelapse_file_name, timelapse_samples,
+};
+pub use timestamp_sanitizer::{TimestampAnomaly, TimestampCorrection, TimestampSanitizer};
+pub use vod_playlist::{
+    InitSegment, init_segment_at, init_segment_uri, init_segments, parse_window_time,
+    place_init_segments, vod_playlist, window_playlist,
+};
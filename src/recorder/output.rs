@@ -0,0 +1,123 @@
+use std::path::PathBuf;
+
+use super::{
+    ArchiveOptions, AudioOptions, HlsOutput, Packaging, PushProtocol, PushTarget, VideoCodec,
+};
+
+/// One destination of a recording, fed from the shared RTSP connection and decoders.
+#[derive(Debug, Clone)]
+pub enum Output {
+    /// HLS, DASH or CMAF presentation.
+    Hls(HlsOutput),
+    /// Long-term MP4 or Matroska archive files, without a manifest.
+    Archive(ArchiveOutput),
+    /// Restreaming targets fed without writing anything locally.
+    Push(PushOnlyOutput),
+}
+
+/// Long-term archive files of a recording.
+#[derive(Debug, Clone)]
+pub struct ArchiveOutput {
+    /// Destination path of the archive, extended with the start time, or its `strftime` pattern
+    /// when rotating.
+    pub path: PathBuf,
+    /// Video codec of the archive, or stream copy of the source video.
+    pub video_codec: VideoCodec,
+    /// Handling of audio tracks: copy, transcode, or drop.
+    pub audio: AudioOptions,
+    /// Container and rotation of the archive files.
+    pub options: ArchiveOptions,
+}
+
+/// Restreaming targets with their own codec choice.
+#[derive(Debug, Clone)]
+pub struct PushOnlyOutput {
+    /// Live restreaming targets receiving the stream.
+    pub targets: Vec<PushTarget>,
+    /// Video codec sent to the targets, or stream copy of the source video.
+    pub video_codec: VideoCodec,
+    /// Handling of audio tracks: copy, transcode, or drop.
+    pub audio: AudioOptions,
+}
+
+impl Output {
+    /// Target codec of the output's video stream.
+    pub fn video_codec(&self) -> VideoCodec {
+        match self {
+            Output::Hls(hls) => hls.video_codec,
+            Output::Archive(archive) => archive.video_codec,
+            Output::Push(push) => push.video_codec,
+        }
+    }
+
+    /// Handling of the output's audio tracks.
+    pub fn audio(&self) -> AudioOptions {
+        match self {
+            Output::Hls(hls) => hls.audio,
+            Output::Archive(archive) => archive.audio,
+            Output::Push(push) => push.audio,
+        }
+    }
+
+    /// Live restreaming targets fed with the packets written to this output.
+    pub fn push_targets(&self) -> &[PushTarget] {
+        match self {
+            Output::Hls(hls) => &hls.push,
+            Output::Archive(_) => &[],
+            Output::Push(push) => &push.targets,
+        }
+    }
+
+    /// The HLS, DASH or CMAF presentation, when this output is one.
+    pub fn as_hls(&self) -> Option<&HlsOutput> {
+        match self {
+            Output::Hls(hls) => Some(hls),
+            Output::Archive(_) | Output::Push(_) => None,
+        }
+    }
+
+    /// Optional target video bitrate in bits per second; only presentations set one.
+    pub fn video_bitrate(&self) -> Option<u32> {
+        self.as_hls().and_then(|hls| hls.video_bitrate)
+    }
+
+    /// Resolves the encoded picture size for a source of `source_width` x `source_height`.
+    ///
+    /// Archives and push outputs keep the source size.
+    pub fn output_size(&self, source_width: u32, source_height: u32) -> (u32, u32) {
+        match self.as_hls() {
+            Some(hls) => hls.output_size(source_width, source_height),
+            None => (source_width, source_height),
+        }
+    }
+
+    /// Whether everything written for this output is MPEG-TS, which carries video parameter sets
+    /// in-band and so accepts an encoder re-opened at a new resolution. MP4, Matroska and FLV fix
+    /// them in a header or init segment.
+    pub fn accepts_parameter_changes(&self) -> bool {
+        let local = match self {
+            Output::Hls(hls) => hls.packaging == Packaging::Hls && !hls.uses_fmp4(),
+            Output::Archive(_) => false,
+            Output::Push(_) => true,
+        };
+        local
+            && self
+                .push_targets()
+                .iter()
+                .all(|target| target.protocol() != Some(PushProtocol::Rtmp))
+    }
+
+    /// Human-readable name of the output for log and error messages: its playlist or archive
+    /// path, or its first push target.
+    pub fn display_name(&self) -> String {
+        match self {
+            Output::Hls(hls) => hls.playlist_path.display().to_string(),
+            Output::Archive(archive) => archive.path.display().to_string(),
+            Output::Push(push) => push
+                .targets
+                .first()
+                .map(PushTarget::display_name)
+                .unwrap_or_else(|| "push output".to_string()),
+        }
+    }
+}
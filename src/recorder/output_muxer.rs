@@ -0,0 +1,644 @@
+use std::ffi::{CStr, CString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::time::SystemTime;
+
+use ffmpeg_next::{Dictionary, Error as FfmpegError, Packet, Rational, ffi, format, media};
+
+use super::encryption::KeyRotator;
+use super::ll_hls::{LowLatencyPackager, write_atomically};
+use super::program_date_time::{
+    WallClock, first_program_date_time, now_epoch_seconds, rewrite_program_date_times,
+};
+use super::push_output::PushOutput;
+use super::segment_index::{PlaylistIndexer, insert_discontinuities};
+use super::{
+    ArchiveOutput, HlsOutput, InitSegment, Output, Packaging, PushTarget, RecorderError,
+    SESSION_PLACEHOLDER, SegmentFormat, WALLCLOCK_PLACEHOLDER, derive_dash_segment_names,
+    derive_init_segment_name, derive_segment_template, init_segments, place_init_segments,
+    recover_playlist, reopen_playlist, segment_file_name, segment_index_path, session_name,
+    split_playlist, wallclock_segment_template,
+};
+
+/// FFmpeg muxer for one output, together with the stream time bases chosen by its header.
+///
+/// HLS outputs use the `hls` muxer, DASH and CMAF outputs the `dash` muxer, archives the `mp4`,
+/// `matroska` or, when rotating, `segment` muxer and push-only outputs the `null` muxer.
+/// Low-Latency HLS bypasses them and is packaged by [`LowLatencyPackager`].
+pub(crate) struct OutputMuxer {
+    octx: format::context::Output,
+    time_bases: Vec<Rational>,
+    video_streams: Vec<bool>,
+    low_latency: Option<LowLatencyPackager>,
+    key_rotator: Option<KeyRotator>,
+    /// Output time of the first packet, in seconds; media time counts from here.
+    first_seconds: Option<f64>,
+    /// Maps media time since the first packet to wall-clock time, for dating and indexing segments.
+    wall_clock: WallClock,
+    /// Latest difference between wall-clock and output time reported by the source clock.
+    wall_clock_offset: Option<f64>,
+    /// Playlist written by the `hls` muxer, followed to index segments and correct their dates.
+    watched_playlist: Option<WatchedPlaylist>,
+    /// Restreaming targets, started once the header fixes the stream parameters.
+    push_targets: Vec<PushTarget>,
+    push_outputs: Vec<PushOutput>,
+    /// Muxer options applied when the header is written.
+    header_options: Vec<(&'static str, String)>,
+}
+
+/// Tracks the `hls` muxer's playlist between rewrites.
+struct WatchedPlaylist {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    indexer: PlaylistIndexer,
+    /// Whether `EXT-X-PROGRAM-DATE-TIME` tags are replaced with wall-clock dates.
+    program_date_time: bool,
+    /// Date the muxer gave the first segment, from its local clock at header time.
+    muxer_origin: Option<f64>,
+    /// First media sequence number of this recording when it continues an interrupted or
+    /// appended one.
+    resumed_from: Option<u64>,
+    /// Init segments of every session of a continued fMP4 playlist; empty otherwise.
+    init_segments: Vec<InitSegment>,
+    /// Target segment length the muxer cuts at, in seconds.
+    segment_seconds: f64,
+    /// Media time from which the next cutting packet ends the current segment. Like the muxer,
+    /// boundaries are counted from the start of the recording, one segment length apart.
+    next_cut: f64,
+    /// Whether a packet that should end the current segment was written. The playlist is only
+    /// checked from then on until the muxer rewrites it.
+    cut_due: bool,
+}
+
+/// FFmpeg's default `hls_time`, in seconds.
+const DEFAULT_SEGMENT_SECONDS: f64 = 2.0;
+
+/// Drift, in seconds, between the wall clock and the source clock that triggers a re-anchor.
+const WALL_CLOCK_TOLERANCE: f64 = 0.1;
+
+impl OutputMuxer {
+    /// Validates `output` and opens the muxer writing it.
+    pub(crate) fn open(output: &Output) -> Result<Self, RecorderError> {
+        if let Some(target) = output
+            .push_targets()
+            .iter()
+            .find(|target| target.protocol().is_none())
+        {
+            return Err(RecorderError::InvalidOutput(format!(
+                "push target {} must use rtmp, rtmps, srt or udp",
+                target.display_name()
+            )));
+        }
+
+        let mut muxer = match output {
+            Output::Hls(hls_output) => Self::open_presentation(hls_output)?,
+            Output::Archive(archive) => Self::open_archive(archive)?,
+            Output::Push(push) => {
+                if push.targets.is_empty() {
+                    return Err(RecorderError::InvalidOutput(
+                        "a push output needs at least one target".to_string(),
+                    ));
+                }
+                Self::open_null()?
+            }
+        };
+        muxer.push_targets = output.push_targets().to_vec();
+        Ok(muxer)
+    }
+
+    /// Creates the playlist directory and opens the muxer of an HLS, DASH or CMAF presentation.
+    fn open_presentation(hls_output: &HlsOutput) -> Result<Self, RecorderError> {
+        create_parent_dir(&hls_output.playlist_path)?;
+
+        if hls_output.segment_format == Some(SegmentFormat::Mpegts) && hls_output.uses_fmp4() {
+            return Err(RecorderError::InvalidOutput(
+                "MPEG-TS segments cannot be combined with low_latency, DASH or CMAF".to_string(),
+            ));
+        }
+
+        if hls_output.encryption.is_some()
+            && (hls_output.packaging != Packaging::Hls || hls_output.low_latency.is_some())
+        {
+            return Err(RecorderError::InvalidOutput(
+                "encryption is only available with regular HLS packaging".to_string(),
+            ));
+        }
+
+        if hls_output
+            .segment_filename
+            .as_deref()
+            .is_some_and(|template| template.contains(WALLCLOCK_PLACEHOLDER))
+            && (hls_output.packaging != Packaging::Hls || hls_output.low_latency.is_some())
+        {
+            return Err(RecorderError::InvalidOutput(
+                "wall-clock segment names are only available with regular HLS packaging"
+                    .to_string(),
+            ));
+        }
+
+        if hls_output.append
+            && (hls_output.packaging != Packaging::Hls || hls_output.low_latency.is_some())
+        {
+            return Err(RecorderError::InvalidOutput(
+                "append is only available with regular HLS packaging".to_string(),
+            ));
+        }
+
+        if hls_output.thumbnails.is_some()
+            && (hls_output.packaging != Packaging::Hls || hls_output.low_latency.is_some())
+        {
+            return Err(RecorderError::InvalidOutput(
+                "thumbnails are only available with regular HLS packaging".to_string(),
+            ));
+        }
+
+        Self::open_muxer(hls_output)
+    }
+
+    /// Opens the packaging-specific muxer once `open_presentation` has validated the options.
+    fn open_muxer(hls_output: &HlsOutput) -> Result<Self, RecorderError> {
+        if hls_output.packaging != Packaging::Hls {
+            if hls_output.low_latency.is_some() {
+                return Err(RecorderError::InvalidOutput(
+                    "low_latency is only available with HLS packaging".to_string(),
+                ));
+            }
+            ensure_fresh_presentation(hls_output)?;
+            return Self::open_dash(hls_output);
+        }
+
+        if let Some(options) = &hls_output.low_latency {
+            ensure_fresh_presentation(hls_output)?;
+            let (octx, packager) = LowLatencyPackager::open(hls_output, options)?;
+            let mut muxer = Self::new(octx);
+            muxer.low_latency = Some(packager);
+            return Ok(muxer);
+        }
+
+        let mut format_options = Dictionary::new();
+
+        if let Some(duration) = hls_output.segment_duration {
+            format_options.set("hls_time", &duration.to_string());
+        }
+
+        if let Some(size) = hls_output.playlist_size {
+            format_options.set("hls_list_size", &size.to_string());
+        }
+
+        let mut hls_flags = Vec::new();
+        let session = session_name(now_epoch_seconds());
+        let segment_template =
+            derive_segment_template(hls_output).replace(SESSION_PLACEHOLDER, &session);
+        match wallclock_segment_template(&segment_template) {
+            Some(pattern) => {
+                format_options.set("strftime", "1");
+                if segment_file_name(&segment_template, 0) != segment_template {
+                    hls_flags.push("second_level_segment_index");
+                }
+                format_options.set("hls_segment_filename", &pattern);
+            }
+            None => format_options.set("hls_segment_filename", &segment_template),
+        }
+        let resumed_from = match recover_playlist(&hls_output.playlist_path, &segment_template)? {
+            Some(recovery) => {
+                println!(
+                    "{}: continuing an interrupted recording at segment {} ({} repaired, {} dropped)",
+                    hls_output.playlist_path.display(),
+                    recovery.next_sequence,
+                    recovery.repaired.len(),
+                    recovery.dropped.len()
+                );
+                Some(recovery.next_sequence)
+            }
+            None if hls_output.append => {
+                let next_sequence = reopen_playlist(&hls_output.playlist_path)?;
+                if let Some(next_sequence) = next_sequence {
+                    println!(
+                        "{}: appending to the previous recording at segment {next_sequence}",
+                        hls_output.playlist_path.display()
+                    );
+                }
+                next_sequence
+            }
+            None => None,
+        };
+        let mut placed_init_segments = Vec::new();
+        if hls_output.uses_fmp4() {
+            let init_name = derive_init_segment_name(hls_output, resumed_from.is_some())
+                .replace(SESSION_PLACEHOLDER, &session);
+            format_options.set("hls_segment_type", "fmp4");
+            format_options.set("hls_fmp4_init_filename", &init_name);
+            if let Some(next_sequence) = resumed_from {
+                // The muxer would announce its init segment for the earlier segments as well.
+                placed_init_segments =
+                    init_segments(&fs::read_to_string(&hls_output.playlist_path)?);
+                placed_init_segments.push(InitSegment {
+                    sequence: next_sequence,
+                    uri: init_name,
+                });
+            }
+        }
+
+        let key_rotator = match &hls_output.encryption {
+            Some(options) => {
+                let rotator = KeyRotator::new(hls_output, options)?;
+                format_options.set(
+                    "hls_key_info_file",
+                    &rotator.key_info_path().to_string_lossy(),
+                );
+                if rotator.rotates() {
+                    hls_flags.push("periodic_rekey");
+                }
+                Some(rotator)
+            }
+            None => None,
+        };
+        if hls_output.program_date_time {
+            hls_flags.push("program_date_time");
+        }
+        if let Some(next_sequence) = resumed_from {
+            format_options.set("start_number", &next_sequence.to_string());
+            hls_flags.push("append_list");
+            hls_flags.push("discont_start");
+        }
+        if !hls_flags.is_empty() {
+            format_options.set("hls_flags", &hls_flags.join("+"));
+        }
+
+        let octx = format::output_as_with(&hls_output.playlist_path, "hls", format_options)?;
+
+        let mut muxer = Self::new(octx);
+        muxer.key_rotator = key_rotator;
+        let segment_seconds = hls_output
+            .segment_duration
+            .map_or(DEFAULT_SEGMENT_SECONDS, f64::from);
+        muxer.watched_playlist = Some(WatchedPlaylist {
+            path: hls_output.playlist_path.clone(),
+            modified: None,
+            indexer: match resumed_from {
+                Some(next_sequence) => {
+                    PlaylistIndexer::resume(&hls_output.playlist_path, next_sequence)?
+                }
+                None => PlaylistIndexer::create(&hls_output.playlist_path)?,
+            },
+            program_date_time: hls_output.program_date_time,
+            muxer_origin: None,
+            resumed_from,
+            init_segments: placed_init_segments,
+            segment_seconds,
+            next_cut: segment_seconds,
+            cut_due: false,
+        });
+        Ok(muxer)
+    }
+
+    fn new(octx: format::context::Output) -> Self {
+        Self {
+            octx,
+            time_bases: Vec::new(),
+            video_streams: Vec::new(),
+            low_latency: None,
+            key_rotator: None,
+            first_seconds: None,
+            wall_clock: WallClock::new(),
+            wall_clock_offset: None,
+            watched_playlist: None,
+            push_targets: Vec::new(),
+            push_outputs: Vec::new(),
+            header_options: Vec::new(),
+        }
+    }
+
+    /// Opens FFmpeg's `dash` muxer; CMAF additionally writes HLS playlists over the same segments.
+    fn open_dash(hls_output: &HlsOutput) -> Result<Self, RecorderError> {
+        let mut format_options = Dictionary::new();
+
+        if let Some(duration) = hls_output.segment_duration {
+            format_options.set("seg_duration", &duration.to_string());
+        }
+
+        if let Some(size) = hls_output.playlist_size {
+            format_options.set("window_size", &size.to_string());
+        }
+
+        let (init_name, media_name) = derive_dash_segment_names(hls_output);
+        format_options.set("init_seg_name", &init_name);
+        format_options.set("media_seg_name", &media_name);
+        format_options.set("use_template", "1");
+        format_options.set("use_timeline", "1");
+
+        if hls_output.packaging == Packaging::Cmaf {
+            let stem = hls_output
+                .playlist_path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or("stream");
+            format_options.set("hls_playlist", "1");
+            format_options.set("hls_master_name", &format!("{stem}.m3u8"));
+        }
+
+        let octx = format::output_as_with(&hls_output.playlist_path, "dash", format_options)?;
+
+        Ok(Self::new(octx))
+    }
+
+    /// Opens an MP4 or Matroska archive, or the `segment` muxer rotating through such files.
+    fn open_archive(output: &ArchiveOutput) -> Result<Self, RecorderError> {
+        let archive = output.options;
+        if archive.rotate_minutes == Some(0) {
+            return Err(RecorderError::InvalidOutput(
+                "rotate_minutes must be at least 1".to_string(),
+            ));
+        }
+        create_parent_dir(&output.path)?;
+
+        let file_template = archive.file_template(&output.path, &session_name(now_epoch_seconds()));
+        let octx = if archive.rotate_minutes.is_some() {
+            // The segment muxer opens each file itself, and the template is no file name.
+            let file_template =
+                CString::new(file_template).map_err(|_| FfmpegError::InvalidData)?;
+            Self::alloc_output(c"segment", &file_template)?
+        } else {
+            format::output_as_with(&file_template, archive.muxer(), Dictionary::new())?
+        };
+
+        let mut muxer = Self::new(octx);
+        muxer.header_options = archive.muxer_options();
+        Ok(muxer)
+    }
+
+    /// Opens FFmpeg's `null` muxer, which accepts every stream and writes nothing, so a push-only
+    /// output still gets stream parameters and time bases for its targets.
+    fn open_null() -> Result<Self, RecorderError> {
+        Ok(Self::new(Self::alloc_output(c"null", c"")?))
+    }
+
+    /// Allocates an output context for a muxer that opens its own files, without opening `url`.
+    fn alloc_output(
+        format_name: &CStr,
+        url: &CStr,
+    ) -> Result<format::context::Output, RecorderError> {
+        unsafe {
+            let mut context = ptr::null_mut();
+            let result = ffi::avformat_alloc_output_context2(
+                &mut context,
+                ptr::null_mut(),
+                format_name.as_ptr(),
+                url.as_ptr(),
+            );
+            if result < 0 {
+                return Err(FfmpegError::from(result).into());
+            }
+            Ok(format::context::Output::wrap(context))
+        }
+    }
+
+    /// Reports that output time `seconds` corresponds to wall-clock time `epoch_seconds`.
+    ///
+    /// Called for every input packet; without reports, segments are dated from the local clock
+    /// at the first packet.
+    pub(crate) fn sync_wall_clock(&mut self, seconds: f64, epoch_seconds: f64) {
+        self.wall_clock_offset = Some(epoch_seconds - seconds);
+    }
+
+    /// Notes a timestamp discontinuity at output time `seconds`; the next segment is tagged with
+    /// `EXT-X-DISCONTINUITY`.
+    pub(crate) fn mark_discontinuity(&mut self, seconds: f64) {
+        // Nothing has been written yet, so there is nothing to separate from.
+        let Some(origin) = self.first_seconds else {
+            return;
+        };
+        if let Some(packager) = self.low_latency.as_mut() {
+            packager.mark_discontinuity();
+        }
+        if let Some(watched) = self.watched_playlist.as_mut() {
+            watched.indexer.mark_discontinuity(seconds - origin);
+        }
+    }
+
+    /// First media sequence number of this recording when it continues an interrupted or
+    /// appended playlist.
+    pub(crate) fn resumed_from(&self) -> Option<u64> {
+        self.watched_playlist
+            .as_ref()
+            .and_then(|watched| watched.resumed_from)
+    }
+
+    /// Underlying output context, for adding streams before the header is written.
+    pub(crate) fn context(&mut self) -> &mut format::context::Output {
+        &mut self.octx
+    }
+
+    /// Number of output streams added so far.
+    pub(crate) fn stream_count(&self) -> usize {
+        self.octx.nb_streams() as usize
+    }
+
+    /// Writes the header and records the time base the muxer settled on for each stream.
+    pub(crate) fn write_header(&mut self, metadata: Dictionary) -> Result<(), RecorderError> {
+        self.octx.set_metadata(metadata);
+        if self.header_options.is_empty() {
+            self.octx.write_header()?;
+        } else {
+            let mut options = Dictionary::new();
+            for (name, value) in &self.header_options {
+                options.set(name, value);
+            }
+            self.octx.write_header_with(options)?;
+        }
+        self.time_bases = self
+            .octx
+            .streams()
+            .map(|stream| stream.time_base())
+            .collect();
+        self.video_streams = self
+            .octx
+            .streams()
+            .map(|stream| stream.parameters().medium() == media::Type::Video)
+            .collect();
+        if let Some(packager) = self.low_latency.as_mut() {
+            packager.start(&mut self.octx)?;
+        }
+        if let Some(rotator) = self.key_rotator.as_mut() {
+            // The header opened the first segment with the first key.
+            rotator.prepare(1)?;
+        }
+        self.push_outputs = self
+            .push_targets
+            .iter()
+            .map(|target| PushOutput::start(target, &self.octx))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    /// Rescales `packet` from `time_base` to output stream `ost_index` and writes it interleaved.
+    pub(crate) fn write(
+        &mut self,
+        packet: &mut Packet,
+        ost_index: usize,
+        time_base: Rational,
+    ) -> Result<(), RecorderError> {
+        let ost_time_base = *self
+            .time_bases
+            .get(ost_index)
+            .ok_or(RecorderError::InvalidStreamMapping(ost_index))?;
+        packet.rescale_ts(time_base, ost_time_base);
+        packet.set_position(-1);
+        packet.set_stream(ost_index);
+        for push in &mut self.push_outputs {
+            push.send(packet);
+        }
+
+        let seconds = packet.pts().unwrap_or_default() as f64 * f64::from(ost_time_base);
+        let origin = *self.first_seconds.get_or_insert(seconds);
+        let media_seconds = seconds - origin;
+        let reported = self
+            .wall_clock_offset
+            .map(|offset| seconds + offset)
+            .or_else(|| (!self.wall_clock.is_anchored()).then(now_epoch_seconds));
+        if let Some(reported) = reported
+            && self
+                .wall_clock
+                .epoch_at(media_seconds)
+                .is_none_or(|current| (current - reported).abs() > WALL_CLOCK_TOLERANCE)
+        {
+            self.wall_clock.anchor(media_seconds, reported);
+        }
+        let epoch_seconds = self.wall_clock.epoch_at(media_seconds).unwrap_or_default();
+
+        if let Some(packager) = self.low_latency.as_mut() {
+            let is_video = self.video_streams.get(ost_index).copied().unwrap_or(false);
+            packager.before_packet(
+                &mut self.octx,
+                seconds,
+                epoch_seconds,
+                is_video,
+                packet.is_key(),
+            )?;
+            // Fragments are cut by hand, so packets go straight to the muxer without interleaving.
+            packet.write(&mut self.octx)?;
+        } else {
+            // The muxer cuts segments at video keyframes, or at any packet without video.
+            let is_video = self.video_streams.get(ost_index).copied().unwrap_or(false);
+            let cuts = packet.is_key() && (is_video || !self.video_streams.contains(&true));
+            packet.write_interleaved(&mut self.octx)?;
+            let Some(watched) = self.watched_playlist.as_mut() else {
+                return Ok(());
+            };
+            if cuts && media_seconds >= watched.next_cut {
+                watched.cut_due = true;
+            }
+            // Interleaving may delay the packet that ends a segment, so keep checking until the
+            // muxer has rewritten the playlist.
+            if watched.cut_due {
+                self.follow_playlist()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Indexes the segments of a freshly written `hls` playlist, tags discontinuities, restores the
+    /// init segments of earlier sessions and, when enabled, replaces the muxer's local-clock dates
+    /// with wall-clock ones.
+    fn follow_playlist(&mut self) -> Result<(), RecorderError> {
+        let Some(watched) = self.watched_playlist.as_mut() else {
+            return Ok(());
+        };
+        let Ok(modified) = fs::metadata(&watched.path).and_then(|metadata| metadata.modified())
+        else {
+            return Ok(());
+        };
+        if watched.modified == Some(modified) {
+            return Ok(());
+        }
+        if watched.cut_due {
+            watched.cut_due = false;
+            watched.next_cut += watched.segment_seconds;
+        }
+
+        let playlist = fs::read_to_string(&watched.path)?;
+        let clock = &self.wall_clock;
+        watched
+            .indexer
+            .update(&playlist, |media_seconds| clock.epoch_at(media_seconds))?;
+        if let Some(rotator) = self.key_rotator.as_mut() {
+            // The segment after the listed ones is open already; prepare the key of the one after.
+            let open = watched.indexer.next_sequence() - watched.resumed_from.unwrap_or(0);
+            rotator.prepare(open + 1)?;
+        }
+
+        let discontinuous = watched.indexer.discontinuous();
+        if !watched.program_date_time
+            && discontinuous.is_empty()
+            && watched.init_segments.is_empty()
+        {
+            watched.modified = Some(modified);
+            return Ok(());
+        }
+
+        let mut rewritten = insert_discontinuities(&playlist, discontinuous);
+        if watched.program_date_time {
+            // Segments of the interrupted recording keep the dates they were written with.
+            let (earlier, current) = match watched.resumed_from {
+                Some(sequence) => split_playlist(&rewritten, sequence),
+                None => ("", rewritten.as_str()),
+            };
+            if watched.muxer_origin.is_none() {
+                // The first playlist the muxer writes still lists the first segment.
+                watched.muxer_origin = first_program_date_time(current);
+            }
+            if let Some(muxer_origin) = watched.muxer_origin {
+                rewritten = format!(
+                    "{earlier}{}",
+                    rewrite_program_date_times(current, muxer_origin, clock)
+                );
+            }
+        }
+        if !watched.init_segments.is_empty() {
+            rewritten = place_init_segments(&rewritten, &watched.init_segments);
+        }
+        write_atomically(&watched.path, rewritten.as_bytes())?;
+        watched.modified = fs::metadata(&watched.path)?.modified().ok();
+        Ok(())
+    }
+
+    /// Finalizes the playlist once all packets are written.
+    pub(crate) fn finish(&mut self) -> Result<(), RecorderError> {
+        if let Some(packager) = self.low_latency.as_mut() {
+            packager.finish(&mut self.octx)?;
+        }
+        for push in &mut self.push_outputs {
+            push.finish();
+        }
+        self.octx.write_trailer()?;
+        self.follow_playlist()
+    }
+}
+
+/// Ensures the directory of `path` exists, so FFmpeg can create the files written there.
+fn create_parent_dir(path: &Path) -> Result<(), RecorderError> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    Ok(())
+}
+
+/// Refuses to start a Low-Latency HLS, DASH or CMAF presentation over the playlist or index of an
+/// earlier recording: these packagings cannot continue one and would overwrite it segment by
+/// segment.
+fn ensure_fresh_presentation(hls_output: &HlsOutput) -> Result<(), RecorderError> {
+    let index_path = segment_index_path(&hls_output.playlist_path);
+    if let Some(existing) = [hls_output.playlist_path.as_path(), index_path.as_path()]
+        .into_iter()
+        .find(|path| path.exists())
+    {
+        return Err(RecorderError::InvalidOutput(format!(
+            "{} is left from an earlier recording, which Low-Latency HLS, DASH and CMAF outputs cannot continue; move it away before restarting",
+            existing.display()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use serde::Deserialize;
+
+/// Corner of the frame where the burned-in overlay text is anchored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverlayPosition {
+    /// Upper-left corner of the frame.
+    TopLeft,
+    /// Upper-right corner of the frame.
+    TopRight,
+    /// Lower-left corner of the frame.
+    BottomLeft,
+    /// Lower-right corner of the frame.
+    BottomRight,
+}
+
+/// Burned-in text overlay drawn on every encoded frame.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OverlayOptions {
+    /// Text template. `{name}` expands to the camera name, `%`-prefixed fields follow `strftime`.
+    #[serde(default = "default_overlay_format")]
+    pub format: String,
+    /// Corner of the frame the text is anchored to.
+    #[serde(default = "default_overlay_position")]
+    pub position: OverlayPosition,
+    /// Font size in pixels.
+    #[serde(default = "default_overlay_font_size")]
+    pub font_size: u32,
+    /// Optional TrueType font file. FFmpeg falls back to fontconfig when omitted.
+    #[serde(default)]
+    pub font_file: Option<String>,
+}
+
+/// Margin, in pixels, between the overlay text and the frame edge.
+const OVERLAY_MARGIN: u32 = 10;
+
+/// Builds the `drawtext` expansion that renders `format` relative to a wall-clock start time.
+///
+/// Frames fed through the filter must carry timestamps relative to the first frame of the
+/// recording, so that `start_epoch_seconds + pts` yields the wall-clock time of each frame.
+pub fn overlay_text(format: &str, camera_name: &str, start_epoch_seconds: f64) -> String {
+    // `strftime` treats `%` as a field marker, so a literal percent in the name must be doubled.
+    let expanded = format.replace("{name}", &camera_name.replace('%', "%%"));
+
+    let mut escaped = String::with_capacity(expanded.len());
+    for ch in expanded.chars() {
+        // Arguments of `%{...}` expansions are split on `:` and terminated by `}`.
+        if matches!(ch, '\\' | ':' | '}') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+
+    format!("%{{pts:localtime:{start_epoch_seconds:.3}:{escaped}}}")
+}
+
+/// Wall-clock time the burned-in timestamps count from.
+///
+/// `drawtext` renders `start + pts`, so once a gap is cut out of the input timestamps every later
+/// frame would lag real time by the gap. A rebase moves the start at the first frame presented at
+/// or after the rebased packet; frames still buffered in the decoder keep the old start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlayClock {
+    start_epoch_seconds: f64,
+    /// Seconds after the first frame where the pending rebase applies, and its wall-clock time.
+    pending: Option<(f64, f64)>,
+}
+
+impl OverlayClock {
+    /// Counts from `start_epoch_seconds` at the first frame.
+    pub fn new(start_epoch_seconds: f64) -> Self {
+        Self {
+            start_epoch_seconds,
+            pending: None,
+        }
+    }
+
+    /// Wall-clock time of the first frame as currently rendered.
+    pub fn start_epoch_seconds(&self) -> f64 {
+        self.start_epoch_seconds
+    }
+
+    /// Records that the frame presented `seconds` after the first one was captured at
+    /// `epoch_seconds`, after the timestamps were rebased there.
+    pub fn rebase(&mut self, seconds: f64, epoch_seconds: f64) {
+        self.pending = Some((seconds, epoch_seconds));
+    }
+
+    /// Returns the new start when the frame presented `seconds` after the first one is the first
+    /// to follow a rebase that moved it.
+    pub fn frame(&mut self, seconds: f64) -> Option<f64> {
+        let (at, epoch_seconds) = self.pending?;
+        if seconds < at {
+            return None;
+        }
+        self.pending = None;
+        let start = epoch_seconds - at;
+        // `drawtext` is given the start with millisecond precision.
+        if (start - self.start_epoch_seconds).abs() < 0.001 {
+            return None;
+        }
+        self.start_epoch_seconds = start;
+        Some(start)
+    }
+}
+
+/// Returns the option string for FFmpeg's `drawtext` filter.
+pub(crate) fn drawtext_args(
+    options: &OverlayOptions,
+    camera_name: &str,
+    start_epoch_seconds: f64,
+) -> String {
+    let text = overlay_text(&options.format, camera_name, start_epoch_seconds);
+    let (x, y) = match options.position {
+        OverlayPosition::TopLeft => (format!("{OVERLAY_MARGIN}"), format!("{OVERLAY_MARGIN}")),
+        OverlayPosition::TopRight => (
+            format!("w-tw-{OVERLAY_MARGIN}"),
+            format!("{OVERLAY_MARGIN}"),
+        ),
+        OverlayPosition::BottomLeft => (
+            format!("{OVERLAY_MARGIN}"),
+            format!("h-th-{OVERLAY_MARGIN}"),
+        ),
+        OverlayPosition::BottomRight => (
+            format!("w-tw-{OVERLAY_MARGIN}"),
+            format!("h-th-{OVERLAY_MARGIN}"),
+        ),
+    };
+
+    let mut args = format!(
+        "text={}:x={x}:y={y}:fontsize={}:fontcolor=white:box=1:boxcolor=black@0.5:boxborderw=4",
+        quote_option_value(&text),
+        options.font_size
+    );
+
+    if let Some(font_file) = &options.font_file {
+        args.push_str(":fontfile=");
+        args.push_str(&quote_option_value(font_file));
+    }
+
+    args
+}
+
+/// Quotes a filter option value so `:` and `\` survive FFmpeg's option parser untouched.
+fn quote_option_value(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn default_overlay_format() -> String {
+    "{name} %Y-%m-%d %H:%M:%S".to_string()
+}
+
+fn default_overlay_position() -> OverlayPosition {
+    OverlayPosition::TopLeft
+}
+
+fn default_overlay_font_size() -> u32 {
+    24
+}
@@ -0,0 +1,330 @@
+use ffmpeg_next::{color, ffi, format, frame};
+use serde::Deserialize;
+
+use super::RecorderError;
+
+/// Polygonal region of the frame that must never be stored in the clear.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrivacyMask {
+    /// Polygon vertices as normalized `[x, y]` pairs, where `[0, 0]` is top-left and `[1, 1]` bottom-right.
+    pub points: Vec<[f64; 2]>,
+    /// How the covered pixels are obscured.
+    #[serde(default)]
+    pub style: MaskStyle,
+}
+
+/// Obscuring technique applied to the pixels covered by a privacy mask.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum MaskStyle {
+    /// Paint the region with a solid RGB colour.
+    Fill {
+        #[serde(default)]
+        color: [u8; 3],
+    },
+    /// Replace the region with coarse blocks of the given size, in luma pixels.
+    Pixelate {
+        #[serde(default = "default_block_size")]
+        block_size: u32,
+    },
+}
+
+impl Default for MaskStyle {
+    fn default() -> Self {
+        MaskStyle::Fill { color: [0, 0, 0] }
+    }
+}
+
+/// Pixel coverage of a single mask polygon for a given frame size.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MaskBitmap {
+    left: u32,
+    top: u32,
+    width: u32,
+    height: u32,
+    covered: Vec<bool>,
+}
+
+impl MaskBitmap {
+    /// Rasterizes normalized polygon `points` onto a `frame_width` x `frame_height` pixel grid.
+    ///
+    /// A pixel is covered when its centre lies inside the polygon (even-odd rule).
+    pub fn rasterize(points: &[[f64; 2]], frame_width: u32, frame_height: u32) -> Self {
+        let empty = Self {
+            left: 0,
+            top: 0,
+            width: 0,
+            height: 0,
+            covered: Vec::new(),
+        };
+        if points.len() < 3 || frame_width == 0 || frame_height == 0 {
+            return empty;
+        }
+
+        let vertices: Vec<(f64, f64)> = points
+            .iter()
+            .map(|[x, y]| {
+                (
+                    x.clamp(0.0, 1.0) * f64::from(frame_width),
+                    y.clamp(0.0, 1.0) * f64::from(frame_height),
+                )
+            })
+            .collect();
+
+        let min_x = vertices.iter().map(|v| v.0).fold(f64::MAX, f64::min);
+        let max_x = vertices.iter().map(|v| v.0).fold(f64::MIN, f64::max);
+        let min_y = vertices.iter().map(|v| v.1).fold(f64::MAX, f64::min);
+        let max_y = vertices.iter().map(|v| v.1).fold(f64::MIN, f64::max);
+
+        let left = (min_x.floor() as u32).min(frame_width);
+        let top = (min_y.floor() as u32).min(frame_height);
+        let right = (max_x.ceil() as u32).min(frame_width);
+        let bottom = (max_y.ceil() as u32).min(frame_height);
+        if right <= left || bottom <= top {
+            return empty;
+        }
+
+        let width = right - left;
+        let height = bottom - top;
+        let mut covered = vec![false; (width * height) as usize];
+
+        for row in 0..height {
+            let cy = f64::from(top + row) + 0.5;
+            for column in 0..width {
+                let cx = f64::from(left + column) + 0.5;
+                covered[(row * width + column) as usize] = polygon_contains(&vertices, cx, cy);
+            }
+        }
+
+        Self {
+            left,
+            top,
+            width,
+            height,
+            covered,
+        }
+    }
+
+    /// Returns whether the pixel at (`x`, `y`) in frame coordinates is covered by the mask.
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        if x < self.left || y < self.top {
+            return false;
+        }
+        let (column, row) = (x - self.left, y - self.top);
+        column < self.width
+            && row < self.height
+            && self.covered[(row * self.width + column) as usize]
+    }
+
+    /// Number of covered pixels.
+    pub fn area(&self) -> usize {
+        self.covered.iter().filter(|covered| **covered).count()
+    }
+}
+
+/// Converts an RGB colour to 8-bit BT.601 Y'CbCr, in limited or full range.
+pub fn rgb_to_yuv(color: [u8; 3], full_range: bool) -> [u8; 3] {
+    let [r, g, b] = color.map(f64::from);
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    let u = -0.168_736 * r - 0.331_264 * g + 0.5 * b;
+    let v = 0.5 * r - 0.418_688 * g - 0.081_312 * b;
+
+    let (y, u, v) = if full_range {
+        (y, u + 128.0, v + 128.0)
+    } else {
+        (
+            16.0 + y * 219.0 / 255.0,
+            128.0 + u * 224.0 / 255.0,
+            128.0 + v * 224.0 / 255.0,
+        )
+    };
+
+    [y, u, v].map(|value| value.round().clamp(0.0, 255.0) as u8)
+}
+
+/// Applies configured privacy masks to decoded frames in place.
+pub(crate) struct PrivacyMasker {
+    masks: Vec<PrivacyMask>,
+    bitmaps: Vec<MaskBitmap>,
+    frame_size: (u32, u32),
+}
+
+impl PrivacyMasker {
+    pub(crate) fn new(masks: Vec<PrivacyMask>) -> Self {
+        Self {
+            masks,
+            bitmaps: Vec::new(),
+            frame_size: (0, 0),
+        }
+    }
+
+    pub(crate) fn apply(&mut self, frame: &mut frame::Video) -> Result<(), RecorderError> {
+        let (chroma_w, chroma_h) = chroma_shift(frame.format())
+            .ok_or(RecorderError::UnsupportedPixelFormat(frame.format()))?;
+        let full_range = matches!(
+            frame.format(),
+            format::Pixel::YUVJ420P
+                | format::Pixel::YUVJ422P
+                | format::Pixel::YUVJ444P
+                | format::Pixel::YUVJ440P
+        ) || frame.color_range() == color::Range::JPEG;
+
+        let size = (frame.width(), frame.height());
+        if size != self.frame_size {
+            self.bitmaps = self
+                .masks
+                .iter()
+                .map(|mask| MaskBitmap::rasterize(&mask.points, size.0, size.1))
+                .collect();
+            self.frame_size = size;
+        }
+
+        // Decoded frames may share buffers with the decoder's reference pictures.
+        let result = unsafe { ffi::av_frame_make_writable(frame.as_mut_ptr()) };
+        if result < 0 {
+            return Err(RecorderError::Ffmpeg(result.into()));
+        }
+
+        for (mask, bitmap) in self.masks.iter().zip(&self.bitmaps) {
+            if bitmap.area() == 0 {
+                continue;
+            }
+
+            for plane in 0..frame.planes().min(3) {
+                let (shift_w, shift_h) = if plane == 0 {
+                    (0, 0)
+                } else {
+                    (chroma_w, chroma_h)
+                };
+                let mut target = PlaneView {
+                    width: frame.plane_width(plane),
+                    height: frame.plane_height(plane),
+                    stride: frame.stride(plane),
+                    shift_w,
+                    shift_h,
+                    data: frame.data_mut(plane),
+                };
+
+                match mask.style {
+                    MaskStyle::Fill { color } => {
+                        target.fill(bitmap, rgb_to_yuv(color, full_range)[plane]);
+                    }
+                    MaskStyle::Pixelate { block_size } => target.pixelate(bitmap, block_size),
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Mutable view of one image plane together with its subsampling relative to luma.
+struct PlaneView<'a> {
+    width: u32,
+    height: u32,
+    stride: usize,
+    shift_w: u8,
+    shift_h: u8,
+    data: &'a mut [u8],
+}
+
+impl PlaneView<'_> {
+    fn covered(&self, bitmap: &MaskBitmap, x: u32, y: u32) -> bool {
+        bitmap.contains(x << self.shift_w, y << self.shift_h)
+    }
+
+    /// Plane-space bounding box of the bitmap, clamped to the plane.
+    fn bounds(&self, bitmap: &MaskBitmap) -> (u32, u32, u32, u32) {
+        let left = bitmap.left >> self.shift_w;
+        let top = bitmap.top >> self.shift_h;
+        let right = (bitmap.left + bitmap.width)
+            .div_ceil(1 << self.shift_w)
+            .min(self.width);
+        let bottom = (bitmap.top + bitmap.height)
+            .div_ceil(1 << self.shift_h)
+            .min(self.height);
+        (left, top, right, bottom)
+    }
+
+    fn fill(&mut self, bitmap: &MaskBitmap, value: u8) {
+        let (left, top, right, bottom) = self.bounds(bitmap);
+        for y in top..bottom {
+            for x in left..right {
+                if self.covered(bitmap, x, y) {
+                    self.data[y as usize * self.stride + x as usize] = value;
+                }
+            }
+        }
+    }
+
+    fn pixelate(&mut self, bitmap: &MaskBitmap, block_size: u32) {
+        let block_w = (block_size >> self.shift_w).max(1);
+        let block_h = (block_size >> self.shift_h).max(1);
+        let (left, top, right, bottom) = self.bounds(bitmap);
+
+        // Align blocks to a plane-wide grid so adjacent masks pixelate consistently.
+        let mut block_y = top - top % block_h;
+        while block_y < bottom {
+            let mut block_x = left - left % block_w;
+            while block_x < right {
+                let x_end = (block_x + block_w).min(self.width);
+                let y_end = (block_y + block_h).min(self.height);
+
+                let mut sum = 0u64;
+                let mut count = 0u64;
+                for y in block_y..y_end {
+                    for x in block_x..x_end {
+                        sum += u64::from(self.data[y as usize * self.stride + x as usize]);
+                        count += 1;
+                    }
+                }
+
+                if count > 0 {
+                    let average = (sum / count) as u8;
+                    for y in block_y..y_end {
+                        for x in block_x..x_end {
+                            if self.covered(bitmap, x, y) {
+                                self.data[y as usize * self.stride + x as usize] = average;
+                            }
+                        }
+                    }
+                }
+
+                block_x += block_w;
+            }
+            block_y += block_h;
+        }
+    }
+}
+
+/// Chroma subsampling shifts for the 8-bit planar formats the masker can edit directly.
+fn chroma_shift(pixel: format::Pixel) -> Option<(u8, u8)> {
+    match pixel {
+        format::Pixel::YUV420P | format::Pixel::YUVJ420P => Some((1, 1)),
+        format::Pixel::YUV422P | format::Pixel::YUVJ422P => Some((1, 0)),
+        format::Pixel::YUV444P | format::Pixel::YUVJ444P => Some((0, 0)),
+        format::Pixel::YUV440P | format::Pixel::YUVJ440P => Some((0, 1)),
+        format::Pixel::YUV411P => Some((2, 0)),
+        format::Pixel::GRAY8 => Some((0, 0)),
+        _ => None,
+    }
+}
+
+/// Even-odd point-in-polygon test in pixel coordinates.
+fn polygon_contains(vertices: &[(f64, f64)], x: f64, y: f64) -> bool {
+    let mut inside = false;
+    let mut previous = vertices[vertices.len() - 1];
+    for &current in vertices {
+        let (x1, y1) = previous;
+        let (x2, y2) = current;
+        if (y1 > y) != (y2 > y) && x < (x2 - x1) * (y - y1) / (y2 - y1) + x1 {
+            inside = !inside;
+        }
+        previous = current;
+    }
+    inside
+}
+
+fn default_block_size() -> u32 {
+    16
+}
@@ -0,0 +1,49 @@
+use serde::Deserialize;
+
+use super::{FrameRateMode, OverlayOptions, PrivacyMask, SnapshotOptions};
+
+/// What happens when the camera starts sending a different resolution or pixel format mid-stream.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolutionChange {
+    /// Scale new frames back to the resolution the recording started with.
+    #[default]
+    Rescale,
+    /// Re-open the encoders at the new resolution and mark a discontinuity. Only MPEG-TS outputs
+    /// can take the new parameters.
+    Reinitialize,
+}
+
+/// Per-recording processing applied to decoded video frames before they are re-encoded.
+#[derive(Debug, Clone, Default)]
+pub struct VideoProcessing {
+    /// Human-readable camera name, substituted for `{name}` in overlay text.
+    pub camera_name: String,
+    /// Optional burned-in camera name and wall-clock timestamp.
+    pub overlay: Option<OverlayOptions>,
+    /// Regions blanked or pixelated on every frame before encoding.
+    pub privacy_masks: Vec<PrivacyMask>,
+    /// Optional frame decimation or constant-rate conversion.
+    pub frame_rate: Option<FrameRateMode>,
+    /// Handling of mid-stream resolution or pixel format changes.
+    pub resolution_change: ResolutionChange,
+    /// Optional JPEG snapshots of the processed frames.
+    pub snapshot: Option<SnapshotOptions>,
+}
+
+impl VideoProcessing {
+    /// Names the first configured feature that needs decoded frames, if any.
+    ///
+    /// Such features cannot be combined with video stream copy.
+    pub fn transcode_requirement(&self) -> Option<&'static str> {
+        if !self.privacy_masks.is_empty() {
+            Some("privacy masking")
+        } else if self.overlay.is_some() {
+            Some("the burned-in overlay")
+        } else if self.frame_rate.is_some() {
+            Some("frame-rate conversion")
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::fmt::Write as _;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Maps output media time, in seconds since the first packet, to wall-clock time.
+///
+/// The mapping is piecewise: every anchor pins one media instant to a wall-clock instant and
+/// holds until the next anchor, so a discontinuity only needs a fresh anchor to stay accurate.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WallClock {
+    anchors: Vec<(f64, f64)>,
+}
+
+impl WallClock {
+    /// Creates a clock without anchors.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether at least one anchor has been recorded.
+    pub fn is_anchored(&self) -> bool {
+        !self.anchors.is_empty()
+    }
+
+    /// Pins `media_seconds` to `epoch_seconds`, replacing anchors at or after that media time.
+    pub fn anchor(&mut self, media_seconds: f64, epoch_seconds: f64) {
+        self.anchors.retain(|&(media, _)| media < media_seconds);
+        self.anchors.push((media_seconds, epoch_seconds));
+    }
+
+    /// Wall-clock time of `media_seconds`, in seconds since the Unix epoch.
+    pub fn epoch_at(&self, media_seconds: f64) -> Option<f64> {
+        let &(media, epoch) = self
+            .anchors
+            .iter()
+            .rev()
+            .find(|&&(media, _)| media <= media_seconds)
+            .or(self.anchors.first())?;
+        Some(epoch + (media_seconds - media))
+    }
+}
+
+/// Current local time in seconds since the Unix epoch.
+pub fn now_epoch_seconds() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs_f64())
+        .unwrap_or_default()
+}
+
+/// Formats `epoch_seconds` as an `EXT-X-PROGRAM-DATE-TIME` value, e.g. `2024-05-01T12:00:00.000Z`.
+pub fn format_program_date_time(epoch_seconds: f64) -> String {
+    let millis = (epoch_seconds * 1000.0).round() as i64;
+    let (days, millis_of_day) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000));
+    let (year, month, day) = civil_from_days(days);
+    let seconds_of_day = millis_of_day / 1000;
+
+    let mut formatted = String::with_capacity(24);
+    let _ = write!(
+        formatted,
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        seconds_of_day / 3600,
+        seconds_of_day / 60 % 60,
+        seconds_of_day % 60,
+        millis_of_day % 1000
+    );
+    formatted
+}
+
+/// Parses an ISO 8601 date-time as written by FFmpeg (`+0200` offsets) or by this crate (`Z`).
+pub fn parse_program_date_time(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let (date, time) = value.split_once('T')?;
+
+    let mut date_fields = date.splitn(3, '-');
+    let year: i64 = date_fields.next()?.parse().ok()?;
+    let month: u32 = date_fields.next()?.parse().ok()?;
+    let day: u32 = date_fields.next()?.parse().ok()?;
+
+    let (clock, offset_seconds) = if let Some(clock) = time.strip_suffix('Z') {
+        (clock, 0)
+    } else {
+        let sign_at = time.rfind(['+', '-'])?;
+        let (clock, offset) = time.split_at(sign_at);
+        let sign = if offset.starts_with('-') { -1 } else { 1 };
+        let digits: String = offset[1..].chars().filter(char::is_ascii_digit).collect();
+        if digits.len() != 4 {
+            return None;
+        }
+        let hours: i64 = digits[..2].parse().ok()?;
+        let minutes: i64 = digits[2..].parse().ok()?;
+        (clock, sign * (hours * 3600 + minutes * 60))
+    };
+
+    let mut clock_fields = clock.splitn(3, ':');
+    let hours: i64 = clock_fields.next()?.parse().ok()?;
+    let minutes: i64 = clock_fields.next()?.parse().ok()?;
+    let seconds: f64 = clock_fields.next()?.parse().ok()?;
+
+    let days = days_from_civil(year, month, day);
+    Some((days * 86_400 + hours * 3600 + minutes * 60 - offset_seconds) as f64 + seconds)
+}
+
+/// Replaces every `EXT-X-PROGRAM-DATE-TIME` written by the `hls` muxer with the time from `clock`.
+///
+/// The muxer dates segments from the local clock at header time plus accumulated segment
+/// durations, so `muxer_origin`, the date it gave the first segment, converts each written date
+/// back into media time.
+pub fn rewrite_program_date_times(playlist: &str, muxer_origin: f64, clock: &WallClock) -> String {
+    let mut rewritten = String::with_capacity(playlist.len());
+    for line in playlist.lines() {
+        let corrected = line
+            .strip_prefix("#EXT-X-PROGRAM-DATE-TIME:")
+            .and_then(parse_program_date_time)
+            .and_then(|written| clock.epoch_at(written - muxer_origin));
+        match corrected {
+            Some(epoch) => {
+                rewritten.push_str("#EXT-X-PROGRAM-DATE-TIME:");
+                rewritten.push_str(&format_program_date_time(epoch));
+            }
+            None => rewritten.push_str(line),
+        }
+        rewritten.push('\n');
+    }
+    rewritten
+}
+
+/// First `EXT-X-PROGRAM-DATE-TIME` of a playlist, in seconds since the Unix epoch.
+pub fn first_program_date_time(playlist: &str) -> Option<f64> {
+    playlist
+        .lines()
+        .find_map(|line| line.strip_prefix("#EXT-X-PROGRAM-DATE-TIME:"))
+        .and_then(parse_program_date_time)
+}
+
+/// Gregorian date of a day count relative to 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 {
+        shifted_month + 3
+    } else {
+        shifted_month - 9
+    } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Day count relative to 1970-01-01 of a Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = i64::from((month + 9) % 12);
+    let day_of_year = (153 * shifted_month + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
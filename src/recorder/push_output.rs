@@ -0,0 +1,370 @@
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use ffmpeg_next::{Dictionary, Packet, Rational, codec, encoder, ffi, format, media};
+use serde::Deserialize;
+
+use super::RecorderError;
+use super::master_playlist::annexb_nal_units;
+
+/// Packets queued per push target before the recorder starts dropping them.
+const QUEUE_PACKETS: usize = 1024;
+/// Timeout for connecting and for every network write, in microseconds.
+const IO_TIMEOUT_MICROSECONDS: u64 = 5_000_000;
+
+/// Network protocol of a push target, derived from its URL scheme.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PushProtocol {
+    /// RTMP or RTMPS, carrying FLV.
+    Rtmp,
+    /// SRT in caller mode, carrying MPEG-TS.
+    Srt,
+    /// Plain UDP, unicast or multicast, carrying MPEG-TS.
+    Udp,
+}
+
+impl PushProtocol {
+    /// Protocol of `url`, or `None` for schemes that cannot be pushed to.
+    pub fn from_url(url: &str) -> Option<Self> {
+        let (scheme, _) = url.split_once("://")?;
+        match scheme.to_ascii_lowercase().as_str() {
+            "rtmp" | "rtmps" => Some(Self::Rtmp),
+            "srt" => Some(Self::Srt),
+            "udp" => Some(Self::Udp),
+            _ => None,
+        }
+    }
+
+    /// FFmpeg muxer producing the container the protocol carries.
+    pub fn muxer(self) -> &'static str {
+        match self {
+            Self::Rtmp => "flv",
+            Self::Srt | Self::Udp => "mpegts",
+        }
+    }
+}
+
+/// Live restreaming target fed with the same packets as the recording's HLS output.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct PushTarget {
+    /// Destination, e.g. `rtmp://live.example.com/app/key`, `srt://host:9000` or `udp://239.0.0.1:5000`.
+    pub url: String,
+    /// Delay before the first reconnect attempt, in seconds; doubles after every failed attempt.
+    #[serde(default = "default_reconnect_delay")]
+    pub reconnect_delay_seconds: u64,
+    /// Upper bound for the reconnect delay, in seconds.
+    #[serde(default = "default_max_reconnect_delay")]
+    pub max_reconnect_delay_seconds: u64,
+}
+
+fn default_reconnect_delay() -> u64 {
+    1
+}
+
+fn default_max_reconnect_delay() -> u64 {
+    30
+}
+
+impl PushTarget {
+    /// Protocol of the target URL.
+    pub fn protocol(&self) -> Option<PushProtocol> {
+        PushProtocol::from_url(&self.url)
+    }
+
+    /// Scheme and host of the URL, without credentials or the path that usually holds a stream key.
+    pub fn display_name(&self) -> String {
+        let (scheme, rest) = self.url.split_once("://").unwrap_or(("", &self.url));
+        let authority = rest.split(['/', '?']).next().unwrap_or(rest);
+        let host = authority
+            .rsplit_once('@')
+            .map_or(authority, |(_, host)| host);
+        format!("{scheme}://{host}")
+    }
+
+    /// Delay before reconnect attempt `attempt`, counted from zero.
+    pub fn reconnect_delay(&self, attempt: u32) -> Duration {
+        let factor = 1u64.checked_shl(attempt).unwrap_or(u64::MAX);
+        let seconds = self
+            .reconnect_delay_seconds
+            .saturating_mul(factor)
+            .min(self.max_reconnect_delay_seconds);
+        Duration::from_secs(seconds)
+    }
+}
+
+/// Annex B extradata holding the parameter sets found in an H.264 or H.265 keyframe.
+///
+/// FLV carries parameter sets only in its header, so encoders that repeat them in-band, as
+/// MPEG-TS outputs do, get their header built from the first keyframe sent to an RTMP target.
+pub fn parameter_set_extradata(codec: codec::Id, keyframe: &[u8]) -> Option<Vec<u8>> {
+    let is_parameter_set = |nal: &[u8]| match codec {
+        codec::Id::H264 => nal
+            .first()
+            .is_some_and(|header| matches!(header & 0x1f, 7 | 8)),
+        codec::Id::HEVC => nal
+            .first()
+            .is_some_and(|header| matches!((header >> 1) & 0x3f, 32..=34)),
+        _ => false,
+    };
+
+    let mut extradata = Vec::new();
+    for nal in annexb_nal_units(keyframe).filter(|nal| is_parameter_set(nal)) {
+        extradata.extend_from_slice(&[0, 0, 0, 1]);
+        extradata.extend_from_slice(nal);
+    }
+    (!extradata.is_empty()).then_some(extradata)
+}
+
+/// Forwards an output's packets to one push target from a background thread.
+///
+/// Packets are queued without blocking, so a slow or unreachable target never holds up the
+/// recording; the worker reconnects on its own with exponential backoff.
+pub struct PushOutput {
+    name: String,
+    sender: Option<SyncSender<Packet>>,
+    worker: Option<JoinHandle<()>>,
+    video_streams: Vec<bool>,
+    /// Set after the queue overflowed; packets are skipped until the next clean starting point.
+    resync: bool,
+    dropped: u64,
+}
+
+/// Source stream of a push target: codec parameters and the time base of its packets.
+struct PushStream {
+    parameters: codec::Parameters,
+    time_base: Rational,
+}
+
+impl PushOutput {
+    /// Starts forwarding the streams of `octx`, whose header has already been written, to `target`.
+    pub fn start(
+        target: &PushTarget,
+        octx: &format::context::Output,
+    ) -> Result<Self, RecorderError> {
+        let name = target.display_name();
+        let protocol = target.protocol().ok_or_else(|| {
+            RecorderError::InvalidOutput(format!("unsupported push target {name}"))
+        })?;
+
+        let streams: Vec<PushStream> = octx
+            .streams()
+            .map(|stream| PushStream {
+                parameters: stream.parameters().clone(),
+                time_base: stream.time_base(),
+            })
+            .collect();
+        let video_streams: Vec<bool> = streams
+            .iter()
+            .map(|stream| stream.parameters.medium() == media::Type::Video)
+            .collect();
+
+        let (sender, receiver) = mpsc::sync_channel(QUEUE_PACKETS);
+        let worker = PushWorker {
+            name: name.clone(),
+            target: target.clone(),
+            protocol,
+            streams,
+            video_streams: video_streams.clone(),
+        };
+        let worker = thread::Builder::new()
+            .name(format!("push {name}"))
+            .spawn(move || worker.run(receiver))?;
+
+        Ok(Self {
+            name,
+            sender: Some(sender),
+            worker: Some(worker),
+            video_streams,
+            resync: false,
+            dropped: 0,
+        })
+    }
+
+    /// Queues a packet already rescaled to its output stream's time base. Never blocks.
+    pub fn send(&mut self, packet: &Packet) {
+        let Some(sender) = self.sender.as_ref() else {
+            return;
+        };
+        if self.resync && !starts_clean(packet, &self.video_streams) {
+            self.dropped += 1;
+            return;
+        }
+        match sender.try_send(packet.clone()) {
+            Ok(()) => self.resync = false,
+            Err(TrySendError::Full(_)) => {
+                if !self.resync {
+                    eprintln!(
+                        "Warning: push to {} is falling behind; dropping packets until the next keyframe",
+                        self.name
+                    );
+                }
+                self.resync = true;
+                self.dropped += 1;
+            }
+            Err(TrySendError::Disconnected(_)) => self.sender = None,
+        }
+    }
+
+    /// Lets the worker drain its queue, then waits for it to close the connection.
+    pub fn finish(&mut self) {
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        if self.dropped > 0 {
+            println!(
+                "Push to {}: dropped {} packets while falling behind",
+                self.name, self.dropped
+            );
+        }
+    }
+}
+
+/// Whether a receiver can start decoding at `packet`: a video keyframe, or any packet when the
+/// output carries no video.
+fn starts_clean(packet: &Packet, video_streams: &[bool]) -> bool {
+    let has_video = video_streams.contains(&true);
+    let is_video = video_streams.get(packet.stream()).copied().unwrap_or(false);
+    packet.is_key() && (is_video || !has_video)
+}
+
+/// Connection state of one push target, owned by its background thread.
+struct PushWorker {
+    name: String,
+    target: PushTarget,
+    protocol: PushProtocol,
+    streams: Vec<PushStream>,
+    video_streams: Vec<bool>,
+}
+
+impl PushWorker {
+    fn run(self, packets: Receiver<Packet>) {
+        let mut connection = None;
+        let mut failures = 0;
+        let mut retry_at = Instant::now();
+
+        for packet in packets {
+            if connection.is_none() {
+                // Connect on a keyframe so receivers start with a decodable picture.
+                if Instant::now() < retry_at || !starts_clean(&packet, &self.video_streams) {
+                    continue;
+                }
+                match self.connect(&packet) {
+                    Ok(opened) => {
+                        println!("Push to {}: connected", self.name);
+                        failures = 0;
+                        connection = Some(opened);
+                    }
+                    Err(error) => {
+                        let delay = self.target.reconnect_delay(failures);
+                        failures = failures.saturating_add(1);
+                        eprintln!(
+                            "Push to {} failed: {error}; retrying in {}s",
+                            self.name,
+                            delay.as_secs()
+                        );
+                        retry_at = Instant::now() + delay;
+                        continue;
+                    }
+                }
+            }
+
+            let Some(active) = connection.as_mut() else {
+                continue;
+            };
+            if let Err(error) = active.write(packet) {
+                let delay = self.target.reconnect_delay(0);
+                failures = 1;
+                eprintln!(
+                    "Push to {} lost: {error}; reconnecting in {}s",
+                    self.name,
+                    delay.as_secs()
+                );
+                connection = None;
+                retry_at = Instant::now() + delay;
+            }
+        }
+
+        if let Some(mut active) = connection {
+            let _ = active.octx.write_trailer();
+        }
+    }
+
+    /// Opens the target and writes its header, taking missing parameter sets from `first`.
+    fn connect(&self, first: &Packet) -> Result<PushConnection, RecorderError> {
+        let mut options = Dictionary::new();
+        options.set("rw_timeout", &IO_TIMEOUT_MICROSECONDS.to_string());
+        let mut octx = format::output_as_with(&self.target.url, self.protocol.muxer(), options)?;
+
+        for (index, stream) in self.streams.iter().enumerate() {
+            let mut parameters = stream.parameters.clone();
+            if self.protocol == PushProtocol::Rtmp
+                && self.video_streams[index]
+                && index == first.stream()
+            {
+                fill_missing_extradata(&mut parameters, first);
+            }
+            let mut ost = octx.add_stream(encoder::find(codec::Id::None))?;
+            ost.set_parameters(parameters);
+            ost.set_time_base(stream.time_base);
+            unsafe {
+                (*ost.parameters().as_mut_ptr()).codec_tag = 0;
+            }
+        }
+        octx.write_header()?;
+
+        let time_bases = self
+            .streams
+            .iter()
+            .zip(octx.streams())
+            .map(|(source, ost)| (source.time_base, ost.time_base()))
+            .collect();
+        Ok(PushConnection { octx, time_bases })
+    }
+}
+
+/// Sets the extradata of a video stream that has none from the parameter sets in `keyframe`.
+fn fill_missing_extradata(parameters: &mut codec::Parameters, keyframe: &Packet) {
+    let Some(extradata) = keyframe
+        .data()
+        .and_then(|data| parameter_set_extradata(parameters.id(), data))
+    else {
+        return;
+    };
+    unsafe {
+        let par = parameters.as_mut_ptr();
+        if !(*par).extradata.is_null() && (*par).extradata_size > 0 {
+            return;
+        }
+        let padding = ffi::AV_INPUT_BUFFER_PADDING_SIZE as usize;
+        let buffer = ffi::av_mallocz(extradata.len() + padding) as *mut u8;
+        if buffer.is_null() {
+            return;
+        }
+        std::ptr::copy_nonoverlapping(extradata.as_ptr(), buffer, extradata.len());
+        ffi::av_freep(&mut (*par).extradata as *mut *mut u8 as *mut std::ffi::c_void);
+        (*par).extradata = buffer;
+        (*par).extradata_size = extradata.len() as i32;
+    }
+}
+
+/// An open push muxer with the source and target time base of every stream.
+struct PushConnection {
+    octx: format::context::Output,
+    time_bases: Vec<(Rational, Rational)>,
+}
+
+impl PushConnection {
+    fn write(&mut self, mut packet: Packet) -> Result<(), RecorderError> {
+        let stream = packet.stream();
+        let (source, target) = *self
+            .time_bases
+            .get(stream)
+            .ok_or(RecorderError::InvalidStreamMapping(stream))?;
+        packet.rescale_ts(source, target);
+        packet.set_position(-1);
+        packet.write_interleaved(&mut self.octx)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,436 @@
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::ll_hls::write_atomically;
+use super::{
+    PlaylistEntry, RecorderError, SegmentRecord, media_playlist_entries, read_segment_index,
+    segment_file_name, segment_index_path, variant_uri,
+};
+
+/// Size of an MPEG-TS packet.
+const TS_PACKET_SIZE: usize = 188;
+/// PES timestamps count in 90 kHz units.
+const PES_CLOCK_RATE: f64 = 90_000.0;
+
+/// What startup recovery did to the output of an interrupted recording.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Recovery {
+    /// Media sequence number the resumed recording continues with.
+    pub next_sequence: u64,
+    /// Segments listed in the repaired playlist.
+    pub segments: usize,
+    /// Segment files cut back to their last complete packet or fragment.
+    pub repaired: Vec<PathBuf>,
+    /// Segment files that were missing, unreadable, or could not be listed; unlisted ones are
+    /// kept with a `.partial` suffix.
+    pub dropped: Vec<PathBuf>,
+}
+
+/// State of a segment file after validation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentRepair {
+    /// The file ends on a packet or fragment boundary.
+    Intact,
+    /// A torn write was cut off; the rest of the file is playable.
+    Truncated,
+    /// Missing, or nothing playable survived.
+    Broken,
+}
+
+/// Repairs the playlist and segments an interrupted recording left behind, so the next recording
+/// continues them instead of starting over.
+///
+/// Returns `None` when there is no playlist or it was finished with `EXT-X-ENDLIST`. Only the
+/// tail is checked: trailing listed segments that are missing or broken are dropped, the last one
+/// is cut back to its last complete packet or fragment, and the segment the muxer was writing
+/// when it died is repaired and listed (MPEG-TS) or set aside (fMP4, whose duration is unknown).
+pub fn recover_playlist(
+    playlist_path: &Path,
+    segment_template: &str,
+) -> Result<Option<Recovery>, RecorderError> {
+    let playlist = match fs::read_to_string(playlist_path) {
+        Ok(playlist) => playlist,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+    if playlist.lines().any(|line| line.trim() == "#EXT-X-ENDLIST") {
+        return Ok(None);
+    }
+
+    let directory = playlist_path.parent().unwrap_or_else(|| Path::new(""));
+    let (media_sequence, entries) = media_playlist_entries(&playlist);
+    // Encrypted segments cannot be parsed, only checked for whole cipher blocks.
+    let encrypted = playlist.contains("#EXT-X-KEY:METHOD=AES-128");
+    let check = |path: &Path| {
+        if encrypted {
+            check_encrypted_segment(path)
+        } else {
+            repair_segment(path)
+        }
+    };
+    let mut recovery = Recovery::default();
+
+    let mut kept = entries.len();
+    while kept > 0 {
+        let path = directory.join(&entries[kept - 1].uri);
+        match check(&path)? {
+            SegmentRepair::Intact => break,
+            SegmentRepair::Truncated => {
+                recovery.repaired.push(path);
+                break;
+            }
+            SegmentRepair::Broken => {
+                if path.exists() {
+                    fs::remove_file(&path)?;
+                }
+                recovery.dropped.push(path);
+                kept -= 1;
+            }
+        }
+    }
+
+    let mut lines = listed_lines(&playlist, kept);
+    let mut listed = kept;
+    let mut orphan_entry = None;
+
+    // The segment in progress when the recording died is not listed yet. It is named after the
+    // last listed one, which also carries the session of a session-named recording.
+    let orphan_sequence = media_sequence + entries.len() as u64;
+    let orphan = entries
+        .last()
+        .and_then(|last| next_segment_uri(&last.uri, orphan_sequence - 1))
+        .map(|uri| directory.join(uri))
+        .unwrap_or_else(|| PathBuf::from(segment_file_name(segment_template, orphan_sequence)));
+    if orphan.exists() {
+        let repair = check(&orphan)?;
+        let duration = (repair != SegmentRepair::Broken && kept == entries.len() && !encrypted)
+            .then(|| ts_duration(&orphan))
+            .transpose()?
+            .flatten();
+        match duration {
+            Some(duration) => {
+                if repair == SegmentRepair::Truncated {
+                    recovery.repaired.push(orphan.clone());
+                }
+                let uri = variant_uri(playlist_path, &orphan);
+                lines.push(format!("#EXTINF:{duration:.6},"));
+                lines.push(uri.clone());
+                listed += 1;
+                orphan_entry = Some(PlaylistEntry {
+                    uri,
+                    duration,
+                    discontinuity: false,
+                });
+            }
+            None if repair == SegmentRepair::Broken => {
+                fs::remove_file(&orphan)?;
+                recovery.dropped.push(orphan);
+            }
+            None => {
+                let mut partial = orphan.clone().into_os_string();
+                partial.push(".partial");
+                fs::rename(&orphan, &partial)?;
+                recovery.dropped.push(orphan);
+            }
+        }
+    }
+
+    let mut contents = lines.join("\n");
+    contents.push('\n');
+    write_atomically(playlist_path, contents.as_bytes())?;
+    recovery.segments = listed;
+    recovery.next_sequence = media_sequence + listed as u64;
+    rewrite_segment_index(playlist_path, &recovery, orphan_entry)?;
+
+    Ok(Some(recovery))
+}
+
+/// Reopens a playlist finished with `EXT-X-ENDLIST` so an appending recording can extend it.
+///
+/// The tag is removed and the media sequence number following the last listed segment returned;
+/// `None` when there is no playlist or it was never finished.
+pub fn reopen_playlist(playlist_path: &Path) -> Result<Option<u64>, RecorderError> {
+    let playlist = match fs::read_to_string(playlist_path) {
+        Ok(playlist) => playlist,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error.into()),
+    };
+    if !playlist.lines().any(|line| line.trim() == "#EXT-X-ENDLIST") {
+        return Ok(None);
+    }
+
+    let (media_sequence, entries) = media_playlist_entries(&playlist);
+    let contents: String = playlist
+        .lines()
+        .filter(|line| line.trim() != "#EXT-X-ENDLIST")
+        .flat_map(|line| [line, "\n"])
+        .collect();
+    write_atomically(playlist_path, contents.as_bytes())?;
+    Ok(Some(media_sequence + entries.len() as u64))
+}
+
+/// Name of the segment following `uri`, which is numbered `sequence`.
+///
+/// The last run of digits in the file stem that spells `sequence` is replaced by the next
+/// number, keeping its zero padding; `None` when the name holds no such run.
+pub fn next_segment_uri(uri: &str, sequence: u64) -> Option<String> {
+    let name_start = uri.rfind('/').map_or(0, |slash| slash + 1);
+    let stem_end = uri[name_start..]
+        .rfind('.')
+        .map_or(uri.len(), |dot| name_start + dot);
+    let bytes = uri.as_bytes();
+    let mut end = stem_end;
+    while end > name_start {
+        if !bytes[end - 1].is_ascii_digit() {
+            end -= 1;
+            continue;
+        }
+        let mut start = end;
+        while start > name_start && bytes[start - 1].is_ascii_digit() {
+            start -= 1;
+        }
+        if uri[start..end].parse::<u64>().ok() == Some(sequence) {
+            return Some(format!(
+                "{}{:0width$}{}",
+                &uri[..start],
+                sequence + 1,
+                &uri[end..],
+                width = end - start
+            ));
+        }
+        end = start;
+    }
+    None
+}
+
+/// Header lines of `playlist` followed by its first `count` segments with their tags.
+fn listed_lines(playlist: &str, count: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut pending = Vec::new();
+    let mut listed = 0;
+    let mut in_segment = false;
+    for line in playlist
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        if listed == count && (in_segment || line.starts_with("#EXTINF:")) {
+            break;
+        }
+        if line.starts_with("#EXTINF:") {
+            in_segment = true;
+        }
+        pending.push(line.to_string());
+        if !line.starts_with('#') && in_segment {
+            lines.append(&mut pending);
+            listed += 1;
+            in_segment = false;
+        } else if listed == 0 && !in_segment && !is_segment_tag(line) {
+            lines.append(&mut pending);
+        }
+    }
+    // Keys and maps announced after the last kept segment still apply to the next ones.
+    lines.extend(
+        pending
+            .into_iter()
+            .filter(|line| line.starts_with("#EXT-X-KEY:") || line.starts_with("#EXT-X-MAP:")),
+    );
+    lines
+}
+
+/// Tags that describe the segment following them rather than the whole playlist.
+fn is_segment_tag(line: &str) -> bool {
+    line.starts_with("#EXTINF:")
+        || line.starts_with("#EXT-X-PROGRAM-DATE-TIME:")
+        || line == "#EXT-X-DISCONTINUITY"
+        || line.starts_with("#EXT-X-BYTERANGE:")
+}
+
+/// Brings the wall-clock index next to the playlist in line with the repaired playlist: dropped
+/// segments are forgotten and a listed orphan is dated right after the last indexed segment.
+fn rewrite_segment_index(
+    playlist_path: &Path,
+    recovery: &Recovery,
+    orphan: Option<PlaylistEntry>,
+) -> Result<(), RecorderError> {
+    let index_path = segment_index_path(playlist_path);
+    if (recovery.dropped.is_empty() && orphan.is_none()) || !index_path.exists() {
+        return Ok(());
+    }
+    let directory = playlist_path.parent().unwrap_or_else(|| Path::new(""));
+    let mut records: Vec<SegmentRecord> = read_segment_index(&index_path)?
+        .into_iter()
+        .filter(|record| !recovery.dropped.contains(&directory.join(&record.uri)))
+        .collect();
+    if let (Some(orphan), Some(last)) = (orphan, records.last()) {
+        records.push(SegmentRecord {
+            uri: orphan.uri,
+            sequence: recovery.next_sequence - 1,
+            start: last.end(),
+            duration: orphan.duration,
+            discontinuity: false,
+        });
+    }
+
+    let mut contents = String::new();
+    for record in &records {
+        contents.push_str(&serde_json::to_string(record).map_err(io::Error::from)?);
+        contents.push('\n');
+    }
+    write_atomically(&index_path, contents.as_bytes())
+}
+
+/// Splits a media playlist before the tags of the first segment numbered `sequence` or later,
+/// separating the segments of an earlier recording from those written since.
+pub fn split_playlist(playlist: &str, sequence: u64) -> (&str, &str) {
+    let (media_sequence, _) = media_playlist_entries(playlist);
+    let mut current = media_sequence;
+    let mut block_start = None;
+    let mut offset = 0;
+    for line in playlist.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if block_start.is_none() && is_segment_tag(trimmed) {
+            block_start = Some(offset);
+        }
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            if current >= sequence {
+                return playlist.split_at(block_start.unwrap_or(offset));
+            }
+            current += 1;
+            block_start = None;
+        }
+        offset += line.len();
+    }
+    (playlist, "")
+}
+
+/// Cuts a segment file back to its last complete MPEG-TS packet or fMP4 fragment.
+pub fn repair_segment(path: &Path) -> io::Result<SegmentRepair> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            return Ok(SegmentRepair::Broken);
+        }
+        Err(error) => return Err(error),
+    };
+    let playable = if data.first() == Some(&0x47) {
+        ts_playable_length(&data)
+    } else {
+        fmp4_playable_length(&data)
+    };
+    if playable == 0 {
+        return Ok(SegmentRepair::Broken);
+    }
+    if playable == data.len() {
+        return Ok(SegmentRepair::Intact);
+    }
+    OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .set_len(playable as u64)?;
+    Ok(SegmentRepair::Truncated)
+}
+
+/// Encrypted segments are intact when they hold whole AES blocks; a torn last block loses the
+/// padding, so such a segment cannot be decrypted to the end and counts as broken.
+fn check_encrypted_segment(path: &Path) -> io::Result<SegmentRepair> {
+    let length = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => 0,
+        Err(error) => return Err(error),
+    };
+    Ok(if length > 0 && length.is_multiple_of(16) {
+        SegmentRepair::Intact
+    } else {
+        SegmentRepair::Broken
+    })
+}
+
+/// Length of the leading run of whole MPEG-TS packets that start with the sync byte.
+pub fn ts_playable_length(data: &[u8]) -> usize {
+    data.chunks_exact(TS_PACKET_SIZE)
+        .take_while(|packet| packet[0] == 0x47)
+        .count()
+        * TS_PACKET_SIZE
+}
+
+/// Length up to the end of the last complete `mdat` box, so every kept fragment has its samples.
+///
+/// Media segments without fragments, such as a lone init segment, have no playable length.
+pub fn fmp4_playable_length(data: &[u8]) -> usize {
+    let mut offset = 0;
+    let mut playable = 0;
+    while offset + 8 <= data.len() {
+        let size = u32::from_be_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ]) as u64;
+        let size = match size {
+            // The box extends to the end of the file, so it can only be complete by luck.
+            0 => break,
+            1 if offset + 16 <= data.len() => {
+                let mut large = [0; 8];
+                large.copy_from_slice(&data[offset + 8..offset + 16]);
+                u64::from_be_bytes(large)
+            }
+            1 => break,
+            size => size,
+        };
+        if size < 8 || offset as u64 + size > data.len() as u64 {
+            break;
+        }
+        let kind = &data[offset + 4..offset + 8];
+        offset += size as usize;
+        if kind == b"mdat" {
+            playable = offset;
+        }
+    }
+    playable
+}
+
+/// Duration of an MPEG-TS segment from the first and last PES timestamps of its video, or of
+/// its audio when it has no video.
+fn ts_duration(path: &Path) -> io::Result<Option<f64>> {
+    let data = fs::read(path)?;
+    Ok(ts_timestamp_span(&data[..ts_playable_length(&data)]))
+}
+
+/// Span, in seconds, between the lowest and highest PES presentation timestamps of `data`.
+pub fn ts_timestamp_span(data: &[u8]) -> Option<f64> {
+    let mut video = None::<(u64, u64)>;
+    let mut audio = None::<(u64, u64)>;
+    for packet in data.chunks_exact(TS_PACKET_SIZE) {
+        let unit_start = packet[1] & 0x40 != 0;
+        let adaptation = (packet[3] >> 4) & 0x03;
+        if !unit_start || adaptation & 0x01 == 0 {
+            continue;
+        }
+        let payload_start = if adaptation == 0x03 {
+            5 + usize::from(packet[4])
+        } else {
+            4
+        };
+        let Some(pes) = packet.get(payload_start..) else {
+            continue;
+        };
+        if pes.len() < 14 || pes[..3] != [0, 0, 1] || pes[7] & 0x80 == 0 {
+            continue;
+        }
+        let pts = ((u64::from(pes[9] >> 1) & 0x07) << 30)
+            | (u64::from(pes[10]) << 22)
+            | (u64::from(pes[11] >> 1) << 15)
+            | (u64::from(pes[12]) << 7)
+            | u64::from(pes[13] >> 1);
+        let range = match pes[3] {
+            0xe0..=0xef => &mut video,
+            0xc0..=0xdf | 0xbd => &mut audio,
+            _ => continue,
+        };
+        *range = Some(range.map_or((pts, pts), |(low, high)| (low.min(pts), high.max(pts))));
+    }
+    let (low, high) = video.or(audio)?;
+    (high > low).then(|| (high - low) as f64 / PES_CLOCK_RATE)
+}
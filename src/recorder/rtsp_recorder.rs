@@ -1,17 +1,36 @@
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
-use ffmpeg_next::{
-    Dictionary, Packet, Rational, codec, decoder, encoder, format, frame, log, media, picture,
-};
+use ffmpeg_next::codec::packet::side_data;
+use ffmpeg_next::{Rational, codec, encoder, ffi, format, log, media};
 
-use super::{HlsOutput, RecorderError, VideoCodec};
+use super::audio_transcoder::AudioTranscoder;
+use super::master_playlist::{
+    VariantStream, audio_codec_string, h264_codec_string, hevc_codec_string,
+    master_playlist as master_playlist_contents, variant_uri,
+};
+use super::output_muxer::OutputMuxer;
+use super::program_date_time::{format_program_date_time, now_epoch_seconds};
+use super::snapshot::{SnapshotHub, Snapshotter};
+use super::source_clock::{ClockSource, SourceClock, producer_reference_time};
+use super::thumbnails::Thumbnailer;
+use super::timestamp_sanitizer::{TimestampAnomaly, TimestampSanitizer};
+use super::video_transcoder::VideoTranscoder;
+use super::{
+    AudioMode, HlsOutput, Output, RecorderError, ResolutionChange, StreamInfo, StreamSelection,
+    VideoCodec, VideoProcessing, insert_thumbnail_reference, thumbnail_track_path,
+};
+use crate::rtsp_server::{RestreamHub, RestreamTrack, TrackCodec, camera_id};
 
 /// High-level orchestrator that translates RTSP input into a file-based HLS presentation.
 pub struct RtspRecorder {
-    _private: (),
+    /// Where recorded inputs are republished for the embedded RTSP server, when enabled.
+    restream: Option<RestreamHub>,
+    /// Where cameras taking snapshots register them for the HTTP server.
+    snapshots: Option<SnapshotHub>,
 }
 
 impl RtspRecorder {
@@ -19,116 +38,395 @@ impl RtspRecorder {
     pub fn new() -> Result<Self, RecorderError> {
         ffmpeg_next::init()?;
         log::set_level(log::Level::Warning);
-        Ok(Self { _private: () })
+        Ok(Self {
+            restream: None,
+            snapshots: None,
+        })
+    }
+
+    /// Republishes every recorded input through `hub`, under the camera's id.
+    pub fn with_restream(mut self, hub: RestreamHub) -> Self {
+        self.restream = Some(hub);
+        self
     }
 
-    /// Copies packets from the provided RTSP source into an HLS muxer until the optional duration elapses.
+    /// Registers every camera taking snapshots with `hub`, under the camera's id.
+    pub fn with_snapshots(mut self, hub: SnapshotHub) -> Self {
+        self.snapshots = Some(hub);
+        self
+    }
+
+    /// Records the RTSP source into one or more outputs until the optional duration elapses.
+    ///
+    /// Every output shares the single RTSP connection and a single decoder per input stream.
+    /// Outputs naming a `master_playlist` are listed there as variant streams.
     pub fn record(
         &self,
         rtsp_url: &str,
-        hls_output: &HlsOutput,
+        outputs: &[Output],
+        processing: &VideoProcessing,
+        streams: &StreamSelection,
         duration_limit: Option<Duration>,
     ) -> Result<(), RecorderError> {
-        let mut ictx = format::input(&rtsp_url)?;
-        let playlist_path = hls_output.playlist_path.as_path();
-
-        if let Some(parent) = playlist_path.parent() {
-            if !parent.as_os_str().is_empty() {
-                // Ensure the target directory exists so FFmpeg can create playlist and segment files.
-                fs::create_dir_all(parent)?;
+        if outputs
+            .iter()
+            .any(|output| output.video_codec() == VideoCodec::Copy)
+        {
+            if let Some(feature) = processing.transcode_requirement() {
+                return Err(RecorderError::TranscodeRequired(feature));
             }
         }
-
-        let mut format_options = Dictionary::new();
-
-        if let Some(duration) = hls_output.segment_duration {
-            format_options.set("hls_time", &duration.to_string());
+        if processing.resolution_change == ResolutionChange::Reinitialize
+            && let Some(output) = outputs.iter().find(|output| {
+                output.video_codec() != VideoCodec::Copy && !output.accepts_parameter_changes()
+            })
+        {
+            return Err(RecorderError::InvalidOutput(format!(
+                "on_resolution_change reinitialize needs MPEG-TS segments and no RTMP targets, but {} fixes the resolution in its header",
+                output.display_name()
+            )));
         }
 
-        if let Some(size) = hls_output.playlist_size {
-            format_options.set("hls_list_size", &size.to_string());
-        }
+        // Sampled before connecting: connection and probing often take seconds, and the first
+        // frames were captured when they began.
+        let connect_epoch_seconds = now_epoch_seconds();
+        let mut ictx = format::input(&rtsp_url)?;
 
-        let segment_template = derive_segment_template(hls_output);
-        format_options.set("hls_segment_filename", &segment_template);
-        if matches!(hls_output.video_codec, VideoCodec::H265) {
-            format_options.set("hls_segment_type", "fmp4");
-        }
+        // Wall-clock time of input timestamp zero, known once the RTSP demuxer has seen an RTCP
+        // sender report during probing.
+        let realtime_base = unsafe {
+            let start = (*ictx.as_ptr()).start_time_realtime;
+            (start != ffi::AV_NOPTS_VALUE).then(|| start as f64 / 1_000_000.0)
+        };
+        // Wall-clock anchor for the first decoded frame of a stream, used by burned-in
+        // timestamps: exact when sender reports date the stream, the session start otherwise.
+        let start_epoch_seconds = |ist: &format::stream::Stream| match realtime_base {
+            Some(base) if ist.start_time() != ffi::AV_NOPTS_VALUE => {
+                base + ist.start_time() as f64 * f64::from(ist.time_base())
+            }
+            _ => connect_epoch_seconds,
+        };
 
-        let mut octx = format::output_as_with(&hls_output.playlist_path, "hls", format_options)?;
+        let mut muxers = outputs
+            .iter()
+            .map(OutputMuxer::open)
+            .collect::<Result<Vec<_>, _>>()?;
+        // Presentations among the outputs, with their output indices.
+        let presentations: Vec<(usize, &HlsOutput)> = outputs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, output)| Some((index, output.as_hls()?)))
+            .collect();
+        let mut variants: Vec<VariantStream> = outputs
+            .iter()
+            .map(|output| VariantStream {
+                uri: output
+                    .as_hls()
+                    .and_then(|hls| {
+                        let master = hls.master_playlist.as_deref()?;
+                        Some(variant_uri(master, &hls.playlist_path))
+                    })
+                    .unwrap_or_default(),
+                bandwidth: 0,
+                resolution: None,
+                codecs: Vec::new(),
+            })
+            .collect();
+
+        let stream_infos: Vec<StreamInfo> = ictx
+            .streams()
+            .map(|ist| StreamInfo::from_stream(&ist))
+            .collect();
+        let selected_streams = streams.select(&stream_infos)?;
 
         let nb_streams = ictx.nb_streams() as usize;
-        let mut stream_mapping = vec![-1; nb_streams];
+        let mut source_clock = SourceClock::new(nb_streams, realtime_base);
+        let mut clock_source = None;
         let mut ist_time_bases = vec![Rational(0, 1); nb_streams];
-        let mut ost_time_bases = vec![Rational(0, 1); nb_streams];
+        // Per input stream: (output, output stream) pairs receiving its packets untouched.
+        let mut copy_routes: Vec<Vec<(usize, usize)>> = vec![Vec::new(); nb_streams];
         let mut video_transcoders: HashMap<usize, VideoTranscoder> = HashMap::new();
-        let mut ost_index = 0usize;
+        // Audio is resampled per output, since each output may ask for a different codec.
+        let mut audio_transcoders: Vec<(usize, usize, AudioTranscoder)> = Vec::new();
+
+        println!("Stream mapping for {rtsp_url}:");
 
         for (ist_index, ist) in ictx.streams().enumerate() {
             let medium = ist.parameters().medium();
-            if medium != media::Type::Video && medium != media::Type::Audio {
+            if !selected_streams.contains(&ist_index) {
                 continue;
             }
-
-            stream_mapping[ist_index] = ost_index as isize;
             ist_time_bases[ist_index] = ist.time_base();
+            let info = &stream_infos[ist_index];
 
-            if medium == media::Type::Video {
-                let transcoder = VideoTranscoder::new(
-                    &ist,
-                    &mut octx,
-                    ost_index,
-                    hls_output.video_codec,
-                )?;
-                video_transcoders.insert(ist_index, transcoder);
-            } else {
-                let mut ost = octx.add_stream(encoder::find(codec::Id::None))?;
-                ost.set_parameters(ist.parameters());
-
-                unsafe {
-                    (*ost.parameters().as_mut_ptr()).codec_tag = 0;
+            for (output, settings) in outputs.iter().enumerate() {
+                let audio = settings.audio();
+                if medium == media::Type::Audio && audio.mode == AudioMode::Disabled {
+                    continue;
                 }
-            }
 
-            ost_index += 1;
+                let muxer = &mut muxers[output];
+                let ost_index = muxer.stream_count();
+                let variant = &mut variants[output];
+                let action = if medium == media::Type::Video {
+                    format!("{:?}", settings.video_codec()).to_lowercase()
+                } else {
+                    format!("{:?}", audio.mode).to_lowercase()
+                };
+                let target = if outputs.len() > 1 {
+                    format!("output {output} #{ost_index}")
+                } else {
+                    format!("output #{ost_index}")
+                };
+                println!(
+                    "  input #{ist_index} ({:?}, {}) -> {target} ({action})",
+                    info.medium, info.codec
+                );
+
+                if medium == media::Type::Video && settings.video_codec() != VideoCodec::Copy {
+                    let transcoder = match video_transcoders.entry(ist_index) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => entry.insert(VideoTranscoder::new(
+                            &ist,
+                            processing,
+                            start_epoch_seconds(&ist),
+                        )?),
+                    };
+                    let encoder = transcoder.add_encoder(muxer, output, settings)?;
+                    variant.resolution = Some(encoder.size());
+                    variant.codecs.extend(encoder.codec_string());
+                    variant.bandwidth += u64::from(settings.video_bitrate().unwrap_or(0));
+                } else if medium == media::Type::Audio && audio.mode != AudioMode::Copy {
+                    let transcoder = AudioTranscoder::new(&ist, muxer, &audio)?;
+                    variant.codecs.extend(transcoder.codec_string());
+                    variant.bandwidth += u64::from(transcoder.bitrate());
+                    audio_transcoders.push((ist_index, output, transcoder));
+                } else {
+                    let parameters = ist.parameters();
+                    let mut ost = muxer.context().add_stream(encoder::find(codec::Id::None))?;
+                    ost.set_parameters(ist.parameters());
+
+                    let (bit_rate, profile, width, height) = unsafe {
+                        let par = parameters.as_ptr();
+                        ((*par).bit_rate, (*par).profile, (*par).width, (*par).height)
+                    };
+                    if medium == media::Type::Video {
+                        variant.resolution = Some((width as u32, height as u32));
+                        variant
+                            .codecs
+                            .extend(copied_video_codec_string(&parameters));
+                        variant.bandwidth += settings
+                            .video_bitrate()
+                            .map_or(bit_rate.max(0) as u64, u64::from);
+                    } else {
+                        variant
+                            .codecs
+                            .extend(audio_codec_string(&info.codec, profile));
+                        variant.bandwidth += bit_rate.max(0) as u64;
+                    }
+
+                    unsafe {
+                        (*ost.parameters().as_mut_ptr()).codec_tag = 0;
+                    }
+                    copy_routes[ist_index].push((output, ost_index));
+                }
+            }
         }
 
-        if ost_index == 0 {
+        if muxers.iter().any(|muxer| muxer.stream_count() == 0) {
             return Err(RecorderError::MissingMediaStreams);
         }
 
-        ost_time_bases.truncate(ost_index);
+        // Snapshots and thumbnails are taken from the first selected video stream.
+        let takes_stills = processing.snapshot.is_some()
+            || presentations
+                .iter()
+                .any(|(_, output)| output.thumbnails.is_some());
+        let video = ictx.streams().find(|ist| {
+            selected_streams.contains(&ist.index())
+                && ist.parameters().medium() == media::Type::Video
+        });
+        match video {
+            Some(ist) if takes_stills => {
+                let transcoder = match video_transcoders.entry(ist.index()) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        // Copied video is decoded for stills only, so key frames suffice.
+                        let mut transcoder =
+                            VideoTranscoder::new(&ist, processing, start_epoch_seconds(&ist))?;
+                        transcoder.decode_key_frames_only();
+                        entry.insert(transcoder)
+                    }
+                };
+                if let Some(options) = &processing.snapshot {
+                    let slot = self.snapshots.as_ref().map(|hub| {
+                        hub.register(&camera_id(&processing.camera_name), options.latest_path())
+                    });
+                    transcoder.set_snapshotter(Snapshotter::new(options, slot)?);
+                    println!(
+                        "  input #{} -> snapshots in {}",
+                        ist.index(),
+                        options.dir.display()
+                    );
+                }
+                for &(output, hls_output) in &presentations {
+                    let Some(options) = &hls_output.thumbnails else {
+                        continue;
+                    };
+                    let resumed = muxers[output].resumed_from().is_some();
+                    transcoder.add_thumbnailer(Thumbnailer::new(hls_output, options, resumed)?);
+                    println!(
+                        "  input #{} -> thumbnails in {}",
+                        ist.index(),
+                        thumbnail_track_path(&hls_output.playlist_path).display()
+                    );
+                }
+            }
+            None if takes_stills => eprintln!(
+                "Warning: {rtsp_url} has no video stream to take snapshots or thumbnails of"
+            ),
+            _ => {}
+        }
 
-        octx.set_metadata(ictx.metadata().to_owned());
-        octx.write_header()?;
+        for muxer in &mut muxers {
+            muxer.write_header(ictx.metadata().to_owned())?;
+        }
 
-        for index in 0..ost_index {
-            let stream = octx
-                .stream(index)
-                .ok_or(RecorderError::InvalidStreamMapping(index))?;
-            ost_time_bases[index] = stream.time_base();
+        let mut master_playlists: Vec<&Path> = Vec::new();
+        for master in presentations
+            .iter()
+            .filter_map(|(_, output)| output.master_playlist.as_deref())
+        {
+            if !master_playlists.contains(&master) {
+                master_playlists.push(master);
+            }
         }
+        for master in master_playlists {
+            let listed: Vec<VariantStream> = presentations
+                .iter()
+                .filter(|(_, output)| output.master_playlist.as_deref() == Some(master))
+                .map(|&(index, _)| variants[index].clone())
+                .collect();
+            if let Some(parent) = master.parent() {
+                if !parent.as_os_str().is_empty() {
+                    fs::create_dir_all(parent)?;
+                }
+            }
+            let mut contents = master_playlist_contents(&listed);
+            if let Some((_, output)) = presentations.iter().find(|(_, output)| {
+                output.master_playlist.as_deref() == Some(master) && output.thumbnails.is_some()
+            }) {
+                let track = thumbnail_track_path(&output.playlist_path);
+                contents = insert_thumbnail_reference(&contents, &variant_uri(master, &track));
+            }
+            fs::write(master, contents)?;
+        }
+
+        // Input packets are republished untouched, so the camera needs no second connection.
+        // Per input stream: republished track index and its RTP clock rate.
+        let mut restream_tracks: Vec<Option<(usize, u32)>> = vec![None; nb_streams];
+        let publisher = self.restream.as_ref().map(|hub| {
+            let mut tracks = Vec::new();
+            for (ist_index, ist) in ictx.streams().enumerate() {
+                if !selected_streams.contains(&ist_index) {
+                    continue;
+                }
+                if let Some(track) = restream_track(&ist.parameters()) {
+                    restream_tracks[ist_index] = Some((tracks.len(), track.clock_rate));
+                    tracks.push(track);
+                }
+            }
+            let id = camera_id(&processing.camera_name);
+            println!("{rtsp_url}: republished as /{id}");
+            hub.publish(&id, tracks)
+        });
 
+        let mut sanitizer = TimestampSanitizer::new(&ist_time_bases);
         let start = Instant::now();
 
         for (stream, mut packet) in ictx.packets() {
             let ist_index = stream.index();
-            let mapping = stream_mapping[ist_index];
-            if mapping < 0 {
+            if !selected_streams.contains(&ist_index) {
                 continue;
             }
-            let mapping = mapping as usize;
 
-            let ost_time_base = ost_time_bases[mapping];
+            let time_base = f64::from(ist_time_bases[ist_index]);
+            let source_pts = packet.pts();
+            let (pts, dts, correction) = sanitizer.sanitize(ist_index, source_pts, packet.dts());
+            packet.set_pts(pts);
+            packet.set_dts(dts);
+            if let Some(correction) = correction {
+                let kind = match correction.anomaly {
+                    TimestampAnomaly::Regression => "went backwards",
+                    TimestampAnomaly::Gap => "jumped",
+                };
+                eprintln!(
+                    "Warning: {rtsp_url} input #{ist_index} timestamps {kind} by {:.3}s; rebased ({} corrections so far)",
+                    correction.jump_seconds,
+                    sanitizer.corrections()
+                );
+                source_clock.discontinuity(ist_index);
+                let seconds = pts.or(dts).unwrap_or_default() as f64 * time_base;
+                for muxer in &mut muxers {
+                    muxer.mark_discontinuity(seconds);
+                }
+            }
+
+            if let (Some(source_pts), Some(pts)) = (source_pts, pts) {
+                let reference = packet
+                    .side_data()
+                    .find(|data| data.kind() == side_data::Type::PRFT)
+                    .and_then(|data| producer_reference_time(data.data()));
+                // Sender report mappings refer to the timestamps the camera sent.
+                let (epoch_seconds, source) =
+                    source_clock.wall_clock(ist_index, source_pts as f64 * time_base, reference);
+                let pts_seconds = pts as f64 * time_base;
+                if clock_source != Some(ClockSource::Rtcp) && clock_source != Some(source) {
+                    match source {
+                        ClockSource::Rtcp => {
+                            println!("{rtsp_url}: wall clock from RTCP sender reports")
+                        }
+                        ClockSource::Local => {
+                            println!(
+                                "{rtsp_url}: no RTCP sender reports yet, using the local clock"
+                            )
+                        }
+                    }
+                    clock_source = Some(source);
+                }
+                for muxer in &mut muxers {
+                    muxer.sync_wall_clock(pts_seconds, epoch_seconds);
+                }
+                // The rebase cut the jump out of the timestamps, so burned-in times are moved
+                // back onto the wall clock.
+                if correction.is_some()
+                    && let Some(transcoder) = video_transcoders.get_mut(&ist_index)
+                {
+                    transcoder.rebase_overlay(pts, epoch_seconds);
+                }
+            }
+
+            if let (Some(publisher), Some((track, clock_rate))) =
+                (&publisher, restream_tracks[ist_index])
+                && let (Some(timestamp), Some(data)) = (pts.or(dts), packet.data())
+            {
+                let rtp_time = (timestamp as f64 * time_base * f64::from(clock_rate)).round();
+                publisher.send(track, data, rtp_time as i64 as u32, packet.is_key());
+            }
 
             if let Some(transcoder) = video_transcoders.get_mut(&ist_index) {
                 transcoder.send_packet_to_decoder(&packet)?;
-                transcoder.receive_and_process_decoded_frames(&mut octx, ost_time_base)?;
-            } else {
-                packet.rescale_ts(ist_time_bases[ist_index], ost_time_base);
-                packet.set_position(-1);
-                packet.set_stream(mapping);
-                packet.write_interleaved(&mut octx)?;
+                transcoder.receive_and_process_decoded_frames(&mut muxers)?;
+            }
+            for (index, output, transcoder) in audio_transcoders.iter_mut() {
+                if *index == ist_index {
+                    transcoder.send_packet_to_decoder(&packet)?;
+                    transcoder.receive_and_process_decoded_frames(&mut muxers[*output])?;
+                }
+            }
+            for &(output, ost_index) in &copy_routes[ist_index] {
+                let mut packet = packet.clone();
+                muxers[output].write(&mut packet, ost_index, ist_time_bases[ist_index])?;
             }
 
             if let Some(limit) = duration_limit {
@@ -138,163 +436,143 @@ impl RtspRecorder {
             }
         }
 
-        for (ist_index, transcoder) in video_transcoders.iter_mut() {
-            let mapping = stream_mapping[*ist_index];
-            if mapping < 0 {
-                continue;
-            }
-            let mapping = mapping as usize;
-            let ost_time_base = ost_time_bases[mapping];
+        for transcoder in video_transcoders.values_mut() {
+            transcoder.send_eof_to_decoder()?;
+            transcoder.receive_and_process_decoded_frames(&mut muxers)?;
+            transcoder.flush_filter(&mut muxers)?;
+            transcoder.flush_encoders(&mut muxers)?;
+        }
 
+        for (_, output, transcoder) in audio_transcoders.iter_mut() {
+            let muxer = &mut muxers[*output];
             transcoder.send_eof_to_decoder()?;
-            transcoder.receive_and_process_decoded_frames(&mut octx, ost_time_base)?;
+            transcoder.receive_and_process_decoded_frames(muxer)?;
+            transcoder.flush_filter(muxer)?;
             transcoder.send_eof_to_encoder()?;
-            transcoder.receive_and_process_encoded_packets(&mut octx, ost_time_base)?;
+            transcoder.receive_and_process_encoded_packets(muxer)?;
         }
 
-        octx.write_trailer()?;
-        Ok(())
-    }
-}
-
-struct VideoTranscoder {
-    decoder: decoder::Video,
-    encoder: encoder::Video,
-    input_time_base: Rational,
-    ost_index: usize,
-}
-
-impl VideoTranscoder {
-    fn new(
-        ist: &format::stream::Stream,
-        octx: &mut format::context::Output,
-        ost_index: usize,
-        codec: VideoCodec,
-    ) -> Result<Self, RecorderError> {
-        let global_header = octx.format().flags().contains(format::Flags::GLOBAL_HEADER);
-        let decoder = ffmpeg_next::codec::context::Context::from_parameters(ist.parameters())?
-            .decoder()
-            .video()?;
-
-        let codec_id = match codec {
-            VideoCodec::H264 => codec::Id::H264,
-            VideoCodec::H265 => codec::Id::HEVC,
-        };
-
-        let encoder_codec = encoder::find(codec_id)
-            .ok_or(RecorderError::UnsupportedVideoCodec(codec))?;
-
-        let mut ost = octx.add_stream(Some(encoder_codec))?;
-        let mut encoder_context =
-            ffmpeg_next::codec::context::Context::new_with_codec(encoder_codec)
-                .encoder()
-                .video()?;
-
-        encoder_context.set_height(decoder.height());
-        encoder_context.set_width(decoder.width());
-        encoder_context.set_aspect_ratio(decoder.aspect_ratio());
-        encoder_context.set_format(decoder.format());
-        encoder_context.set_frame_rate(decoder.frame_rate());
-        encoder_context.set_time_base(ist.time_base());
-
-        if global_header {
-            encoder_context.set_flags(codec::Flags::GLOBAL_HEADER);
+        for muxer in &mut muxers {
+            muxer.finish()?;
         }
 
-        ost.set_parameters(&encoder_context);
-        let options = encoder_options(codec);
-        let opened_encoder = encoder_context.open_with(options)?;
-        ost.set_parameters(&opened_encoder);
-
-        Ok(Self {
-            decoder,
-            encoder: opened_encoder,
-            input_time_base: ist.time_base(),
-            ost_index,
-        })
-    }
-
-    fn send_packet_to_decoder(&mut self, packet: &Packet) -> Result<(), RecorderError> {
-        self.decoder.send_packet(packet)?;
-        Ok(())
-    }
-
-    fn send_eof_to_decoder(&mut self) -> Result<(), RecorderError> {
-        self.decoder.send_eof()?;
+        if sanitizer.corrections() > 0 {
+            println!(
+                "{rtsp_url}: corrected {} timestamp anomalies",
+                sanitizer.corrections()
+            );
+        }
         Ok(())
     }
+}
 
-    fn send_eof_to_encoder(&mut self) -> Result<(), RecorderError> {
-        self.encoder.send_eof()?;
-        Ok(())
-    }
+/// Describes an input stream for republishing, or `None` for codecs the RTSP server cannot carry.
+fn restream_track(parameters: &codec::Parameters) -> Option<RestreamTrack> {
+    let (sample_rate, channels, extradata) = unsafe {
+        let par = parameters.as_ptr();
+        let extradata = if (*par).extradata.is_null() || (*par).extradata_size <= 0 {
+            Vec::new()
+        } else {
+            std::slice::from_raw_parts((*par).extradata, (*par).extradata_size as usize).to_vec()
+        };
+        (
+            (*par).sample_rate.max(0) as u32,
+            (*par).ch_layout.nb_channels.max(1) as u32,
+            extradata,
+        )
+    };
+    let (codec, clock_rate) = match parameters.id() {
+        codec::Id::H264 => (TrackCodec::H264, 90_000),
+        codec::Id::HEVC => (TrackCodec::H265, 90_000),
+        codec::Id::AAC => (TrackCodec::Aac, sample_rate),
+        codec::Id::PCM_MULAW => (TrackCodec::Pcmu, sample_rate),
+        codec::Id::PCM_ALAW => (TrackCodec::Pcma, sample_rate),
+        codec::Id::OPUS => (TrackCodec::Opus, 48_000),
+        _ => return None,
+    };
+    (clock_rate > 0).then_some(RestreamTrack {
+        codec,
+        clock_rate,
+        channels,
+        extradata,
+    })
+}
 
-    fn receive_and_process_decoded_frames(
-        &mut self,
-        octx: &mut format::context::Output,
-        ost_time_base: Rational,
-    ) -> Result<(), RecorderError> {
-        let mut frame = frame::Video::empty();
-        while self.decoder.receive_frame(&mut frame).is_ok() {
-            let timestamp = frame.timestamp();
-            frame.set_pts(timestamp);
-            frame.set_kind(picture::Type::None);
-            self.encoder.send_frame(&frame)?;
-            self.receive_and_process_encoded_packets(octx, ost_time_base)?;
+/// Reads the codec string of a passed-through video stream from its parameter sets.
+fn copied_video_codec_string(parameters: &codec::Parameters) -> Option<String> {
+    let extradata = unsafe {
+        let par = parameters.as_ptr();
+        if (*par).extradata.is_null() || (*par).extradata_size <= 0 {
+            return None;
         }
-        Ok(())
+        std::slice::from_raw_parts((*par).extradata, (*par).extradata_size as usize)
+    };
+    match parameters.id() {
+        codec::Id::H264 => h264_codec_string(extradata),
+        codec::Id::HEVC => hevc_codec_string(extradata),
+        _ => None,
     }
+}
 
-    fn receive_and_process_encoded_packets(
-        &mut self,
-        octx: &mut format::context::Output,
-        ost_time_base: Rational,
-    ) -> Result<(), RecorderError> {
-        let mut encoded = Packet::empty();
-        while self.encoder.receive_packet(&mut encoded).is_ok() {
-            encoded.set_stream(self.ost_index);
-            encoded.rescale_ts(self.input_time_base, ost_time_base);
-            encoded.set_position(-1);
-            encoded.write_interleaved(octx)?;
-        }
-        Ok(())
-    }
+/// Placeholder in `segment_filename` replaced by the start time of the recording session.
+pub const SESSION_PLACEHOLDER: &str = "{session}";
+
+/// Placeholder in `segment_filename` replaced by the local wall-clock time each segment starts.
+pub const WALLCLOCK_PLACEHOLDER: &str = "{wallclock}";
+
+/// `strftime` conversions [`WALLCLOCK_PLACEHOLDER`] expands to, e.g. `20240501T140000+0200`.
+const WALLCLOCK_FORMAT: &str = "%Y%m%dT%H%M%S%z";
+
+/// Turns a segment template holding [`WALLCLOCK_PLACEHOLDER`] into the `strftime` pattern that
+/// FFmpeg's `hls` muxer expands as each segment starts, or `None` for other templates.
+///
+/// Every other `%` is doubled, so a sequence number such as `%05d` survives `strftime` and is
+/// filled in by the muxer's `second_level_segment_index` flag.
+pub fn wallclock_segment_template(template: &str) -> Option<String> {
+    template.contains(WALLCLOCK_PLACEHOLDER).then(|| {
+        template
+            .replace('%', "%%")
+            .replace(WALLCLOCK_PLACEHOLDER, WALLCLOCK_FORMAT)
+    })
 }
 
-fn encoder_options(codec: VideoCodec) -> Dictionary<'static> {
-    let mut options = Dictionary::new();
-    match codec {
-        VideoCodec::H264 => {
-            options.set("preset", "veryfast");
-            options.set("crf", "23");
-        }
-        VideoCodec::H265 => {
-            options.set("preset", "medium");
-            options.set("crf", "28");
-        }
-    }
-    options
+/// Name of a recording session started at `epoch_seconds`: its UTC start time, such as
+/// `20240501T120000Z`.
+pub fn session_name(epoch_seconds: f64) -> String {
+    format_program_date_time(epoch_seconds.floor())
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == 'T')
+        .take(15)
+        .chain(['Z'])
+        .collect()
 }
 
+/// Segment file name pattern of `hls_output`, with [`SESSION_PLACEHOLDER`] left unexpanded.
+///
+/// Appending outputs without a `segment_filename` get the session in their default names, so a
+/// run that starts numbering over never overwrites the files of an earlier one.
 pub fn derive_segment_template(hls_output: &HlsOutput) -> String {
+    let format = hls_output.effective_segment_format();
+    let extensions = format.extensions();
+
     if let Some(template) = &hls_output.segment_filename {
-        if hls_output.video_codec == VideoCodec::H265 {
-            let original = PathBuf::from(template);
-            let requires_override = match original.extension().and_then(|ext| ext.to_str()) {
-                Some(ext) if ext.eq_ignore_ascii_case("m4s") => false,
-                _ => true,
-            };
-
-            if requires_override {
-                let mut adjusted = original.clone();
-                adjusted.set_extension("m4s");
-                let original_display = original.to_string_lossy();
-                let adjusted_display = adjusted.to_string_lossy().to_string();
-                eprintln!(
-                    "Warning: overriding segment filename extension from {original_display} to {adjusted_display} for H.265 output"
-                );
-                return adjusted_display;
-            }
+        let original = PathBuf::from(template);
+        let requires_override = match original.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) => !extensions
+                .iter()
+                .any(|allowed| ext.eq_ignore_ascii_case(allowed)),
+            None => true,
+        };
+
+        if requires_override {
+            let mut adjusted = original.clone();
+            adjusted.set_extension(extensions[0]);
+            let original_display = original.to_string_lossy();
+            let adjusted_display = adjusted.to_string_lossy().to_string();
+            eprintln!(
+                "Warning: overriding segment filename extension from {original_display} to {adjusted_display} for {format:?} segments"
+            );
+            return adjusted_display;
         }
         return template.clone();
     }
@@ -306,13 +584,13 @@ pub fn derive_segment_template(hls_output: &HlsOutput) -> String {
         .and_then(|s| s.to_str())
         .unwrap_or("segment");
 
-    let extension = match hls_output.video_codec {
-        VideoCodec::H264 => "ts",
-        VideoCodec::H265 => "m4s",
+    let session = if hls_output.append {
+        format!("_{SESSION_PLACEHOLDER}")
+    } else {
+        String::new()
     };
-
     parent
-        .join(format!("{stem}_%05d.{extension}"))
+        .join(format!("{stem}{session}_%05d.{}", extensions[0]))
         .to_string_lossy()
         .to_string()
 }
@@ -0,0 +1,252 @@
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// One finished segment together with the wall-clock time of its first sample.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SegmentRecord {
+    /// Segment URI as listed in the media playlist.
+    pub uri: String,
+    /// Media sequence number of the segment.
+    pub sequence: u64,
+    /// Wall-clock start, in seconds since the Unix epoch.
+    pub start: f64,
+    /// Duration in seconds.
+    pub duration: f64,
+    /// Whether the segment follows a timestamp discontinuity.
+    #[serde(default)]
+    pub discontinuity: bool,
+}
+
+impl SegmentRecord {
+    /// Wall-clock time just past the last sample.
+    pub fn end(&self) -> f64 {
+        self.start + self.duration
+    }
+}
+
+/// One segment entry of a media playlist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaylistEntry {
+    /// Segment URI.
+    pub uri: String,
+    /// `EXTINF` duration in seconds.
+    pub duration: f64,
+    /// Whether an `EXT-X-DISCONTINUITY` tag precedes the segment.
+    pub discontinuity: bool,
+}
+
+/// Location of the wall-clock index kept next to a playlist, `<playlist>.index.jsonl`.
+pub fn segment_index_path(playlist_path: &Path) -> PathBuf {
+    let stem = playlist_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("stream");
+    playlist_path.with_file_name(format!("{stem}.index.jsonl"))
+}
+
+/// Reads a segment index, skipping lines that do not parse, such as one cut short by a crash.
+pub fn read_segment_index(path: &Path) -> io::Result<Vec<SegmentRecord>> {
+    Ok(fs::read_to_string(path)?
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect())
+}
+
+/// Segment covering wall-clock time `epoch_seconds`, and the offset of that instant within it.
+///
+/// Indexes of different cameras share the wall-clock timeline, so looking up the same instant in
+/// each yields synchronized playback positions.
+pub fn locate_segment(
+    records: &[SegmentRecord],
+    epoch_seconds: f64,
+) -> Option<(&SegmentRecord, f64)> {
+    records
+        .iter()
+        .rev()
+        .find(|record| record.start <= epoch_seconds && epoch_seconds < record.end())
+        .map(|record| (record, epoch_seconds - record.start))
+}
+
+/// Media sequence number of the first segment and the segments listed in a media playlist.
+pub fn media_playlist_entries(playlist: &str) -> (u64, Vec<PlaylistEntry>) {
+    let mut media_sequence = 0;
+    let mut entries = Vec::new();
+    let mut duration = None;
+    let mut discontinuity = false;
+
+    for line in playlist.lines().map(str::trim) {
+        if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            media_sequence = value.parse().unwrap_or(0);
+        } else if let Some(value) = line.strip_prefix("#EXTINF:") {
+            let value = value.split(',').next().unwrap_or_default();
+            duration = value.parse().ok();
+        } else if line == "#EXT-X-DISCONTINUITY" {
+            discontinuity = true;
+        } else if !line.is_empty()
+            && !line.starts_with('#')
+            && let Some(duration) = duration.take()
+        {
+            entries.push(PlaylistEntry {
+                uri: line.to_string(),
+                duration,
+                discontinuity,
+            });
+            discontinuity = false;
+        }
+    }
+    (media_sequence, entries)
+}
+
+/// Tags the segments with media sequence numbers in `discontinuous` with `EXT-X-DISCONTINUITY`.
+///
+/// Tagged segments that already slid out of the window are counted in
+/// `EXT-X-DISCONTINUITY-SEQUENCE`, as players require.
+pub fn insert_discontinuities(playlist: &str, discontinuous: &BTreeSet<u64>) -> String {
+    let (media_sequence, _) = media_playlist_entries(playlist);
+    let dropped = discontinuous.range(..media_sequence).count();
+
+    let mut tagged = String::with_capacity(playlist.len());
+    let mut sequence = media_sequence;
+    let mut already_tagged = false;
+    for line in playlist.lines() {
+        let trimmed = line.trim();
+        if trimmed == "#EXT-X-DISCONTINUITY" {
+            already_tagged = true;
+        } else if trimmed.starts_with("#EXTINF:") {
+            if discontinuous.contains(&sequence) && !already_tagged {
+                tagged.push_str("#EXT-X-DISCONTINUITY\n");
+            }
+            sequence += 1;
+            already_tagged = false;
+        }
+        tagged.push_str(line);
+        tagged.push('\n');
+        if trimmed.starts_with("#EXT-X-MEDIA-SEQUENCE:") && dropped > 0 {
+            let _ = writeln!(tagged, "#EXT-X-DISCONTINUITY-SEQUENCE:{dropped}");
+        }
+    }
+    tagged
+}
+
+/// Appends segment records to an index file, one JSON object per line.
+pub(crate) struct SegmentIndexWriter {
+    file: File,
+}
+
+impl SegmentIndexWriter {
+    /// Opens the index for `playlist_path`, truncating any index of an earlier recording.
+    pub(crate) fn create(playlist_path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(segment_index_path(playlist_path))?;
+        Ok(Self { file })
+    }
+
+    /// Opens the index for `playlist_path` to add to the records of an earlier recording.
+    pub(crate) fn open_existing(playlist_path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(segment_index_path(playlist_path))?;
+        Ok(Self { file })
+    }
+
+    pub(crate) fn append(&mut self, record: &SegmentRecord) -> io::Result<()> {
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())
+    }
+}
+
+/// Follows the media playlist written by FFmpeg's `hls` muxer and indexes each new segment.
+pub(crate) struct PlaylistIndexer {
+    writer: SegmentIndexWriter,
+    next_sequence: u64,
+    /// Media time, since the first packet, at which the next new segment starts.
+    media_end: f64,
+    /// Media times of timestamp discontinuities not yet assigned to a segment.
+    pending_discontinuities: Vec<f64>,
+    /// Sequence numbers of the segments that follow a discontinuity.
+    discontinuous: BTreeSet<u64>,
+}
+
+impl PlaylistIndexer {
+    pub(crate) fn create(playlist_path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            writer: SegmentIndexWriter::create(playlist_path)?,
+            next_sequence: 0,
+            media_end: 0.0,
+            pending_discontinuities: Vec::new(),
+            discontinuous: BTreeSet::new(),
+        })
+    }
+
+    /// Continues the index of a recovered playlist; segments before `next_sequence` belong to the
+    /// earlier recording and are already indexed.
+    pub(crate) fn resume(playlist_path: &Path, next_sequence: u64) -> io::Result<Self> {
+        Ok(Self {
+            writer: SegmentIndexWriter::open_existing(playlist_path)?,
+            next_sequence,
+            media_end: 0.0,
+            pending_discontinuities: Vec::new(),
+            discontinuous: BTreeSet::new(),
+        })
+    }
+
+    /// Notes a discontinuity at `media_seconds`; the first segment starting after it is tagged.
+    pub(crate) fn mark_discontinuity(&mut self, media_seconds: f64) {
+        self.pending_discontinuities.push(media_seconds);
+    }
+
+    /// Sequence number of the next segment the playlist will list.
+    pub(crate) fn next_sequence(&self) -> u64 {
+        self.next_sequence
+    }
+
+    /// Sequence numbers of the segments that follow a discontinuity.
+    pub(crate) fn discontinuous(&self) -> &BTreeSet<u64> {
+        &self.discontinuous
+    }
+
+    /// Records the segments of `playlist` not seen before, dating each with `epoch_at`.
+    pub(crate) fn update(
+        &mut self,
+        playlist: &str,
+        epoch_at: impl Fn(f64) -> Option<f64>,
+    ) -> io::Result<()> {
+        let (media_sequence, entries) = media_playlist_entries(playlist);
+        for (sequence, entry) in (media_sequence..).zip(entries) {
+            if sequence < self.next_sequence {
+                continue;
+            }
+            let media_start = self.media_end;
+            let pending = self.pending_discontinuities.len();
+            // `EXTINF` durations are rounded, so allow a millisecond of slack at the boundary.
+            self.pending_discontinuities
+                .retain(|&discontinuity| discontinuity > media_start + 0.001);
+            let discontinuity = entry.discontinuity || self.pending_discontinuities.len() < pending;
+            if discontinuity {
+                self.discontinuous.insert(sequence);
+            }
+            if let Some(start) = epoch_at(media_start) {
+                self.writer.append(&SegmentRecord {
+                    uri: entry.uri,
+                    sequence,
+                    start,
+                    duration: entry.duration,
+                    discontinuity,
+                })?;
+            }
+            self.media_end += entry.duration;
+            self.next_sequence = sequence + 1;
+        }
+        Ok(())
+    }
+}
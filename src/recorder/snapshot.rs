@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use ffmpeg_next::format::Pixel;
+use ffmpeg_next::software::scaling;
+use ffmpeg_next::{Packet, Rational, codec, encoder, frame};
+use serde::Deserialize;
+
+use super::RecorderError;
+use super::ll_hls::write_atomically;
+use super::program_date_time::{format_program_date_time, now_epoch_seconds};
+
+/// JPEG quantizer used when `quality` is not configured.
+const DEFAULT_QUALITY: u32 = 4;
+
+/// Periodic or on-demand JPEG stills of a camera.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SnapshotOptions {
+    /// Directory receiving `latest.jpg` and, when archiving, every snapshot taken.
+    pub dir: PathBuf,
+    /// Seconds between snapshots. Only on-demand snapshots are taken when unset.
+    #[serde(default)]
+    pub interval_seconds: Option<f64>,
+    /// Keep every snapshot as `snapshot_<UTC time>.jpg`, with milliseconds, next to `latest.jpg`.
+    #[serde(default)]
+    pub archive: bool,
+    /// Snapshot width in pixels, keeping the aspect ratio. Defaults to the source width.
+    #[serde(default)]
+    pub width: Option<u32>,
+    /// JPEG quantizer from 2 (best) to 31. Defaults to 4.
+    #[serde(default)]
+    pub quality: Option<u32>,
+}
+
+impl SnapshotOptions {
+    /// Path of the most recent snapshot, replaced atomically on every capture.
+    pub fn latest_path(&self) -> PathBuf {
+        self.dir.join("latest.jpg")
+    }
+}
+
+/// File name of a snapshot archived at `epoch_seconds`, e.g. `snapshot_20240501T120000.250Z.jpg`.
+///
+/// The milliseconds keep snapshots taken within the same second, by sub-second intervals or
+/// on-demand requests, from replacing each other.
+pub fn snapshot_archive_name(epoch_seconds: f64) -> String {
+    let time = format_program_date_time(epoch_seconds).replace(['-', ':'], "");
+    format!("snapshot_{time}.jpg")
+}
+
+/// Picture size of a snapshot of a `source_width` x `source_height` frame, scaled to `width`
+/// with the source aspect ratio and rounded to even values.
+pub fn snapshot_size(source_width: u32, source_height: u32, width: Option<u32>) -> (u32, u32) {
+    let Some(width) = width else {
+        return (source_width, source_height);
+    };
+    let height = u64::from(source_height) * u64::from(width) / u64::from(source_width.max(1));
+    ((width & !1).max(2), (height as u32 & !1).max(2))
+}
+
+/// Picks the frames that become snapshots.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotSchedule {
+    interval: Option<f64>,
+    next_due: Option<f64>,
+}
+
+impl SnapshotSchedule {
+    /// Takes a snapshot every `interval_seconds` of media time; non-positive intervals disable
+    /// periodic snapshots.
+    pub fn new(interval_seconds: Option<f64>) -> Self {
+        Self {
+            interval: interval_seconds.filter(|seconds| *seconds > 0.0),
+            next_due: None,
+        }
+    }
+
+    /// Whether the frame at media time `seconds` is captured, given whether a snapshot was
+    /// requested on demand. The next periodic snapshot is due one interval after any capture.
+    pub fn take(&mut self, seconds: f64, requested: bool) -> bool {
+        let periodic = self.interval.is_some() && self.next_due.is_none_or(|due| seconds >= due);
+        if !periodic && !requested {
+            return false;
+        }
+        self.next_due = self.interval.map(|interval| seconds + interval);
+        true
+    }
+}
+
+/// A camera's latest snapshot together with its pending on-demand request.
+#[derive(Debug)]
+pub struct SnapshotSlot {
+    latest: PathBuf,
+    requested: AtomicBool,
+    taken: AtomicU64,
+}
+
+impl SnapshotSlot {
+    /// Path of the camera's `latest.jpg`.
+    pub fn latest_path(&self) -> &Path {
+        &self.latest
+    }
+
+    /// Asks the recorder to capture the next decoded frame.
+    pub fn request(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+    }
+
+    /// Clears and returns the pending request.
+    pub fn take_request(&self) -> bool {
+        self.requested.swap(false, Ordering::SeqCst)
+    }
+
+    /// Number of snapshots written so far; it changes once a requested snapshot is on disk.
+    pub fn taken(&self) -> u64 {
+        self.taken.load(Ordering::SeqCst)
+    }
+
+    fn mark_taken(&self) {
+        self.taken.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Snapshot slots of the recorded cameras, shared between recorders and the HTTP server.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotHub {
+    slots: Arc<Mutex<HashMap<String, Arc<SnapshotSlot>>>>,
+}
+
+impl SnapshotHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `camera_id`'s snapshots at `latest`, replacing an earlier registration.
+    ///
+    /// Slots outlive the recording, so the last snapshot stays available after it ends.
+    pub fn register(&self, camera_id: &str, latest: PathBuf) -> Arc<SnapshotSlot> {
+        let slot = Arc::new(SnapshotSlot {
+            latest,
+            requested: AtomicBool::new(false),
+            taken: AtomicU64::new(0),
+        });
+        lock(&self.slots).insert(camera_id.to_string(), Arc::clone(&slot));
+        slot
+    }
+
+    /// Slot of `camera_id`, if it records snapshots.
+    pub fn slot(&self, camera_id: &str) -> Option<Arc<SnapshotSlot>> {
+        lock(&self.slots).get(camera_id).cloned()
+    }
+}
+
+/// Captures decoded frames of one camera as JPEG snapshots.
+pub(crate) struct Snapshotter {
+    options: SnapshotOptions,
+    schedule: SnapshotSchedule,
+    slot: Option<Arc<SnapshotSlot>>,
+    jpeg: JpegEncoder,
+}
+
+impl Snapshotter {
+    /// Creates the snapshot directory; `slot` receives on-demand requests from the HTTP server.
+    pub(crate) fn new(
+        options: &SnapshotOptions,
+        slot: Option<Arc<SnapshotSlot>>,
+    ) -> Result<Self, RecorderError> {
+        fs::create_dir_all(&options.dir)?;
+        Ok(Self {
+            options: options.clone(),
+            schedule: SnapshotSchedule::new(options.interval_seconds),
+            slot,
+            jpeg: JpegEncoder::new(options.width, options.quality.unwrap_or(DEFAULT_QUALITY)),
+        })
+    }
+
+    /// Captures `frame` when a snapshot is due. Failures are reported without stopping the
+    /// recording.
+    pub(crate) fn offer(&mut self, frame: &frame::Video, time_base: Rational) {
+        let seconds = frame.pts().unwrap_or_default() as f64 * f64::from(time_base);
+        let requested = self.slot.as_ref().is_some_and(|slot| slot.take_request());
+        if !self.schedule.take(seconds, requested) {
+            return;
+        }
+        match self.capture(frame) {
+            Ok(()) => {
+                if let Some(slot) = &self.slot {
+                    slot.mark_taken();
+                }
+            }
+            Err(error) => eprintln!(
+                "Failed to write snapshot to {}: {error}",
+                self.options.dir.display()
+            ),
+        }
+    }
+
+    fn capture(&mut self, frame: &frame::Video) -> Result<(), RecorderError> {
+        let jpeg = self.jpeg.encode(frame)?;
+        write_atomically(&self.options.latest_path(), &jpeg)?;
+        if self.options.archive {
+            let name = snapshot_archive_name(now_epoch_seconds());
+            fs::write(self.options.dir.join(name), &jpeg)?;
+        }
+        Ok(())
+    }
+}
+
+/// Encodes single frames to JPEG with FFmpeg's MJPEG encoder, scaling them on the way.
+pub(crate) struct JpegEncoder {
+    width: Option<u32>,
+    quality: u32,
+    /// Input size and format the scaler was built for, and the scaled size.
+    scaler: Option<((u32, u32, Pixel), (u32, u32), scaling::Context)>,
+    encoder: Option<encoder::Video>,
+    scaled: frame::Video,
+    frames: i64,
+}
+
+impl JpegEncoder {
+    /// Scales pictures to `width` (the source width when unset) and encodes them with quantizer
+    /// `quality`.
+    pub(crate) fn new(width: Option<u32>, quality: u32) -> Self {
+        Self {
+            width,
+            quality: quality.clamp(2, 31),
+            scaler: None,
+            encoder: None,
+            scaled: frame::Video::empty(),
+            frames: 0,
+        }
+    }
+
+    /// Encodes `frame` into a complete JPEG file.
+    pub(crate) fn encode(&mut self, frame: &frame::Video) -> Result<Vec<u8>, RecorderError> {
+        let input = (frame.width(), frame.height(), frame.format());
+        let size = snapshot_size(frame.width(), frame.height(), self.width);
+        if self
+            .scaler
+            .as_ref()
+            .is_none_or(|(built, scaled, _)| *built != input || *scaled != size)
+        {
+            let context = scaling::Context::get(
+                input.2,
+                input.0,
+                input.1,
+                Pixel::YUVJ420P,
+                size.0,
+                size.1,
+                scaling::Flags::BICUBIC,
+            )?;
+            self.scaler = Some((input, size, context));
+        }
+        if self
+            .encoder
+            .as_ref()
+            .is_none_or(|encoder| (encoder.width(), encoder.height()) != size)
+        {
+            self.encoder = Some(open_jpeg_encoder(size, self.quality)?);
+        }
+        let (Some((_, _, scaler)), Some(encoder)) = (self.scaler.as_mut(), self.encoder.as_mut())
+        else {
+            unreachable!("scaler and encoder are set up above");
+        };
+
+        scaler.run(frame, &mut self.scaled)?;
+        self.scaled.set_pts(Some(self.frames));
+        self.frames += 1;
+        encoder.send_frame(&self.scaled)?;
+        let mut packet = Packet::empty();
+        encoder.receive_packet(&mut packet)?;
+        Ok(packet.data().unwrap_or_default().to_vec())
+    }
+}
+
+/// Opens an intra-only MJPEG encoder whose quantizer is pinned to `quality`.
+fn open_jpeg_encoder(
+    (width, height): (u32, u32),
+    quality: u32,
+) -> Result<encoder::Video, RecorderError> {
+    let codec = encoder::find(codec::Id::MJPEG).ok_or_else(|| {
+        RecorderError::InvalidOutput("FFmpeg build does not provide the MJPEG encoder".to_string())
+    })?;
+    let mut context = codec::context::Context::new_with_codec(codec)
+        .encoder()
+        .video()?;
+    context.set_width(width);
+    context.set_height(height);
+    context.set_format(Pixel::YUVJ420P);
+    context.set_time_base(Rational(1, 25));
+    context.set_qmin(quality as i32);
+    context.set_qmax(quality as i32);
+    Ok(context.open()?)
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,81 @@
+use super::program_date_time::now_epoch_seconds;
+
+/// Origin of a packet's wall-clock time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClockSource {
+    /// NTP time from the camera's RTCP sender reports.
+    Rtcp,
+    /// Local clock at arrival, used when the session carries no sender reports.
+    Local,
+}
+
+/// Wall-clock time, in seconds since the Unix epoch, of an `AV_PKT_DATA_PRFT` side data block.
+///
+/// FFmpeg's RTP demuxer attaches one to every packet once a sender report has arrived, mapping
+/// the packet's RTP timestamp through the report's NTP time.
+pub fn producer_reference_time(side_data: &[u8]) -> Option<f64> {
+    // `AVProducerReferenceTime` starts with the wall clock as an `int64_t` in microseconds.
+    let wallclock = i64::from_ne_bytes(side_data.get(..8)?.try_into().ok()?);
+    (wallclock > 0).then(|| wallclock as f64 / 1_000_000.0)
+}
+
+/// Assigns a wall-clock time to every input packet.
+///
+/// Per-packet sender report mappings take precedence, followed by the session's NTP start time.
+/// Streams without either fall back to the local clock at their first packet.
+#[derive(Debug, Clone)]
+pub struct SourceClock {
+    realtime_base: Option<f64>,
+    /// Per input stream: wall-clock time minus presentation time, and where it came from.
+    offsets: Vec<Option<(f64, ClockSource)>>,
+}
+
+impl SourceClock {
+    /// Creates a clock for `nb_streams` input streams. `realtime_base` is the wall-clock time of
+    /// input timestamp zero, as reported by the RTSP demuxer.
+    pub fn new(nb_streams: usize, realtime_base: Option<f64>) -> Self {
+        Self {
+            realtime_base,
+            offsets: vec![None; nb_streams],
+        }
+    }
+
+    /// Forgets a local-clock estimate for `stream` after its timestamps jumped, so the next
+    /// packet is timed from its arrival again. Sender report mappings stay valid.
+    pub fn discontinuity(&mut self, stream: usize) {
+        if let Some(offset) = self.offsets.get_mut(stream)
+            && matches!(offset, Some((_, ClockSource::Local)))
+        {
+            *offset = None;
+        }
+    }
+
+    /// Wall-clock time of a packet presented at `pts_seconds` on input stream `stream`.
+    ///
+    /// `reference` is the packet's producer reference time, when it carries one.
+    pub fn wall_clock(
+        &mut self,
+        stream: usize,
+        pts_seconds: f64,
+        reference: Option<f64>,
+    ) -> (f64, ClockSource) {
+        if stream >= self.offsets.len() {
+            self.offsets.resize(stream + 1, None);
+        }
+
+        let (offset, source) = match (reference, self.offsets[stream]) {
+            (Some(reference), _) => (reference - pts_seconds, ClockSource::Rtcp),
+            (None, Some(known)) => known,
+            (None, None) => self
+                .offsets
+                .iter()
+                .flatten()
+                .copied()
+                .find(|&(_, source)| source == ClockSource::Rtcp)
+                .or(self.realtime_base.map(|base| (base, ClockSource::Rtcp)))
+                .unwrap_or((now_epoch_seconds() - pts_seconds, ClockSource::Local)),
+        };
+        self.offsets[stream] = Some((offset, source));
+        (pts_seconds + offset, source)
+    }
+}
@@ -0,0 +1,103 @@
+use ffmpeg_next::{format, media};
+use serde::Deserialize;
+
+use super::RecorderError;
+
+/// Rule choosing which input streams of one media type are recorded.
+///
+/// Deserializes from `"all"`, `"first"`, `"none"`, `{"index": 2}`, `{"codec": "h264"}` or
+/// `{"language": "eng"}`.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StreamSelector {
+    /// Record every stream of this type.
+    #[default]
+    All,
+    /// Record only the first stream of this type, if there is one.
+    First,
+    /// Record no stream of this type.
+    None,
+    /// Record the stream with this absolute input index.
+    Index(usize),
+    /// Record the first stream encoded with this codec (FFmpeg codec name, e.g. `h264`, `pcm_alaw`).
+    Codec(String),
+    /// Record the first stream tagged with this language (e.g. `eng`).
+    Language(String),
+}
+
+/// Per-media-type stream selection for a recording.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize)]
+pub struct StreamSelection {
+    /// Which video streams to record.
+    #[serde(default)]
+    pub video: StreamSelector,
+    /// Which audio streams to record.
+    #[serde(default)]
+    pub audio: StreamSelector,
+}
+
+/// Properties of an input stream that selectors match against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamInfo {
+    /// Absolute index of the stream within the input.
+    pub index: usize,
+    /// Media type of the stream.
+    pub medium: media::Type,
+    /// FFmpeg codec name, e.g. `h264` or `pcm_mulaw`.
+    pub codec: String,
+    /// Language tag from the stream metadata, when present.
+    pub language: Option<String>,
+}
+
+impl StreamInfo {
+    pub fn from_stream(stream: &format::stream::Stream) -> Self {
+        let parameters = stream.parameters();
+        Self {
+            index: stream.index(),
+            medium: parameters.medium(),
+            codec: parameters.id().name().to_string(),
+            language: stream.metadata().get("language").map(str::to_string),
+        }
+    }
+}
+
+impl StreamSelection {
+    /// Returns the input indices to record, in input order.
+    pub fn select(&self, streams: &[StreamInfo]) -> Result<Vec<usize>, RecorderError> {
+        let mut selected = self.video.select(media::Type::Video, streams)?;
+        selected.extend(self.audio.select(media::Type::Audio, streams)?);
+        selected.sort_unstable();
+        Ok(selected)
+    }
+}
+
+impl StreamSelector {
+    fn select(
+        &self,
+        medium: media::Type,
+        streams: &[StreamInfo],
+    ) -> Result<Vec<usize>, RecorderError> {
+        let mut candidates = streams.iter().filter(|stream| stream.medium == medium);
+        let chosen = match self {
+            StreamSelector::All => return Ok(candidates.map(|stream| stream.index).collect()),
+            StreamSelector::None => return Ok(Vec::new()),
+            StreamSelector::First => {
+                return Ok(candidates.next().map(|s| s.index).into_iter().collect());
+            }
+            StreamSelector::Index(index) => candidates.find(|stream| stream.index == *index),
+            StreamSelector::Codec(codec) => {
+                candidates.find(|stream| stream.codec.eq_ignore_ascii_case(codec))
+            }
+            StreamSelector::Language(language) => candidates.find(|stream| {
+                stream
+                    .language
+                    .as_deref()
+                    .is_some_and(|tag| tag.eq_ignore_ascii_case(language))
+            }),
+        };
+
+        chosen.map(|stream| vec![stream.index]).ok_or_else(|| {
+            RecorderError::StreamNotFound(format!("{medium:?} stream matching {self:?}"))
+        })
+    }
+}
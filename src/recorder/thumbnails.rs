@@ -0,0 +1,372 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use ffmpeg_next::format::Pixel;
+use ffmpeg_next::software::scaling;
+use ffmpeg_next::{Rational, frame};
+use serde::Deserialize;
+
+use super::ll_hls::write_atomically;
+use super::snapshot::JpegEncoder;
+use super::{HlsOutput, RecorderError, media_playlist_entries, snapshot_size};
+
+/// `DATA-ID` of the `EXT-X-SESSION-DATA` tag pointing players at the thumbnails track.
+pub const THUMBNAILS_DATA_ID: &str = "com.otnvr.thumbnails";
+
+/// Sprite sheet quantizer used when `quality` is not configured.
+const DEFAULT_QUALITY: u32 = 5;
+
+/// Preview thumbnails of an HLS output, packed into JPEG sprite sheets and indexed by a WebVTT
+/// track for scrubbing.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ThumbnailOptions {
+    /// Seconds of media time between thumbnails. Defaults to the segment duration, giving one
+    /// thumbnail per segment.
+    #[serde(default)]
+    pub interval_seconds: Option<f64>,
+    /// Thumbnail width in pixels, keeping the aspect ratio. Defaults to 160.
+    #[serde(default = "default_width")]
+    pub width: u32,
+    /// Thumbnails per sprite sheet row. Defaults to 10.
+    #[serde(default = "default_grid")]
+    pub columns: u32,
+    /// Rows per sprite sheet. Defaults to 10.
+    #[serde(default = "default_grid")]
+    pub rows: u32,
+    /// JPEG quantizer of the sprite sheets from 2 (best) to 31. Defaults to 5.
+    #[serde(default)]
+    pub quality: Option<u32>,
+}
+
+fn default_width() -> u32 {
+    160
+}
+
+fn default_grid() -> u32 {
+    10
+}
+
+/// Location of the WebVTT thumbnails track of the playlist at `playlist_path`,
+/// `<playlist>_thumbnails.vtt` next to it.
+pub fn thumbnail_track_path(playlist_path: &Path) -> PathBuf {
+    let stem = playlist_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("stream");
+    playlist_path.with_file_name(format!("{stem}_thumbnails.vtt"))
+}
+
+/// Location of the master playlist announcing the thumbnails track of a single playlist at
+/// `playlist_path`, `<playlist>_master.m3u8` next to it.
+pub fn thumbnail_master_path(playlist_path: &Path) -> PathBuf {
+    let stem = playlist_path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or("stream");
+    playlist_path.with_file_name(format!("{stem}_master.m3u8"))
+}
+
+/// Name of sprite sheet `index`, `<stem>_sprite_<index>.jpg`.
+pub fn sprite_sheet_name(stem: &str, index: u64) -> String {
+    format!("{stem}_sprite_{index:05}.jpg")
+}
+
+/// Index following the last sprite sheet referenced by a thumbnails track, so a continued
+/// recording never overwrites the sheets of an earlier run.
+pub fn next_sprite_sheet(track: &str) -> u64 {
+    track
+        .lines()
+        .filter_map(|line| line.split_once("#xywh="))
+        .filter_map(|(uri, _)| {
+            uri.strip_suffix(".jpg")?
+                .rsplit_once('_')?
+                .1
+                .parse::<u64>()
+                .ok()
+        })
+        .max()
+        .map_or(0, |index| index + 1)
+}
+
+/// Formats `seconds` as a WebVTT timestamp, e.g. `01:02:03.500`.
+pub fn vtt_timestamp(seconds: f64) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// Adds an `EXT-X-SESSION-DATA` tag naming the thumbnails track at `uri` below the `#EXTM3U`
+/// line of the master playlist `playlist`, unless it is already there. Media playlists must not
+/// carry the tag.
+pub fn insert_thumbnail_reference(playlist: &str, uri: &str) -> String {
+    if playlist.contains(THUMBNAILS_DATA_ID) {
+        return playlist.to_string();
+    }
+    let tag = format!("#EXT-X-SESSION-DATA:DATA-ID=\"{THUMBNAILS_DATA_ID}\",URI=\"{uri}\"\n");
+    match playlist.split_once('\n') {
+        Some((header, rest)) => format!("{header}\n{tag}{rest}"),
+        None => format!("{playlist}\n{tag}"),
+    }
+}
+
+/// Where one thumbnail goes: a sprite sheet cell and the media time its cue covers.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThumbnailCell {
+    /// Sprite sheet index.
+    pub sheet: u64,
+    /// Column within the sheet, from 0.
+    pub column: u32,
+    /// Row within the sheet, from 0.
+    pub row: u32,
+    /// Cue start, in seconds of playlist time.
+    pub start: f64,
+    /// Cue end, in seconds of playlist time.
+    pub end: f64,
+}
+
+impl ThumbnailCell {
+    /// WebVTT cue showing this cell of `sheet_uri`, for thumbnails of `width` x `height`.
+    pub fn cue(&self, sheet_uri: &str, (width, height): (u32, u32)) -> String {
+        format!(
+            "{} --> {}\n{sheet_uri}#xywh={},{},{width},{height}\n\n",
+            vtt_timestamp(self.start),
+            vtt_timestamp(self.end),
+            self.column * width,
+            self.row * height
+        )
+    }
+}
+
+/// Assigns thumbnails to sprite sheet cells on a fixed grid of playlist time.
+///
+/// Every interval gets at most one thumbnail, taken from its first frame. Intervals without
+/// frames, e.g. during an outage, get no cue and leave their cell empty.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThumbnailGrid {
+    interval: f64,
+    per_row: u32,
+    per_sheet: u64,
+    /// Playlist time of media time zero, when continuing an earlier recording's track.
+    offset: f64,
+    first_sheet: u64,
+    first_slot: Option<u64>,
+    last_slot: Option<u64>,
+}
+
+impl ThumbnailGrid {
+    /// Grid of one thumbnail every `interval_seconds`, on sheets of `columns` x `rows`.
+    pub fn new(interval_seconds: f64, columns: u32, rows: u32) -> Self {
+        let columns = columns.max(1);
+        Self {
+            interval: interval_seconds,
+            per_row: columns,
+            per_sheet: u64::from(columns) * u64::from(rows.max(1)),
+            offset: 0.0,
+            first_sheet: 0,
+            first_slot: None,
+            last_slot: None,
+        }
+    }
+
+    /// Continues a track whose playlist already lasts `offset` seconds, starting at sprite sheet
+    /// `first_sheet`.
+    pub fn continuing(mut self, offset: f64, first_sheet: u64) -> Self {
+        self.offset = offset;
+        self.first_sheet = first_sheet;
+        self
+    }
+
+    /// Cell of the frame at `media_seconds` since the recording started, or `None` when its
+    /// interval already has a thumbnail.
+    pub fn place(&mut self, media_seconds: f64) -> Option<ThumbnailCell> {
+        let seconds = self.offset + media_seconds.max(0.0);
+        let slot = (seconds / self.interval) as u64;
+        if self.last_slot.is_some_and(|last| slot <= last) {
+            return None;
+        }
+        self.last_slot = Some(slot);
+
+        let position = slot - *self.first_slot.get_or_insert(slot);
+        let cell = position % self.per_sheet;
+        let start = slot as f64 * self.interval;
+        Some(ThumbnailCell {
+            sheet: self.first_sheet + position / self.per_sheet,
+            column: (cell % u64::from(self.per_row)) as u32,
+            row: (cell / u64::from(self.per_row)) as u32,
+            start: start.max(self.offset),
+            end: start + self.interval,
+        })
+    }
+}
+
+/// Renders decoded frames of one HLS output into sprite sheets and its WebVTT thumbnails track.
+pub(crate) struct Thumbnailer {
+    grid: ThumbnailGrid,
+    track_path: PathBuf,
+    directory: PathBuf,
+    stem: String,
+    width: u32,
+    columns: u32,
+    rows: u32,
+    /// Thumbnail size, fixed by the first frame.
+    size: Option<(u32, u32)>,
+    /// Input size and format the scaler was built for.
+    scaler: Option<((u32, u32, Pixel), scaling::Context)>,
+    thumbnail: frame::Video,
+    /// Sprite sheet being filled, with its index.
+    sheet: Option<(u64, frame::Video)>,
+    jpeg: JpegEncoder,
+    /// Input time of the first frame offered, in seconds.
+    origin: Option<f64>,
+}
+
+impl Thumbnailer {
+    /// Starts the thumbnails track of `hls_output`, continuing the earlier one when the output
+    /// `resumed` an interrupted or appended playlist.
+    pub(crate) fn new(
+        hls_output: &HlsOutput,
+        options: &ThumbnailOptions,
+        resumed: bool,
+    ) -> Result<Self, RecorderError> {
+        let interval = options
+            .interval_seconds
+            .filter(|seconds| *seconds > 0.0)
+            .unwrap_or(f64::from(hls_output.segment_duration.unwrap_or(2)));
+        let mut grid = ThumbnailGrid::new(interval, options.columns, options.rows);
+
+        let track_path = thumbnail_track_path(&hls_output.playlist_path);
+        // The playlist of a continued recording already lists the earlier run's segments.
+        let track = fs::read_to_string(&track_path).ok().filter(|_| resumed);
+        if resumed {
+            let (_, entries) =
+                media_playlist_entries(&fs::read_to_string(&hls_output.playlist_path)?);
+            let offset = entries.iter().map(|entry| entry.duration).sum();
+            grid = grid.continuing(offset, track.as_deref().map_or(0, next_sprite_sheet));
+        }
+        if track.is_none() {
+            write_atomically(&track_path, b"WEBVTT\n\n")?;
+        }
+
+        Ok(Self {
+            grid,
+            directory: track_path
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+            track_path,
+            stem: hls_output
+                .playlist_path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or("stream")
+                .to_string(),
+            width: options.width,
+            columns: options.columns.max(1),
+            rows: options.rows.max(1),
+            size: None,
+            scaler: None,
+            thumbnail: frame::Video::empty(),
+            sheet: None,
+            jpeg: JpegEncoder::new(None, options.quality.unwrap_or(DEFAULT_QUALITY)),
+            origin: None,
+        })
+    }
+
+    /// Adds `frame` to the current sprite sheet when its interval has no thumbnail yet.
+    /// Failures are reported without stopping the recording.
+    pub(crate) fn offer(&mut self, frame: &frame::Video, time_base: Rational) {
+        let seconds = frame.pts().unwrap_or_default() as f64 * f64::from(time_base);
+        let origin = *self.origin.get_or_insert(seconds);
+        let Some(cell) = self.grid.place(seconds - origin) else {
+            return;
+        };
+        if let Err(error) = self.capture(frame, cell) {
+            eprintln!(
+                "Failed to write thumbnail to {}: {error}",
+                self.track_path.display()
+            );
+        }
+    }
+
+    fn capture(&mut self, frame: &frame::Video, cell: ThumbnailCell) -> Result<(), RecorderError> {
+        let size = *self
+            .size
+            .get_or_insert_with(|| snapshot_size(frame.width(), frame.height(), Some(self.width)));
+        let input = (frame.width(), frame.height(), frame.format());
+        if self
+            .scaler
+            .as_ref()
+            .is_none_or(|(built, _)| *built != input)
+        {
+            let context = scaling::Context::get(
+                input.2,
+                input.0,
+                input.1,
+                Pixel::YUVJ420P,
+                size.0,
+                size.1,
+                scaling::Flags::BICUBIC,
+            )?;
+            self.scaler = Some((input, context));
+        }
+        if let Some((_, scaler)) = self.scaler.as_mut() {
+            scaler.run(frame, &mut self.thumbnail)?;
+        }
+
+        if self
+            .sheet
+            .as_ref()
+            .is_none_or(|(index, _)| *index != cell.sheet)
+        {
+            let mut sheet =
+                frame::Video::new(Pixel::YUVJ420P, self.columns * size.0, self.rows * size.1);
+            // Full-range black, so empty cells stay dark.
+            sheet.data_mut(0).fill(0);
+            sheet.data_mut(1).fill(128);
+            sheet.data_mut(2).fill(128);
+            self.sheet = Some((cell.sheet, sheet));
+        }
+        let Some((_, sheet)) = self.sheet.as_mut() else {
+            unreachable!("sheet is set up above");
+        };
+        paste(
+            sheet,
+            &self.thumbnail,
+            (cell.column * size.0) as usize,
+            (cell.row * size.1) as usize,
+        );
+
+        let sheet_name = sprite_sheet_name(&self.stem, cell.sheet);
+        let jpeg = self.jpeg.encode(sheet)?;
+        write_atomically(&self.directory.join(&sheet_name), &jpeg)?;
+        OpenOptions::new()
+            .append(true)
+            .open(&self.track_path)?
+            .write_all(cell.cue(&sheet_name, size).as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Copies the 4:2:0 picture `thumbnail` into `sheet` with its top-left corner at `x`, `y`.
+fn paste(sheet: &mut frame::Video, thumbnail: &frame::Video, x: usize, y: usize) {
+    for plane in 0..3 {
+        let shift = usize::from(plane > 0);
+        let width = thumbnail.plane_width(plane) as usize;
+        let height = thumbnail.plane_height(plane) as usize;
+        let source_stride = thumbnail.stride(plane);
+        let target_stride = sheet.stride(plane);
+        let source = thumbnail.data(plane);
+        let target = sheet.data_mut(plane);
+        for row in 0..height {
+            let from = row * source_stride;
+            let to = ((y >> shift) + row) * target_stride + (x >> shift);
+            target[to..to + width].copy_from_slice(&source[from..from + width]);
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ffmpeg_next::format::Pixel;
+use ffmpeg_next::software::scaling;
+use ffmpeg_next::{Dictionary, Packet, Rational, codec, decoder, encoder, format, frame, media};
+use serde::Deserialize;
+
+use super::video_transcoder::{encoder_options, find_encoder};
+use super::{
+    RecorderError, SegmentRecord, VideoCodec, init_segment_at, init_segments, read_segment_index,
+    segment_index_path, session_name, snapshot_size,
+};
+
+const SECONDS_PER_DAY: f64 = 86_400.0;
+
+/// A timelapse of a camera's recording, rendered on request or every night.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TimelapseOptions {
+    /// Directory receiving the timelapse videos.
+    pub dir: PathBuf,
+    /// Seconds of recording between sampled frames. Defaults to 60.
+    #[serde(default = "default_sample_interval")]
+    pub sample_interval_seconds: f64,
+    /// Frame rate of the timelapse video. Defaults to 30.
+    #[serde(default = "default_fps")]
+    pub fps: u32,
+    /// Video width in pixels, keeping the aspect ratio. Defaults to the source width.
+    #[serde(default)]
+    pub width: Option<u32>,
+    /// UTC time of day, e.g. `01:30`, at which a running recorder renders the previous day.
+    #[serde(default)]
+    pub nightly_at: Option<String>,
+}
+
+fn default_sample_interval() -> f64 {
+    60.0
+}
+
+fn default_fps() -> u32 {
+    30
+}
+
+/// One frame of a timelapse: the segment holding it and its offset from the segment start.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimelapseSample {
+    /// Segment URI, relative to the playlist.
+    pub uri: String,
+    /// Seconds from the start of the segment.
+    pub offset: f64,
+}
+
+/// Picks one frame every `interval` seconds of wall-clock time in `start..end` from the indexed
+/// segments `records`.
+///
+/// Sample times fall on a fixed grid from `start`; those inside recording gaps are skipped, so
+/// outages are cut from the timelapse.
+pub fn timelapse_samples(
+    records: &[SegmentRecord],
+    start: f64,
+    end: f64,
+    interval: f64,
+) -> Vec<TimelapseSample> {
+    let mut records: Vec<&SegmentRecord> = records
+        .iter()
+        .filter(|record| record.start < end && record.end() > start)
+        .collect();
+    records.sort_by(|left, right| left.start.total_cmp(&right.start));
+
+    let mut samples = Vec::new();
+    if interval <= 0.0 {
+        return samples;
+    }
+    let mut index = 0;
+    let mut time = start;
+    while time < end {
+        while records
+            .get(index)
+            .is_some_and(|record| record.end() <= time)
+        {
+            index += 1;
+        }
+        let Some(record) = records.get(index) else {
+            break;
+        };
+        if record.start <= time {
+            samples.push(TimelapseSample {
+                uri: record.uri.clone(),
+                offset: time - record.start,
+            });
+            time += interval;
+        } else {
+            // Jump over the gap to the first grid point inside the next segment.
+            time += ((record.start - time) / interval).ceil().max(1.0) * interval;
+        }
+    }
+    samples
+}
+
+/// File name of the timelapse of `camera_id` over `start..end`, e.g.
+/// `gate_20240501T000000Z-20240502T000000Z.mp4`.
+pub fn timelapse_file_name(camera_id: &str, start: f64, end: f64) -> String {
+    format!(
+        "{camera_id}_{}-{}.mp4",
+        session_name(start),
+        session_name(end)
+    )
+}
+
+/// Parses a UTC time of day given as `HH:MM` into seconds after midnight.
+pub fn parse_time_of_day(value: &str) -> Option<f64> {
+    let (hours, minutes) = value.trim().split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    (hours < 24 && minutes < 60).then(|| f64::from(hours * 3600 + minutes * 60))
+}
+
+/// First moment after `now` that falls at `time_of_day` seconds after UTC midnight.
+pub fn next_daily_run(now: f64, time_of_day: f64) -> f64 {
+    let today = (now / SECONDS_PER_DAY).floor() * SECONDS_PER_DAY + time_of_day;
+    if today > now {
+        today
+    } else {
+        today + SECONDS_PER_DAY
+    }
+}
+
+/// The UTC day before the one holding `epoch_seconds`, as a `start..end` window.
+pub fn previous_day(epoch_seconds: f64) -> (f64, f64) {
+    let midnight = (epoch_seconds / SECONDS_PER_DAY).floor() * SECONDS_PER_DAY;
+    (midnight - SECONDS_PER_DAY, midnight)
+}
+
+/// Renders the timelapse of the recording whose media playlist is at `playlist_path` over
+/// `start..end`, in seconds since the Unix epoch, into the MP4 file at `output_path`.
+///
+/// Segments are found through the wall-clock index and only those holding a sample are
+/// decoded. Returns the number of frames written.
+pub fn render_timelapse(
+    playlist_path: &Path,
+    start: f64,
+    end: f64,
+    options: &TimelapseOptions,
+    output_path: &Path,
+) -> Result<usize, RecorderError> {
+    ffmpeg_next::init()?;
+
+    let playlist = fs::read_to_string(playlist_path)?;
+    if playlist.contains("#EXT-X-KEY:") {
+        return Err(RecorderError::InvalidOutput(
+            "timelapses cannot be rendered from encrypted recordings".to_string(),
+        ));
+    }
+    let directory = playlist_path.parent().unwrap_or_else(|| Path::new(""));
+    let init_segments = init_segments(&playlist);
+    let records: Vec<SegmentRecord> = read_segment_index(&segment_index_path(playlist_path))?
+        .into_iter()
+        .filter(|record| directory.join(&record.uri).exists())
+        .collect();
+
+    let samples = timelapse_samples(&records, start, end, options.sample_interval_seconds);
+    if samples.is_empty() {
+        return Err(RecorderError::NoFootage(playlist_path.to_path_buf()));
+    }
+
+    if let Some(parent) = output_path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    let mut writer = TimelapseWriter::new(output_path, options);
+    let mut remaining = samples.as_slice();
+    while let Some(first) = remaining.first() {
+        let count = remaining
+            .iter()
+            .take_while(|sample| sample.uri == first.uri)
+            .count();
+        let (segment, rest) = remaining.split_at(count);
+        let offsets: Vec<f64> = segment.iter().map(|sample| sample.offset).collect();
+        let segment_path = directory.join(&first.uri);
+        // Sessions continuing an fMP4 playlist each have their own init segment.
+        let init_path = records
+            .iter()
+            .find(|record| record.uri == first.uri)
+            .and_then(|record| init_segment_at(&init_segments, record.sequence))
+            .map(|uri| directory.join(uri));
+        if let Err(error) =
+            sample_segment(&segment_path, init_path.as_deref(), &offsets, &mut writer)
+        {
+            // A damaged segment only costs its frames.
+            eprintln!("Skipping {} in timelapse: {error}", segment_path.display());
+        }
+        remaining = rest;
+    }
+    match writer.finish()? {
+        0 => Err(RecorderError::NoFootage(playlist_path.to_path_buf())),
+        frames => Ok(frames),
+    }
+}
+
+/// Decodes the segment at `path` and hands the first frame at or after each of `offsets`, in
+/// seconds from the segment's first frame, to `writer`.
+///
+/// fMP4 segments are read behind their init segment through FFmpeg's `concat` protocol.
+fn sample_segment(
+    path: &Path,
+    init_path: Option<&Path>,
+    offsets: &[f64],
+    writer: &mut TimelapseWriter,
+) -> Result<(), RecorderError> {
+    let mut ictx = match init_path {
+        Some(init_path) => format::input(&format!(
+            "concat:{}|{}",
+            init_path.display(),
+            path.display()
+        ))?,
+        None => format::input(&path)?,
+    };
+    let (stream_index, time_base, mut decoder) = {
+        let ist = ictx
+            .streams()
+            .best(media::Type::Video)
+            .ok_or(RecorderError::MissingMediaStreams)?;
+        let decoder = codec::context::Context::from_parameters(ist.parameters())?
+            .decoder()
+            .video()?;
+        (ist.index(), ist.time_base(), decoder)
+    };
+
+    let mut pending = offsets.iter().copied().peekable();
+    let mut first_pts = None;
+    let mut decoded = frame::Video::empty();
+    let mut last = frame::Video::empty();
+    let mut receive = |decoder: &mut decoder::Video,
+                       last: &mut frame::Video,
+                       writer: &mut TimelapseWriter|
+     -> Result<(), RecorderError> {
+        while decoder.receive_frame(&mut decoded).is_ok() {
+            let pts = decoded.timestamp().unwrap_or_default();
+            let seconds = (pts - *first_pts.get_or_insert(pts)) as f64 * f64::from(time_base);
+            while pending.next_if(|offset| *offset <= seconds).is_some() {
+                writer.write(&decoded)?;
+            }
+            std::mem::swap(last, &mut decoded);
+        }
+        Ok(())
+    };
+
+    for (stream, packet) in ictx.packets() {
+        if stream.index() != stream_index {
+            continue;
+        }
+        decoder.send_packet(&packet)?;
+        receive(&mut decoder, &mut last, writer)?;
+    }
+    decoder.send_eof()?;
+    receive(&mut decoder, &mut last, writer)?;
+
+    // Offsets past the last frame, e.g. from a rounded duration, take the last frame.
+    if last.width() > 0 {
+        for _ in pending {
+            writer.write(&last)?;
+        }
+    }
+    Ok(())
+}
+
+/// H.264 MP4 encoder of timelapse frames, opened with the size of the first frame.
+struct TimelapseWriter {
+    output_path: PathBuf,
+    fps: u32,
+    width: Option<u32>,
+    output: Option<(format::context::Output, encoder::Video)>,
+    /// Input size and format the scaler was built for.
+    scaler: Option<((u32, u32, Pixel), scaling::Context)>,
+    scaled: frame::Video,
+    frames: usize,
+}
+
+impl TimelapseWriter {
+    fn new(output_path: &Path, options: &TimelapseOptions) -> Self {
+        Self {
+            output_path: output_path.to_path_buf(),
+            fps: options.fps.max(1),
+            width: options.width,
+            output: None,
+            scaler: None,
+            scaled: frame::Video::empty(),
+            frames: 0,
+        }
+    }
+
+    fn write(&mut self, frame: &frame::Video) -> Result<(), RecorderError> {
+        if self.output.is_none() {
+            let size = snapshot_size(frame.width(), frame.height(), self.width);
+            self.output = Some(self.open(size)?);
+        }
+        let Some((octx, encoder)) = self.output.as_mut() else {
+            unreachable!("output is opened above");
+        };
+
+        // The camera may change resolution mid-recording; every frame keeps the first size.
+        let input = (frame.width(), frame.height(), frame.format());
+        if self
+            .scaler
+            .as_ref()
+            .is_none_or(|(built, _)| *built != input)
+        {
+            let context = scaling::Context::get(
+                input.2,
+                input.0,
+                input.1,
+                Pixel::YUV420P,
+                encoder.width(),
+                encoder.height(),
+                scaling::Flags::BICUBIC,
+            )?;
+            self.scaler = Some((input, context));
+        }
+        if let Some((_, scaler)) = self.scaler.as_mut() {
+            scaler.run(frame, &mut self.scaled)?;
+        }
+
+        self.scaled.set_pts(Some(self.frames as i64));
+        self.frames += 1;
+        encoder.send_frame(&self.scaled)?;
+        write_packets(octx, encoder, self.fps)
+    }
+
+    /// Opens the MP4 muxer and an H.264 encoder for `width` x `height` pictures.
+    fn open(
+        &self,
+        (width, height): (u32, u32),
+    ) -> Result<(format::context::Output, encoder::Video), RecorderError> {
+        let mut octx = format::output_as_with(&self.output_path, "mp4", Dictionary::new())?;
+        let codec = find_encoder(VideoCodec::H264)?;
+        let global_header = octx.format().flags().contains(format::Flags::GLOBAL_HEADER);
+
+        let mut context = codec::context::Context::new_with_codec(codec)
+            .encoder()
+            .video()?;
+        context.set_width(width);
+        context.set_height(height);
+        context.set_format(Pixel::YUV420P);
+        context.set_time_base(Rational(1, self.fps as i32));
+        context.set_frame_rate(Some(Rational(self.fps as i32, 1)));
+        context.set_gop(self.fps * 2);
+        if global_header {
+            context.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let encoder = context.open_with(encoder_options(VideoCodec::H264, None))?;
+
+        let mut ost = octx.add_stream(Some(codec))?;
+        ost.set_parameters(&encoder);
+        ost.set_time_base(Rational(1, self.fps as i32));
+        let mut header_options = Dictionary::new();
+        header_options.set("movflags", "+faststart");
+        octx.write_header_with(header_options)?;
+        Ok((octx, encoder))
+    }
+
+    /// Flushes the encoder and finalizes the file, returning the number of frames written.
+    fn finish(mut self) -> Result<usize, RecorderError> {
+        if let Some((octx, encoder)) = self.output.as_mut() {
+            encoder.send_eof()?;
+            write_packets(octx, encoder, self.fps)?;
+            octx.write_trailer()?;
+        }
+        Ok(self.frames)
+    }
+}
+
+/// Moves every packet the encoder has ready into the muxer.
+fn write_packets(
+    octx: &mut format::context::Output,
+    encoder: &mut encoder::Video,
+    fps: u32,
+) -> Result<(), RecorderError> {
+    let ost_time_base = octx
+        .stream(0)
+        .map(|stream| stream.time_base())
+        .ok_or(RecorderError::InvalidStreamMapping(0))?;
+    let mut packet = Packet::empty();
+    while encoder.receive_packet(&mut packet).is_ok() {
+        packet.rescale_ts(Rational(1, fps as i32), ost_time_base);
+        packet.set_stream(0);
+        packet.write_interleaved(octx)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use ffmpeg_next::Rational;
+
+/// Largest forward jump between consecutive packets of a stream that is kept as is, in seconds.
+const MAX_GAP_SECONDS: f64 = 10.0;
+/// How far timestamps may run backwards without rebasing the stream. Covers B-frame reordering of
+/// presentation timestamps when a stream carries no decode timestamps, and late or duplicated
+/// packets when it does.
+const REORDER_TOLERANCE_SECONDS: f64 = 1.0;
+
+/// Kind of timestamp anomaly found on an input stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampAnomaly {
+    /// Timestamps went backwards by more than the reorder tolerance.
+    Regression,
+    /// Timestamps jumped forward by more than the tolerated gap.
+    Gap,
+}
+
+/// A rebase applied to one input stream.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimestampCorrection {
+    /// Input stream index.
+    pub stream: usize,
+    /// What was wrong with the timestamps.
+    pub anomaly: TimestampAnomaly,
+    /// Size of the removed jump in seconds; negative for regressions.
+    pub jump_seconds: f64,
+}
+
+/// Keeps per-stream timestamps monotonic by rebasing each stream across jumps and regressions.
+///
+/// After a correction the stream continues one packet duration after its last good packet, so
+/// muxers never see non-monotonic decode timestamps. A decode timestamp that repeats or slightly
+/// trails the previous one is moved one tick past it instead, without a correction.
+#[derive(Debug, Clone)]
+pub struct TimestampSanitizer {
+    streams: Vec<StreamTimestamps>,
+    corrections: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct StreamTimestamps {
+    seconds_per_tick: f64,
+    /// Offset added to every timestamp of the stream, in stream ticks.
+    offset: i64,
+    /// Latest corrected decode timestamp, or the highest presentation timestamp without them.
+    last: Option<i64>,
+    /// Most recent positive step between packets, used to place the packet after a correction.
+    step: i64,
+}
+
+impl TimestampSanitizer {
+    /// Creates a sanitizer for input streams with the given time bases.
+    pub fn new(time_bases: &[Rational]) -> Self {
+        let streams = time_bases
+            .iter()
+            .map(|&time_base| StreamTimestamps {
+                seconds_per_tick: f64::from(time_base),
+                offset: 0,
+                last: None,
+                step: 1,
+            })
+            .collect();
+        Self {
+            streams,
+            corrections: 0,
+        }
+    }
+
+    /// Number of corrections applied so far.
+    pub fn corrections(&self) -> u64 {
+        self.corrections
+    }
+
+    /// Returns the corrected `(pts, dts)` of a packet on `stream`, and the correction when one
+    /// had to be applied.
+    pub fn sanitize(
+        &mut self,
+        stream: usize,
+        pts: Option<i64>,
+        dts: Option<i64>,
+    ) -> (Option<i64>, Option<i64>, Option<TimestampCorrection>) {
+        let Some(state) = self.streams.get_mut(stream) else {
+            return (pts, dts, None);
+        };
+        let Some(timestamp) = dts.or(pts) else {
+            return (pts, dts, None);
+        };
+        if state.seconds_per_tick <= 0.0 {
+            return (pts, dts, None);
+        }
+
+        let max_gap = (MAX_GAP_SECONDS / state.seconds_per_tick) as i64;
+        let reorder_tolerance = (REORDER_TOLERANCE_SECONDS / state.seconds_per_tick) as i64;
+
+        let mut corrected = timestamp + state.offset;
+        let mut correction = None;
+        // Ticks added to this packet alone to keep its decode timestamp after the previous one.
+        let mut nudge = 0;
+        if let Some(last) = state.last {
+            let delta = corrected - last;
+            let anomaly = if delta < -reorder_tolerance {
+                Some(TimestampAnomaly::Regression)
+            } else if delta > max_gap {
+                Some(TimestampAnomaly::Gap)
+            } else {
+                None
+            };
+
+            match anomaly {
+                Some(anomaly) => {
+                    let target = last + state.step;
+                    state.offset += target - corrected;
+                    correction = Some(TimestampCorrection {
+                        stream,
+                        anomaly,
+                        jump_seconds: (corrected - target) as f64 * state.seconds_per_tick,
+                    });
+                    corrected = target;
+                    self.corrections += 1;
+                }
+                None if delta > 0 => state.step = delta,
+                None if dts.is_some() => {
+                    nudge = 1 - delta;
+                    corrected = last + 1;
+                }
+                None => {}
+            }
+        }
+
+        state.last = Some(match (state.last, dts) {
+            (Some(last), None) => last.max(corrected),
+            _ => corrected,
+        });
+        let offset = state.offset;
+        let dts = dts.map(|dts| dts + offset + nudge);
+        let pts = pts.map(|pts| match dts {
+            // A nudged packet must not be presented before it is decoded.
+            Some(dts) if nudge > 0 => (pts + offset).max(dts),
+            _ => pts + offset,
+        });
+        (pts, dts, correction)
+    }
+}
@@ -0,0 +1,612 @@
+use ffmpeg_next::format::Pixel;
+use ffmpeg_next::software::scaling;
+use ffmpeg_next::{
+    Dictionary, Discard, Packet, Rational, codec, decoder, encoder, format, frame, picture,
+};
+
+use super::filter_graph::FilterChain;
+use super::frame_rate::FrameRateConverter;
+use super::master_playlist::{h264_codec_string, hevc_codec_string};
+use super::output_muxer::OutputMuxer;
+use super::overlay::{OverlayClock, drawtext_args};
+use super::privacy_mask::PrivacyMasker;
+use super::snapshot::Snapshotter;
+use super::thumbnails::Thumbnailer;
+use super::{
+    FrameRateMode, Output, OverlayOptions, RecorderError, ResolutionChange, VideoCodec,
+    VideoProcessing,
+};
+
+/// Decodes an input video stream once, applies per-recording processing, and feeds every encoder attached to it.
+pub(crate) struct VideoTranscoder {
+    decoder: decoder::Video,
+    encoders: Vec<VideoEncoder>,
+    frame_rate: Option<FrameRateConverter>,
+    masker: Option<PrivacyMasker>,
+    filter: Option<FilterChain>,
+    /// Filters of `filter`, kept to rebuild it when the source resolution changes.
+    chain: Vec<(&'static str, String)>,
+    /// Burned-in timestamp, re-anchored to the wall clock when input timestamps are rebased.
+    overlay: Option<Overlay>,
+    input_time_base: Rational,
+    frame_rate_mode: Option<FrameRateMode>,
+    /// Timestamp of the first decoded frame; filter input is rebased so the recording starts at zero.
+    first_pts: Option<i64>,
+    /// Picture size and format the filter and encoders are currently set up for.
+    geometry: Geometry,
+    /// Picture size and format of the latest decoded frame.
+    decoded: Geometry,
+    resolution_change: ResolutionChange,
+    /// Scales frames back to `geometry` once the camera switched resolution, in rescale mode.
+    normalizer: Option<FrameConverter>,
+    /// Captures processed frames as JPEG snapshots.
+    snapshotter: Option<Snapshotter>,
+    /// Render processed frames into outputs' thumbnail sprite sheets.
+    thumbnailers: Vec<Thumbnailer>,
+}
+
+/// Burned-in overlay settings together with the clock its timestamps count from.
+struct Overlay {
+    options: OverlayOptions,
+    camera_name: String,
+    clock: OverlayClock,
+}
+
+impl Overlay {
+    fn drawtext(&self) -> (&'static str, String) {
+        (
+            "drawtext",
+            drawtext_args(
+                &self.options,
+                &self.camera_name,
+                self.clock.start_epoch_seconds(),
+            ),
+        )
+    }
+}
+
+/// One encoded rendition of the processed frames, written to a single output.
+pub(crate) struct VideoEncoder {
+    encoder: encoder::Video,
+    /// Resizes processed frames when the output size or format differs from the source.
+    converter: FrameConverter,
+    scaled: frame::Video,
+    codec: VideoCodec,
+    /// Output whose codec, size and bitrate the encoder is opened with.
+    settings: Output,
+    /// Index of the muxer this encoder writes to.
+    output: usize,
+    ost_index: usize,
+    time_base: Rational,
+}
+
+/// Picture size and pixel format of a video frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Geometry {
+    width: u32,
+    height: u32,
+    format: Pixel,
+}
+
+impl Geometry {
+    fn of(frame: &frame::Video) -> Self {
+        Self {
+            width: frame.width(),
+            height: frame.height(),
+            format: frame.format(),
+        }
+    }
+}
+
+/// Scales frames to a fixed geometry, rebuilding the scaler whenever the input geometry changes.
+struct FrameConverter {
+    target: Geometry,
+    input: Option<Geometry>,
+    scaler: Option<scaling::Context>,
+}
+
+impl FrameConverter {
+    fn new(target: Geometry) -> Self {
+        Self {
+            target,
+            input: None,
+            scaler: None,
+        }
+    }
+
+    /// Whether `frame` has to be converted before use.
+    fn converts(&self, frame: &frame::Video) -> bool {
+        Geometry::of(frame) != self.target
+    }
+
+    fn convert(
+        &mut self,
+        frame: &frame::Video,
+        output: &mut frame::Video,
+    ) -> Result<(), RecorderError> {
+        let input = Geometry::of(frame);
+        if self.input != Some(input) {
+            self.scaler = None;
+            self.input = Some(input);
+        }
+        let scaler = match &mut self.scaler {
+            Some(scaler) => scaler,
+            None => self.scaler.insert(scaling::Context::get(
+                input.format,
+                input.width,
+                input.height,
+                self.target.format,
+                self.target.width,
+                self.target.height,
+                scaling::Flags::BICUBIC,
+            )?),
+        };
+        scaler.run(frame, output)?;
+        output.set_pts(frame.pts());
+        Ok(())
+    }
+}
+
+impl VideoTranscoder {
+    pub(crate) fn new(
+        ist: &format::stream::Stream,
+        processing: &VideoProcessing,
+        start_epoch_seconds: f64,
+    ) -> Result<Self, RecorderError> {
+        let decoder = ffmpeg_next::codec::context::Context::from_parameters(ist.parameters())?
+            .decoder()
+            .video()?;
+
+        let geometry = Geometry {
+            width: decoder.width(),
+            height: decoder.height(),
+            format: decoder.format(),
+        };
+
+        let overlay = processing.overlay.as_ref().map(|options| Overlay {
+            options: options.clone(),
+            camera_name: processing.camera_name.clone(),
+            clock: OverlayClock::new(start_epoch_seconds),
+        });
+        let chain: Vec<_> = overlay.iter().map(Overlay::drawtext).collect();
+
+        let frame_rate = processing
+            .frame_rate
+            .map(|mode| FrameRateConverter::new(mode, ist.time_base()));
+
+        let masker = if processing.privacy_masks.is_empty() {
+            None
+        } else {
+            Some(PrivacyMasker::new(processing.privacy_masks.clone()))
+        };
+
+        let filter = if chain.is_empty() {
+            None
+        } else {
+            Some(FilterChain::video(
+                (geometry.width, geometry.height),
+                geometry.format,
+                decoder.aspect_ratio(),
+                ist.time_base(),
+                &chain,
+            )?)
+        };
+
+        Ok(Self {
+            decoder,
+            encoders: Vec::new(),
+            frame_rate,
+            masker,
+            filter,
+            chain,
+            overlay,
+            input_time_base: ist.time_base(),
+            frame_rate_mode: processing.frame_rate,
+            first_pts: None,
+            geometry,
+            decoded: geometry,
+            resolution_change: processing.resolution_change,
+            normalizer: None,
+            snapshotter: None,
+            thumbnailers: Vec::new(),
+        })
+    }
+
+    /// Moves the burned-in timestamp so the frame presented at `pts` shows `epoch_seconds`, once
+    /// the input timestamps were rebased there.
+    pub(crate) fn rebase_overlay(&mut self, pts: i64, epoch_seconds: f64) {
+        if let Some(overlay) = self.overlay.as_mut() {
+            let origin = self.first_pts.unwrap_or(pts);
+            let seconds = (pts - origin) as f64 * f64::from(self.input_time_base);
+            overlay.clock.rebase(seconds, epoch_seconds);
+        }
+    }
+
+    /// Hands processed frames to `snapshotter` as well as to the encoders.
+    pub(crate) fn set_snapshotter(&mut self, snapshotter: Snapshotter) {
+        self.snapshotter = Some(snapshotter);
+    }
+
+    /// Hands processed frames to `thumbnailer` as well as to the encoders.
+    pub(crate) fn add_thumbnailer(&mut self, thumbnailer: Thumbnailer) {
+        self.thumbnailers.push(thumbnailer);
+    }
+
+    /// Decodes key frames only, for a transcoder that feeds snapshots or thumbnails but no
+    /// encoders.
+    pub(crate) fn decode_key_frames_only(&mut self) {
+        self.decoder.skip_frame(Discard::NonKey);
+    }
+
+    /// Adds an encoder for `settings`, registering its stream with the muxer at index `output`.
+    pub(crate) fn add_encoder(
+        &mut self,
+        muxer: &mut OutputMuxer,
+        output: usize,
+        settings: &Output,
+    ) -> Result<&VideoEncoder, RecorderError> {
+        let codec = settings.video_codec();
+        let ost_index = muxer.stream_count();
+        let octx = muxer.context();
+        let global_header = octx.format().flags().contains(format::Flags::GLOBAL_HEADER);
+
+        let encoder_codec = find_encoder(codec)?;
+        let mut ost = octx.add_stream(Some(encoder_codec))?;
+        let opened_encoder =
+            self.open_encoder(settings, self.decoder.aspect_ratio(), global_header)?;
+        ost.set_parameters(&opened_encoder);
+
+        let converter = FrameConverter::new(Geometry {
+            width: opened_encoder.width(),
+            height: opened_encoder.height(),
+            format: self.geometry.format,
+        });
+
+        self.encoders.push(VideoEncoder {
+            encoder: opened_encoder,
+            converter,
+            scaled: frame::Video::empty(),
+            codec,
+            settings: settings.clone(),
+            output,
+            ost_index,
+            time_base: self.input_time_base,
+        });
+        Ok(&self.encoders[self.encoders.len() - 1])
+    }
+
+    /// Opens an encoder for `settings` sized from the current source geometry.
+    fn open_encoder(
+        &self,
+        settings: &Output,
+        aspect_ratio: Rational,
+        global_header: bool,
+    ) -> Result<encoder::Video, RecorderError> {
+        let codec = settings.video_codec();
+        let (width, height) = settings.output_size(self.geometry.width, self.geometry.height);
+
+        let mut encoder_context =
+            ffmpeg_next::codec::context::Context::new_with_codec(find_encoder(codec)?)
+                .encoder()
+                .video()?;
+
+        encoder_context.set_height(height);
+        encoder_context.set_width(width);
+        encoder_context.set_aspect_ratio(aspect_ratio);
+        encoder_context.set_format(self.geometry.format);
+        encoder_context.set_frame_rate(self.decoder.frame_rate());
+        encoder_context.set_time_base(self.input_time_base);
+
+        if let Some(mode) = self.frame_rate_mode {
+            encoder_context.set_frame_rate(Some(Rational::from(mode.fps())));
+            // Keep keyframes, and therefore HLS segment boundaries, about two seconds apart.
+            encoder_context.set_gop((mode.fps() * 2.0).ceil().max(1.0) as u32);
+        }
+
+        if let Some(hls_output) = settings.as_hls()
+            && hls_output.low_latency.is_some()
+        {
+            // LL-HLS segments are cut on keyframes, so place one at every segment boundary.
+            let fps = self
+                .frame_rate_mode
+                .map(|mode| mode.fps())
+                .or(self.decoder.frame_rate().map(f64::from))
+                .unwrap_or(25.0);
+            let segment_seconds = f64::from(hls_output.segment_duration.unwrap_or(2));
+            encoder_context.set_gop((fps * segment_seconds).ceil().max(1.0) as u32);
+        }
+
+        if let Some(bitrate) = settings.video_bitrate() {
+            encoder_context.set_bit_rate(bitrate as usize);
+            encoder_context.set_max_bit_rate(bitrate as usize);
+        }
+
+        if global_header {
+            encoder_context.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+
+        let options = encoder_options(codec, settings.video_bitrate());
+        Ok(encoder_context.open_with(options)?)
+    }
+
+    pub(crate) fn send_packet_to_decoder(&mut self, packet: &Packet) -> Result<(), RecorderError> {
+        self.decoder.send_packet(packet)?;
+        Ok(())
+    }
+
+    pub(crate) fn send_eof_to_decoder(&mut self) -> Result<(), RecorderError> {
+        self.decoder.send_eof()?;
+        Ok(())
+    }
+
+    /// Flushes every encoder and writes the remaining packets.
+    pub(crate) fn flush_encoders(
+        &mut self,
+        muxers: &mut [OutputMuxer],
+    ) -> Result<(), RecorderError> {
+        for encoder in &mut self.encoders {
+            encoder.encoder.send_eof()?;
+            encoder.receive_and_process_encoded_packets(&mut muxers[encoder.output])?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn receive_and_process_decoded_frames(
+        &mut self,
+        muxers: &mut [OutputMuxer],
+    ) -> Result<(), RecorderError> {
+        let mut decoded = frame::Video::empty();
+        let mut normalized = frame::Video::empty();
+        while self.decoder.receive_frame(&mut decoded).is_ok() {
+            let timestamp = decoded.timestamp();
+            decoded.set_pts(timestamp);
+
+            if Geometry::of(&decoded) != self.decoded {
+                self.resolution_changed(&decoded, muxers)?;
+            }
+            let frame = match self.normalizer.as_mut() {
+                Some(normalizer) if normalizer.converts(&decoded) => {
+                    normalizer.convert(&decoded, &mut normalized)?;
+                    &mut normalized
+                }
+                _ => &mut decoded,
+            };
+            frame.set_kind(picture::Type::None);
+
+            let timestamps = match (self.frame_rate.as_mut(), timestamp) {
+                (Some(converter), Some(pts)) => converter.timestamps(pts),
+                _ => timestamp.into_iter().collect(),
+            };
+            if timestamp.is_some() && timestamps.is_empty() {
+                continue;
+            }
+
+            if let Some(masker) = self.masker.as_mut() {
+                masker.apply(frame)?;
+            }
+
+            if timestamps.is_empty() {
+                self.process_frame(frame, muxers)?;
+            }
+            for pts in timestamps {
+                frame.set_pts(Some(pts));
+                self.process_frame(frame, muxers)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reacts to the camera switching resolution or pixel format mid-stream, as configured.
+    fn resolution_changed(
+        &mut self,
+        frame: &frame::Video,
+        muxers: &mut [OutputMuxer],
+    ) -> Result<(), RecorderError> {
+        let geometry = Geometry::of(frame);
+        eprintln!(
+            "Video resolution changed from {}x{} {:?} to {}x{} {:?}",
+            self.decoded.width,
+            self.decoded.height,
+            self.decoded.format,
+            geometry.width,
+            geometry.height,
+            geometry.format
+        );
+        self.decoded = geometry;
+
+        if self.resolution_change == ResolutionChange::Rescale {
+            // Frames matching the original geometry pass through the normalizer untouched.
+            self.normalizer
+                .get_or_insert_with(|| FrameConverter::new(self.geometry));
+            return Ok(());
+        }
+
+        // Frames buffered in the filter still have the old size, so push them out first.
+        self.flush_filter(muxers)?;
+        self.geometry = geometry;
+        self.rebuild_filter(frame.aspect_ratio())?;
+
+        // `RtspRecorder::record` only allows re-initializing when every encoded output is MPEG-TS,
+        // which takes the new parameter sets in-band; no container header needs rewriting.
+        let seconds = frame.pts().unwrap_or_default() as f64 * f64::from(self.input_time_base);
+        for index in 0..self.encoders.len() {
+            let encoder = &mut self.encoders[index];
+            encoder.encoder.send_eof()?;
+            encoder.receive_and_process_encoded_packets(&mut muxers[encoder.output])?;
+
+            let settings = encoder.settings.clone();
+            let reopened = self.open_encoder(&settings, frame.aspect_ratio(), false)?;
+            let encoder = &mut self.encoders[index];
+            encoder.converter = FrameConverter::new(Geometry {
+                width: reopened.width(),
+                height: reopened.height(),
+                format: geometry.format,
+            });
+            encoder.encoder = reopened;
+            muxers[encoder.output].mark_discontinuity(seconds);
+        }
+        Ok(())
+    }
+
+    /// Routes a decoded (and masked) frame through the filter graph, or straight to the encoders.
+    fn process_frame(
+        &mut self,
+        frame: &mut frame::Video,
+        muxers: &mut [OutputMuxer],
+    ) -> Result<(), RecorderError> {
+        if self.filter.is_none() {
+            return self.send_frame_to_encoders(frame, muxers);
+        }
+
+        let pts = frame.pts();
+        let origin = *self.first_pts.get_or_insert(pts.unwrap_or(0));
+        if let (Some(pts), Some(overlay)) = (pts, self.overlay.as_mut()) {
+            let seconds = (pts - origin) as f64 * f64::from(self.input_time_base);
+            if overlay.clock.frame(seconds).is_some() {
+                // Frames already in the filter are drawn with the previous start.
+                self.flush_filter(muxers)?;
+                self.chain = self.overlay.iter().map(Overlay::drawtext).collect();
+                self.rebuild_filter(frame.aspect_ratio())?;
+            }
+        }
+        frame.set_pts(pts.map(|pts| pts - origin));
+        if let Some(filter) = self.filter.as_mut() {
+            filter.push(frame)?;
+        }
+        self.receive_and_process_filtered_frames(muxers)
+    }
+
+    /// Sets the filter graph up again from `chain` for the current geometry.
+    fn rebuild_filter(&mut self, aspect_ratio: Rational) -> Result<(), RecorderError> {
+        self.filter = if self.chain.is_empty() {
+            None
+        } else {
+            Some(FilterChain::video(
+                (self.geometry.width, self.geometry.height),
+                self.geometry.format,
+                aspect_ratio,
+                self.input_time_base,
+                &self.chain,
+            )?)
+        };
+        Ok(())
+    }
+
+    /// Drains frames buffered inside the filter graph once the decoder has been flushed.
+    pub(crate) fn flush_filter(&mut self, muxers: &mut [OutputMuxer]) -> Result<(), RecorderError> {
+        if let Some(filter) = self.filter.as_mut() {
+            filter.flush()?;
+            self.receive_and_process_filtered_frames(muxers)?;
+        }
+        Ok(())
+    }
+
+    fn receive_and_process_filtered_frames(
+        &mut self,
+        muxers: &mut [OutputMuxer],
+    ) -> Result<(), RecorderError> {
+        let origin = self.first_pts.unwrap_or(0);
+        let mut filtered = frame::Video::empty();
+        while let Some(filter) = self.filter.as_mut() {
+            if !filter.pull(&mut filtered)? {
+                break;
+            }
+            let pts = filtered.pts();
+            filtered.set_pts(pts.map(|pts| pts + origin));
+            self.send_frame_to_encoders(&filtered, muxers)?;
+        }
+        Ok(())
+    }
+
+    fn send_frame_to_encoders(
+        &mut self,
+        frame: &frame::Video,
+        muxers: &mut [OutputMuxer],
+    ) -> Result<(), RecorderError> {
+        if let Some(snapshotter) = self.snapshotter.as_mut() {
+            snapshotter.offer(frame, self.input_time_base);
+        }
+        for thumbnailer in &mut self.thumbnailers {
+            thumbnailer.offer(frame, self.input_time_base);
+        }
+        for encoder in &mut self.encoders {
+            encoder.send_frame(frame, &mut muxers[encoder.output])?;
+        }
+        Ok(())
+    }
+}
+
+impl VideoEncoder {
+    /// Encoded picture size.
+    pub(crate) fn size(&self) -> (u32, u32) {
+        (self.encoder.width(), self.encoder.height())
+    }
+
+    /// Codec string advertised for this encoder in a master playlist, read from its parameter sets.
+    pub(crate) fn codec_string(&self) -> Option<String> {
+        let extradata = unsafe {
+            let context = self.encoder.as_ptr();
+            if (*context).extradata.is_null() || (*context).extradata_size <= 0 {
+                return None;
+            }
+            std::slice::from_raw_parts((*context).extradata, (*context).extradata_size as usize)
+        };
+        match self.codec {
+            VideoCodec::H264 => h264_codec_string(extradata),
+            VideoCodec::H265 => hevc_codec_string(extradata),
+            VideoCodec::Copy => None,
+        }
+    }
+
+    fn send_frame(
+        &mut self,
+        frame: &frame::Video,
+        muxer: &mut OutputMuxer,
+    ) -> Result<(), RecorderError> {
+        if self.converter.converts(frame) {
+            self.converter.convert(frame, &mut self.scaled)?;
+            self.encoder.send_frame(&self.scaled)?;
+        } else {
+            self.encoder.send_frame(frame)?;
+        }
+        self.receive_and_process_encoded_packets(muxer)
+    }
+
+    fn receive_and_process_encoded_packets(
+        &mut self,
+        muxer: &mut OutputMuxer,
+    ) -> Result<(), RecorderError> {
+        let mut encoded = Packet::empty();
+        while self.encoder.receive_packet(&mut encoded).is_ok() {
+            muxer.write(&mut encoded, self.ost_index, self.time_base)?;
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn find_encoder(codec: VideoCodec) -> Result<ffmpeg_next::Codec, RecorderError> {
+    let codec_id = match codec {
+        VideoCodec::H264 => codec::Id::H264,
+        VideoCodec::H265 => codec::Id::HEVC,
+        VideoCodec::Copy => return Err(RecorderError::UnsupportedVideoCodec(codec)),
+    };
+    encoder::find(codec_id).ok_or(RecorderError::UnsupportedVideoCodec(codec))
+}
+
+/// Encoder private options: constant quality by default, or capped average bitrate for ladder renditions.
+pub(crate) fn encoder_options(codec: VideoCodec, bitrate: Option<u32>) -> Dictionary<'static> {
+    let mut options = Dictionary::new();
+    match codec {
+        VideoCodec::H264 => options.set("preset", "veryfast"),
+        VideoCodec::H265 => options.set("preset", "medium"),
+        VideoCodec::Copy => {}
+    }
+    match (codec, bitrate) {
+        (VideoCodec::Copy, _) => {}
+        // A two-second VBV buffer keeps the peak close to the advertised BANDWIDTH.
+        (_, Some(bitrate)) => options.set("bufsize", &(u64::from(bitrate) * 2).to_string()),
+        (VideoCodec::H264, None) => options.set("crf", "23"),
+        (VideoCodec::H265, None) => options.set("crf", "28"),
+    }
+    options
+}
@@ -0,0 +1,200 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::{
+    SegmentRecord, format_program_date_time, media_playlist_entries, parse_program_date_time,
+    read_segment_index, segment_index_path,
+};
+
+/// An fMP4 init segment and the first media sequence number it applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitSegment {
+    /// Media sequence number of the first segment encoded against this init segment.
+    pub sequence: u64,
+    /// URI of the init segment, relative to the playlist.
+    pub uri: String,
+}
+
+/// Largest gap, in seconds, between one segment's end and the next one's start that still counts
+/// as continuous recording; `EXTINF` rounding and clock jitter stay well below it.
+pub(crate) const GAP_TOLERANCE: f64 = 0.5;
+
+/// Builds an `EXT-X-PLAYLIST-TYPE:VOD` playlist of the indexed segments overlapping the
+/// wall-clock window `start..end`, in seconds since the Unix epoch.
+///
+/// Segments come in wall-clock order. One that follows a recording gap, a skipped sequence
+/// number or a timestamp discontinuity is tagged `EXT-X-DISCONTINUITY`, and every run of
+/// continuous segments is dated with `EXT-X-PROGRAM-DATE-TIME`. fMP4 recordings pass their init
+/// segments, and each segment is preceded by an `EXT-X-MAP` whenever its init segment differs
+/// from the previous one's. Returns `None` when no segment overlaps the window.
+pub fn vod_playlist(
+    records: &[SegmentRecord],
+    start: f64,
+    end: f64,
+    init_segments: &[InitSegment],
+) -> Option<String> {
+    let mut selected: Vec<&SegmentRecord> = records
+        .iter()
+        .filter(|record| record.start < end && record.end() > start)
+        .collect();
+    selected.sort_by(|left, right| left.start.total_cmp(&right.start));
+    let first = selected.first()?;
+
+    let target_duration = selected
+        .iter()
+        .map(|record| record.duration.round() as u64)
+        .max()
+        .unwrap_or(1)
+        .max(1);
+    let version = if init_segments.is_empty() { 3 } else { 6 };
+
+    let mut playlist = String::from("#EXTM3U\n");
+    let _ = writeln!(playlist, "#EXT-X-VERSION:{version}");
+    playlist.push_str("#EXT-X-PLAYLIST-TYPE:VOD\n");
+    let _ = writeln!(playlist, "#EXT-X-TARGETDURATION:{target_duration}");
+    let _ = writeln!(playlist, "#EXT-X-MEDIA-SEQUENCE:{}", first.sequence);
+
+    let mut previous: Option<&SegmentRecord> = None;
+    let mut init_uri = None;
+    for record in selected {
+        let continuous = previous.is_some_and(|previous| {
+            !record.discontinuity
+                && record.sequence == previous.sequence + 1
+                && (record.start - previous.end()).abs() <= GAP_TOLERANCE
+        });
+        if previous.is_some() && !continuous {
+            playlist.push_str("#EXT-X-DISCONTINUITY\n");
+        }
+        if !continuous {
+            let _ = writeln!(
+                playlist,
+                "#EXT-X-PROGRAM-DATE-TIME:{}",
+                format_program_date_time(record.start)
+            );
+        }
+        let record_init_uri = init_segment_at(init_segments, record.sequence);
+        if record_init_uri != init_uri {
+            if let Some(uri) = record_init_uri {
+                let _ = writeln!(playlist, "#EXT-X-MAP:URI=\"{uri}\"");
+            }
+            init_uri = record_init_uri;
+        }
+        let _ = writeln!(playlist, "#EXTINF:{:.6},", record.duration);
+        playlist.push_str(&record.uri);
+        playlist.push('\n');
+        previous = Some(record);
+    }
+    playlist.push_str("#EXT-X-ENDLIST\n");
+    Some(playlist)
+}
+
+/// VOD playlist of the window `start..end` of the recording whose media playlist is at
+/// `playlist_path`, built from its wall-clock index.
+///
+/// Segments whose files no longer exist, such as ones deleted by a retention job, are left out
+/// and show up as gaps. Returns `None` when nothing recorded overlaps the window.
+pub fn window_playlist(playlist_path: &Path, start: f64, end: f64) -> io::Result<Option<String>> {
+    let playlist = fs::read_to_string(playlist_path)?;
+    let directory = playlist_path.parent().unwrap_or_else(|| Path::new(""));
+    let records: Vec<SegmentRecord> = read_segment_index(&segment_index_path(playlist_path))?
+        .into_iter()
+        .filter(|record| directory.join(&record.uri).exists())
+        .collect();
+    Ok(vod_playlist(
+        &records,
+        start,
+        end,
+        &init_segments(&playlist),
+    ))
+}
+
+/// URI of the init segment announced by a media playlist's first `EXT-X-MAP` tag.
+pub fn init_segment_uri(playlist: &str) -> Option<&str> {
+    playlist.lines().find_map(map_uri)
+}
+
+/// Init segments announced by the `EXT-X-MAP` tags of a media playlist, in playlist order.
+pub fn init_segments(playlist: &str) -> Vec<InitSegment> {
+    let (mut sequence, _) = media_playlist_entries(playlist);
+    let mut init_segments: Vec<InitSegment> = Vec::new();
+    for line in playlist.lines().map(str::trim) {
+        if let Some(uri) = map_uri(line) {
+            // Of two maps before the same segment, the later one applies.
+            if init_segments
+                .last()
+                .is_some_and(|last| last.sequence == sequence)
+            {
+                init_segments.pop();
+            }
+            init_segments.push(InitSegment {
+                sequence,
+                uri: uri.to_string(),
+            });
+        } else if !line.is_empty() && !line.starts_with('#') {
+            sequence += 1;
+        }
+    }
+    init_segments
+}
+
+/// URI of the init segment that segment `sequence` was encoded against.
+pub fn init_segment_at(init_segments: &[InitSegment], sequence: u64) -> Option<&str> {
+    init_segments
+        .iter()
+        .rev()
+        .find(|init_segment| init_segment.sequence <= sequence)
+        .or(init_segments.first())
+        .map(|init_segment| init_segment.uri.as_str())
+}
+
+/// Replaces the `EXT-X-MAP` tags of a media playlist with ones for `init_segments`, each placed
+/// before the first listed segment it applies to.
+///
+/// FFmpeg's `hls` muxer announces only its own init segment, at the top of the playlist, even
+/// when it continues the playlist of an earlier session.
+pub fn place_init_segments(playlist: &str, init_segments: &[InitSegment]) -> String {
+    let (mut sequence, _) = media_playlist_entries(playlist);
+    let mut placed = String::with_capacity(playlist.len());
+    let mut init_uri = None;
+    for line in playlist.lines() {
+        let trimmed = line.trim();
+        if map_uri(trimmed).is_some() {
+            continue;
+        }
+        if trimmed.starts_with("#EXTINF:") {
+            let segment_init_uri = init_segment_at(init_segments, sequence);
+            if segment_init_uri != init_uri {
+                if let Some(uri) = segment_init_uri {
+                    let _ = writeln!(placed, "#EXT-X-MAP:URI=\"{uri}\"");
+                }
+                init_uri = segment_init_uri;
+            }
+            sequence += 1;
+        }
+        placed.push_str(line);
+        placed.push('\n');
+    }
+    placed
+}
+
+/// URI attribute of an `EXT-X-MAP` tag line.
+fn map_uri(line: &str) -> Option<&str> {
+    line.trim()
+        .strip_prefix("#EXT-X-MAP:")?
+        .split(',')
+        .find_map(|attribute| attribute.trim().strip_prefix("URI="))
+        .map(|uri| uri.trim_matches('"'))
+}
+
+/// Parses a window boundary given either as seconds since the Unix epoch or as an ISO 8601
+/// date-time such as `2024-05-01T08:00:00+0200`.
+pub fn parse_window_time(value: &str) -> Option<f64> {
+    let value = value.trim();
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|seconds| seconds.is_finite())
+        .or_else(|| parse_program_date_time(value))
+}
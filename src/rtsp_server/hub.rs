@@ -0,0 +1,184 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::rtp::{Packetizer, RestreamTrack, session_description};
+
+/// RTP packets queued per client before the server starts dropping them.
+const CLIENT_QUEUE_PACKETS: usize = 2048;
+
+/// A complete RTP packet of one track.
+pub(crate) struct RtpPacket {
+    pub(crate) track: usize,
+    pub(crate) data: Vec<u8>,
+}
+
+/// Cameras currently republished by the RTSP server, shared between recorders and the server.
+#[derive(Clone, Default)]
+pub struct RestreamHub {
+    channels: Arc<Mutex<HashMap<String, Arc<Channel>>>>,
+}
+
+/// Tracks of one published camera and the clients playing it.
+pub(crate) struct Channel {
+    pub(crate) camera_id: String,
+    pub(crate) tracks: Vec<RestreamTrack>,
+    packetizers: Mutex<Vec<Packetizer>>,
+    subscribers: Mutex<Vec<Subscriber>>,
+}
+
+/// Packet queue of one playing client.
+struct Subscriber {
+    sender: SyncSender<Arc<RtpPacket>>,
+    /// Set after the queue overflowed; access units are skipped until the next clean start.
+    resync: bool,
+}
+
+impl RestreamHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts republishing `tracks` as `camera_id`, replacing an earlier publisher of that id.
+    pub fn publish(&self, camera_id: &str, tracks: Vec<RestreamTrack>) -> Publisher {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.subsec_nanos())
+            .unwrap_or_default();
+        let packetizers = tracks
+            .iter()
+            .enumerate()
+            .map(|(index, track)| {
+                let ssrc = seed.rotate_left(index as u32 * 7) ^ index as u32;
+                Packetizer::new(track, track.payload_type(index), ssrc)
+            })
+            .collect();
+        let channel = Arc::new(Channel {
+            camera_id: camera_id.to_string(),
+            tracks,
+            packetizers: Mutex::new(packetizers),
+            subscribers: Mutex::new(Vec::new()),
+        });
+        lock(&self.channels).insert(camera_id.to_string(), Arc::clone(&channel));
+        Publisher {
+            hub: self.clone(),
+            channel,
+        }
+    }
+
+    /// Ids of the cameras currently published, sorted.
+    pub fn camera_ids(&self) -> Vec<String> {
+        let mut ids: Vec<String> = lock(&self.channels).keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    pub(crate) fn channel(&self, camera_id: &str) -> Option<Arc<Channel>> {
+        lock(&self.channels).get(camera_id).cloned()
+    }
+}
+
+impl Channel {
+    /// SDP describing the channel's tracks.
+    pub(crate) fn description(&self) -> String {
+        session_description(&self.camera_id, &self.tracks)
+    }
+
+    /// Next sequence number and latest RTP timestamp of each track, for `RTP-Info`.
+    pub(crate) fn rtp_info(&self) -> Vec<(u16, u32)> {
+        lock(&self.packetizers)
+            .iter()
+            .map(|packetizer| (packetizer.next_sequence(), packetizer.timestamp()))
+            .collect()
+    }
+
+    /// Registers a client; packets published from now on are queued for it.
+    pub(crate) fn subscribe(&self) -> Receiver<Arc<RtpPacket>> {
+        let (sender, receiver) = mpsc::sync_channel(CLIENT_QUEUE_PACKETS);
+        lock(&self.subscribers).push(Subscriber {
+            sender,
+            resync: false,
+        });
+        receiver
+    }
+}
+
+/// Feeds one camera's access units to the RTSP server's clients.
+///
+/// Dropping the publisher unpublishes the camera and ends every client's playback.
+pub struct Publisher {
+    hub: RestreamHub,
+    channel: Arc<Channel>,
+}
+
+impl Publisher {
+    /// Sends one access unit of `track`, presented at RTP time `timestamp`, to every playing client.
+    ///
+    /// Clients that fall behind lose packets rather than slowing down the recording, and then skip
+    /// access units until the next keyframe so they never decode a partial picture.
+    pub fn send(&self, track: usize, access_unit: &[u8], timestamp: u32, keyframe: bool) {
+        let mut subscribers = lock(&self.channel.subscribers);
+        let packets = match lock(&self.channel.packetizers).get_mut(track) {
+            Some(packetizer) => packetizer.packetize(access_unit, timestamp),
+            None => return,
+        };
+        if subscribers.is_empty() {
+            return;
+        }
+        let packets: Vec<Arc<RtpPacket>> = packets
+            .into_iter()
+            .map(|data| Arc::new(RtpPacket { track, data }))
+            .collect();
+        let starts_clean = keyframe && self.starts_clean(track);
+        subscribers.retain_mut(|subscriber| {
+            if subscriber.resync && !starts_clean {
+                return true;
+            }
+            for packet in &packets {
+                match subscriber.sender.try_send(Arc::clone(packet)) {
+                    Ok(()) => subscriber.resync = false,
+                    Err(TrySendError::Full(_)) => {
+                        if !subscriber.resync {
+                            eprintln!(
+                                "Warning: an RTSP client of {} is falling behind; dropping packets until the next keyframe",
+                                self.channel.camera_id
+                            );
+                        }
+                        subscriber.resync = true;
+                        break;
+                    }
+                    Err(TrySendError::Disconnected(_)) => return false,
+                }
+            }
+            true
+        });
+    }
+
+    /// Whether a client can start decoding at a keyframe of `track`: a video track, or any track
+    /// when the camera is republished without video.
+    fn starts_clean(&self, track: usize) -> bool {
+        let tracks = &self.channel.tracks;
+        tracks[track].is_video() || !tracks.iter().any(RestreamTrack::is_video)
+    }
+}
+
+impl Drop for Publisher {
+    fn drop(&mut self) {
+        let mut channels = lock(&self.hub.channels);
+        if channels
+            .get(&self.channel.camera_id)
+            .is_some_and(|current| Arc::ptr_eq(current, &self.channel))
+        {
+            channels.remove(&self.channel.camera_id);
+        }
+        lock(&self.channel.subscribers).clear();
+    }
+}
+
+/// Locks `mutex`, recovering the data if a client thread panicked while holding it.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
@@ -0,0 +1,611 @@
+mod hub;
+mod rtp;
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::Deserialize;
+
+pub use hub::{Publisher, RestreamHub};
+pub use rtp::{Packetizer, RestreamTrack, TrackCodec, session_description};
+
+use hub::{Channel, RtpPacket};
+
+/// Seconds a session may go without a request before the server stops serving it, advertised in
+/// every `Session` header.
+const SESSION_TIMEOUT_SECONDS: u32 = 60;
+/// How often a playing session checks whether it was torn down.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Settings for the built-in RTSP server that republishes the recorded cameras.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RtspServerConfig {
+    /// Socket address to listen on, e.g. `0.0.0.0:8554`. Clients are not authenticated, so every
+    /// host that can reach it may watch the cameras.
+    pub listen: String,
+}
+
+/// A parsed RTSP request with the headers the server cares about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RtspRequest {
+    /// RTSP method, e.g. `DESCRIBE`.
+    pub method: String,
+    /// Request URI, e.g. `rtsp://nvr:8554/front-door/trackID=0`.
+    pub uri: String,
+    /// Value of the `CSeq` header, echoed in the response.
+    pub cseq: Option<String>,
+    /// Value of the `Transport` header, when present.
+    pub transport: Option<String>,
+    /// Session id from the `Session` header, without parameters.
+    pub session: Option<String>,
+}
+
+impl RtspRequest {
+    /// Reads a request head from `reader`, skipping interleaved RTCP sent by the client.
+    /// Returns `None` when the peer closed the connection.
+    pub fn read(reader: &mut impl BufRead) -> io::Result<Option<Self>> {
+        loop {
+            let Some(&first) = reader.fill_buf()?.first() else {
+                return Ok(None);
+            };
+            if first != b'$' {
+                break;
+            }
+            // `$`, channel and a 16-bit length precede every interleaved packet.
+            let mut header = [0; 4];
+            reader.read_exact(&mut header)?;
+            let length = u16::from_be_bytes([header[2], header[3]]);
+            io::copy(
+                &mut reader.by_ref().take(u64::from(length)),
+                &mut io::sink(),
+            )?;
+        }
+
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(uri)) = (parts.next(), parts.next()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "malformed request line",
+            ));
+        };
+
+        let mut request = Self {
+            method: method.to_string(),
+            uri: uri.to_string(),
+            cseq: None,
+            transport: None,
+            session: None,
+        };
+        let mut content_length = 0;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+            let Some((name, value)) = header.split_once(':') else {
+                continue;
+            };
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("cseq") {
+                request.cseq = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("transport") {
+                request.transport = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("session") {
+                let id = value.split(';').next().unwrap_or(value).trim();
+                request.session = Some(id.to_string());
+            } else if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.parse().unwrap_or(0);
+            }
+        }
+        // Bodies, such as SET_PARAMETER ones, carry nothing the server uses.
+        io::copy(&mut reader.by_ref().take(content_length), &mut io::sink())?;
+        Ok(Some(request))
+    }
+}
+
+/// How a client asked to receive the RTP packets of one track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    /// Interleaved on the RTSP connection, on the given RTP and RTCP channels.
+    Tcp { interleaved: (u8, u8) },
+    /// Unicast UDP to the given RTP and RTCP client ports.
+    Udp { client_ports: (u16, u16) },
+}
+
+/// Picks the first transport of a `Transport` header the server supports.
+pub fn parse_transport(header: &str) -> Option<Transport> {
+    header.split(',').find_map(|spec| {
+        let mut fields = spec.split(';').map(str::trim);
+        let profile = fields.next()?;
+        let params: Vec<&str> = fields.collect();
+        let range = |name: &str| {
+            params.iter().find_map(|param| {
+                let (first, second) = param.strip_prefix(name)?.split_once('-')?;
+                Some((first.parse::<u16>().ok()?, second.parse::<u16>().ok()?))
+            })
+        };
+
+        if profile.eq_ignore_ascii_case("RTP/AVP/TCP") {
+            let (rtp, rtcp) = range("interleaved=")?;
+            Some(Transport::Tcp {
+                interleaved: (u8::try_from(rtp).ok()?, u8::try_from(rtcp).ok()?),
+            })
+        } else if profile.eq_ignore_ascii_case("RTP/AVP")
+            || profile.eq_ignore_ascii_case("RTP/AVP/UDP")
+        {
+            if params
+                .iter()
+                .any(|param| param.eq_ignore_ascii_case("multicast"))
+            {
+                return None;
+            }
+            Some(Transport::Udp {
+                client_ports: range("client_port=")?,
+            })
+        } else {
+            None
+        }
+    })
+}
+
+/// Camera id and optional track index addressed by a request URI such as
+/// `rtsp://nvr:8554/front-door/trackID=1`.
+pub fn parse_stream_uri(uri: &str) -> Option<(String, Option<usize>)> {
+    let without_scheme = uri.split_once("://").map_or(uri, |(_, rest)| rest);
+    let path = without_scheme.split_once('/')?.1;
+    let path = path.split('?').next().unwrap_or(path).trim_end_matches('/');
+
+    let (camera_id, track) = match path.rsplit_once('/') {
+        Some((camera_id, last)) if last.starts_with("trackID=") => {
+            (camera_id, Some(last["trackID=".len()..].parse().ok()?))
+        }
+        _ => (path, None),
+    };
+    (!camera_id.is_empty()).then(|| (camera_id.to_string(), track))
+}
+
+/// Path component a camera is published under: its name lowercased, with runs of other
+/// characters turned into single dashes.
+pub fn camera_id(name: &str) -> String {
+    let mut id = String::with_capacity(name.len());
+    for character in name.chars() {
+        if character.is_ascii_alphanumeric() {
+            id.push(character.to_ascii_lowercase());
+        } else if !id.is_empty() && !id.ends_with('-') {
+            id.push('-');
+        }
+    }
+    let trimmed = id.trim_end_matches('-').len();
+    id.truncate(trimmed);
+    if id.is_empty() {
+        id.push_str("camera");
+    }
+    id
+}
+
+/// Binds the listener and serves clients until the process exits, one thread per connection.
+pub fn serve(config: &RtspServerConfig, hub: RestreamHub) -> io::Result<()> {
+    let listener = TcpListener::bind(&config.listen)?;
+    println!("Republishing cameras on rtsp://{}", config.listen);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("Failed to accept RTSP connection: {error}");
+                continue;
+            }
+        };
+        let hub = hub.clone();
+        thread::spawn(move || {
+            if let Err(error) = handle_connection(stream, &hub)
+                && error.kind() != io::ErrorKind::BrokenPipe
+                && error.kind() != io::ErrorKind::ConnectionReset
+            {
+                eprintln!("RTSP connection error: {error}");
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Transport set up for one track of a session.
+enum TrackTransport {
+    Tcp { channel: u8 },
+    Udp { socket: UdpSocket, peer: SocketAddr },
+}
+
+impl TrackTransport {
+    fn try_clone(&self) -> io::Result<Self> {
+        Ok(match self {
+            Self::Tcp { channel } => Self::Tcp { channel: *channel },
+            Self::Udp { socket, peer } => Self::Udp {
+                socket: socket.try_clone()?,
+                peer: *peer,
+            },
+        })
+    }
+}
+
+/// One client connection: the shared writer for responses and interleaved packets, and the
+/// addresses UDP transports are set up between.
+struct Connection {
+    writer: Arc<Mutex<TcpStream>>,
+    local: IpAddr,
+    peer: IpAddr,
+}
+
+/// State of the single session a connection may hold.
+struct Session {
+    id: String,
+    channel: Arc<Channel>,
+    transports: Vec<Option<TrackTransport>>,
+    /// Set on teardown to stop the playback thread.
+    stopped: Arc<AtomicBool>,
+    /// When the client last sent a request, checked by the playback thread.
+    liveness: Arc<Liveness>,
+    playing: bool,
+}
+
+/// Time of a session's latest request, shared with its playback thread.
+struct Liveness {
+    origin: Instant,
+    /// Milliseconds from `origin` to the latest request.
+    last_request: AtomicU64,
+}
+
+impl Liveness {
+    fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            last_request: AtomicU64::new(0),
+        }
+    }
+
+    fn touch(&self) {
+        let elapsed = self.origin.elapsed().as_millis() as u64;
+        self.last_request.store(elapsed, Ordering::Relaxed);
+    }
+
+    /// Whether the client has been silent for longer than the advertised session timeout.
+    fn expired(&self) -> bool {
+        let last_request = Duration::from_millis(self.last_request.load(Ordering::Relaxed));
+        self.origin.elapsed().saturating_sub(last_request)
+            > Duration::from_secs(u64::from(SESSION_TIMEOUT_SECONDS))
+    }
+}
+
+fn handle_connection(stream: TcpStream, hub: &RestreamHub) -> io::Result<()> {
+    // Interleaved packets to a client that vanished must not block forwarding forever.
+    stream.set_write_timeout(Some(Duration::from_secs(u64::from(
+        SESSION_TIMEOUT_SECONDS,
+    ))))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let connection = Connection {
+        local: stream.local_addr()?.ip(),
+        peer: stream.peer_addr()?.ip(),
+        writer: Arc::new(Mutex::new(stream)),
+    };
+    let mut session = None;
+    let result = serve_requests(&mut reader, &connection, hub, &mut session);
+    if let Some(session) = session {
+        session.stopped.store(true, Ordering::Relaxed);
+    }
+    result
+}
+
+fn serve_requests(
+    reader: &mut impl BufRead,
+    connection: &Connection,
+    hub: &RestreamHub,
+    session: &mut Option<Session>,
+) -> io::Result<()> {
+    while let Some(request) = RtspRequest::read(reader)? {
+        respond(connection, hub, &request, session)?;
+    }
+    Ok(())
+}
+
+fn respond(
+    connection: &Connection,
+    hub: &RestreamHub,
+    request: &RtspRequest,
+    session: &mut Option<Session>,
+) -> io::Result<()> {
+    let reply = |status: u16, reason: &str, headers: &[(&str, String)], body: &str| {
+        write_response(&connection.writer, request, status, reason, headers, body)
+    };
+
+    if let (Some(requested), Some(current)) = (&request.session, session.as_ref())
+        && *requested != current.id
+    {
+        return reply(454, "Session Not Found", &[], "");
+    }
+    // Any request on the connection keeps its session alive, as keep-alives need not name it.
+    if let Some(current) = session.as_ref() {
+        current.liveness.touch();
+    }
+
+    match request.method.as_str() {
+        "OPTIONS" => reply(
+            200,
+            "OK",
+            &[(
+                "Public",
+                "OPTIONS, DESCRIBE, SETUP, PLAY, TEARDOWN, GET_PARAMETER, SET_PARAMETER"
+                    .to_string(),
+            )],
+            "",
+        ),
+        "DESCRIBE" => {
+            let Some(channel) =
+                parse_stream_uri(&request.uri).and_then(|(camera_id, _)| hub.channel(&camera_id))
+            else {
+                return reply(404, "Not Found", &[], "");
+            };
+            let base = format!("{}/", request.uri.trim_end_matches('/'));
+            reply(
+                200,
+                "OK",
+                &[
+                    ("Content-Base", base),
+                    ("Content-Type", "application/sdp".to_string()),
+                ],
+                &channel.description(),
+            )
+        }
+        "SETUP" => setup(connection, hub, request, session),
+        "PLAY" => {
+            let Some(current) = session.as_mut() else {
+                return reply(455, "Method Not Valid in This State", &[], "");
+            };
+            if !current.playing {
+                start_playback(&connection.writer, current)?;
+                current.playing = true;
+            }
+            let base = request.uri.trim_end_matches('/');
+            let rtp_info: Vec<String> = current
+                .channel
+                .rtp_info()
+                .into_iter()
+                .enumerate()
+                .filter(|(track, _)| matches!(current.transports.get(*track), Some(Some(_))))
+                .map(|(track, (sequence, timestamp))| {
+                    format!("url={base}/trackID={track};seq={sequence};rtptime={timestamp}")
+                })
+                .collect();
+            reply(
+                200,
+                "OK",
+                &[
+                    ("Session", session_header(&current.id)),
+                    ("Range", "npt=now-".to_string()),
+                    ("RTP-Info", rtp_info.join(",")),
+                ],
+                "",
+            )
+        }
+        "TEARDOWN" => {
+            if let Some(current) = session.take() {
+                current.stopped.store(true, Ordering::Relaxed);
+            }
+            reply(200, "OK", &[], "")
+        }
+        "GET_PARAMETER" | "SET_PARAMETER" => {
+            let headers: Vec<(&str, String)> = session
+                .as_ref()
+                .map(|current| ("Session", session_header(&current.id)))
+                .into_iter()
+                .collect();
+            reply(200, "OK", &headers, "")
+        }
+        _ => reply(501, "Not Implemented", &[], ""),
+    }
+}
+
+fn setup(
+    connection: &Connection,
+    hub: &RestreamHub,
+    request: &RtspRequest,
+    session: &mut Option<Session>,
+) -> io::Result<()> {
+    let reply = |status: u16, reason: &str, headers: &[(&str, String)]| {
+        write_response(&connection.writer, request, status, reason, headers, "")
+    };
+
+    let Some((camera_id, track)) = parse_stream_uri(&request.uri) else {
+        return reply(404, "Not Found", &[]);
+    };
+    let channel = match session.as_ref() {
+        Some(current) if current.channel.camera_id == camera_id => Arc::clone(&current.channel),
+        Some(_) => return reply(459, "Aggregate Operation Not Allowed", &[]),
+        None => match hub.channel(&camera_id) {
+            Some(channel) => channel,
+            None => return reply(404, "Not Found", &[]),
+        },
+    };
+    // A single-track camera may be set up without naming the track.
+    let track = match track {
+        Some(track) if track < channel.tracks.len() => track,
+        None if channel.tracks.len() == 1 => 0,
+        _ => return reply(404, "Not Found", &[]),
+    };
+    let Some(transport) = request.transport.as_deref().and_then(parse_transport) else {
+        return reply(461, "Unsupported Transport", &[]);
+    };
+
+    let current = session.get_or_insert_with(|| Session {
+        id: new_session_id(),
+        transports: (0..channel.tracks.len()).map(|_| None).collect(),
+        channel,
+        stopped: Arc::new(AtomicBool::new(false)),
+        liveness: Arc::new(Liveness::new()),
+        playing: false,
+    });
+    if current.playing {
+        return reply(455, "Method Not Valid in This State", &[]);
+    }
+
+    let (track_transport, transport_header) = match transport {
+        Transport::Tcp { interleaved } => (
+            TrackTransport::Tcp {
+                channel: interleaved.0,
+            },
+            format!(
+                "RTP/AVP/TCP;unicast;interleaved={}-{}",
+                interleaved.0, interleaved.1
+            ),
+        ),
+        Transport::Udp { client_ports } => {
+            let socket = UdpSocket::bind(SocketAddr::new(connection.local, 0))?;
+            let server_port = socket.local_addr()?.port();
+            (
+                TrackTransport::Udp {
+                    socket,
+                    peer: SocketAddr::new(connection.peer, client_ports.0),
+                },
+                format!(
+                    "RTP/AVP;unicast;client_port={}-{};server_port={}-{}",
+                    client_ports.0,
+                    client_ports.1,
+                    server_port,
+                    server_port.wrapping_add(1)
+                ),
+            )
+        }
+    };
+    current.transports[track] = Some(track_transport);
+
+    reply(
+        200,
+        "OK",
+        &[
+            ("Transport", transport_header),
+            ("Session", session_header(&current.id)),
+        ],
+    )
+}
+
+/// Subscribes the session to its camera and forwards packets from a background thread.
+fn start_playback(writer: &Arc<Mutex<TcpStream>>, session: &Session) -> io::Result<()> {
+    let transports = session
+        .transports
+        .iter()
+        .map(|transport| {
+            transport
+                .as_ref()
+                .map(TrackTransport::try_clone)
+                .transpose()
+        })
+        .collect::<io::Result<Vec<_>>>()?;
+    // Interleaved sessions end with their connection; UDP ones only with the client's requests.
+    let liveness = transports
+        .iter()
+        .any(|transport| matches!(transport, Some(TrackTransport::Udp { .. })))
+        .then(|| Arc::clone(&session.liveness));
+    let receiver = session.channel.subscribe();
+    let writer = Arc::clone(writer);
+    let stopped = Arc::clone(&session.stopped);
+    let id = session.id.clone();
+    thread::spawn(move || {
+        forward_packets(
+            &receiver,
+            &transports,
+            &writer,
+            &stopped,
+            liveness.as_deref(),
+        );
+        if !stopped.swap(true, Ordering::Relaxed) {
+            println!("RTSP session {id} ended");
+        }
+    });
+    Ok(())
+}
+
+fn forward_packets(
+    receiver: &Receiver<Arc<RtpPacket>>,
+    transports: &[Option<TrackTransport>],
+    writer: &Mutex<TcpStream>,
+    stopped: &AtomicBool,
+    liveness: Option<&Liveness>,
+) {
+    while !stopped.load(Ordering::Relaxed) {
+        if liveness.is_some_and(Liveness::expired) {
+            break;
+        }
+        let packet = match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(packet) => packet,
+            Err(RecvTimeoutError::Timeout) => continue,
+            // The camera stopped publishing.
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        let result = match transports.get(packet.track) {
+            Some(Some(TrackTransport::Tcp { channel })) => {
+                let mut frame = Vec::with_capacity(packet.data.len() + 4);
+                frame.push(b'$');
+                frame.push(*channel);
+                frame.extend_from_slice(&(packet.data.len() as u16).to_be_bytes());
+                frame.extend_from_slice(&packet.data);
+                match writer.lock() {
+                    Ok(mut stream) => stream.write_all(&frame),
+                    Err(_) => break,
+                }
+            }
+            Some(Some(TrackTransport::Udp { socket, peer })) => {
+                socket.send_to(&packet.data, peer).map(|_| ())
+            }
+            _ => Ok(()),
+        };
+        if result.is_err() {
+            break;
+        }
+    }
+}
+
+fn session_header(id: &str) -> String {
+    format!("{id};timeout={SESSION_TIMEOUT_SECONDS}")
+}
+
+fn new_session_id() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+    format!(
+        "{:016X}",
+        (nanos as u64) ^ u64::from(std::process::id()).rotate_left(32)
+    )
+}
+
+fn write_response(
+    writer: &Mutex<TcpStream>,
+    request: &RtspRequest,
+    status: u16,
+    reason: &str,
+    headers: &[(&str, String)],
+    body: &str,
+) -> io::Result<()> {
+    let mut response = format!("RTSP/1.0 {status} {reason}\r\n");
+    if let Some(cseq) = &request.cseq {
+        response.push_str(&format!("CSeq: {cseq}\r\n"));
+    }
+    for (name, value) in headers {
+        response.push_str(&format!("{name}: {value}\r\n"));
+    }
+    response.push_str(&format!("Content-Length: {}\r\n\r\n{body}", body.len()));
+
+    let mut stream = writer
+        .lock()
+        .map_err(|_| io::Error::other("RTSP connection writer poisoned"))?;
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
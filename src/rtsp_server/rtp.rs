@@ -0,0 +1,291 @@
+use std::fmt::Write as _;
+
+use crate::recorder::annexb_nal_units;
+
+/// Largest RTP payload sent in one packet; keeps packets below a typical 1500-byte MTU.
+const MAX_PAYLOAD: usize = 1400;
+
+/// Codec of a republished track, as far as RTP packetization is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackCodec {
+    H264,
+    H265,
+    Aac,
+    /// G.711 µ-law.
+    Pcmu,
+    /// G.711 A-law.
+    Pcma,
+    Opus,
+}
+
+/// One elementary stream republished by the RTSP server.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestreamTrack {
+    pub codec: TrackCodec,
+    /// RTP clock rate in Hz: 90 kHz for video, the sample rate for audio.
+    pub clock_rate: u32,
+    /// Audio channel count; ignored for video.
+    pub channels: u32,
+    /// Annex B parameter sets for video, the AudioSpecificConfig for AAC.
+    pub extradata: Vec<u8>,
+}
+
+impl RestreamTrack {
+    /// Static payload type for G.711, a dynamic one derived from the track index otherwise.
+    pub fn payload_type(&self, index: usize) -> u8 {
+        match self.codec {
+            TrackCodec::Pcmu => 0,
+            TrackCodec::Pcma => 8,
+            _ => 96 + (index % 32) as u8,
+        }
+    }
+
+    pub(crate) fn is_video(&self) -> bool {
+        matches!(self.codec, TrackCodec::H264 | TrackCodec::H265)
+    }
+}
+
+/// SDP returned by `DESCRIBE`, with one media section per track controlled as `trackID=<index>`.
+pub fn session_description(name: &str, tracks: &[RestreamTrack]) -> String {
+    let mut sdp = format!(
+        "v=0\r\no=- 0 1 IN IP4 0.0.0.0\r\ns={name}\r\nc=IN IP4 0.0.0.0\r\nt=0 0\r\na=control:*\r\na=range:npt=now-\r\n"
+    );
+
+    for (index, track) in tracks.iter().enumerate() {
+        let payload_type = track.payload_type(index);
+        let medium = if track.is_video() { "video" } else { "audio" };
+        let _ = write!(sdp, "m={medium} 0 RTP/AVP {payload_type}\r\n");
+
+        let nal_units = || annexb_nal_units(&track.extradata);
+        match track.codec {
+            TrackCodec::H264 => {
+                let _ = write!(sdp, "a=rtpmap:{payload_type} H264/{}\r\n", track.clock_rate);
+                let mut fmtp = String::from("packetization-mode=1");
+                let sps = nal_units().find(|nal| nal.first().map(|h| h & 0x1f) == Some(7));
+                if let Some(profile) = sps.and_then(|sps| sps.get(1..4)) {
+                    let _ = write!(
+                        fmtp,
+                        "; profile-level-id={:02X}{:02X}{:02X}",
+                        profile[0], profile[1], profile[2]
+                    );
+                }
+                let parameter_sets: Vec<String> = nal_units()
+                    .filter(|nal| matches!(nal.first().map(|h| h & 0x1f), Some(7 | 8)))
+                    .map(base64)
+                    .collect();
+                if !parameter_sets.is_empty() {
+                    let _ = write!(fmtp, "; sprop-parameter-sets={}", parameter_sets.join(","));
+                }
+                let _ = write!(sdp, "a=fmtp:{payload_type} {fmtp}\r\n");
+            }
+            TrackCodec::H265 => {
+                let _ = write!(sdp, "a=rtpmap:{payload_type} H265/{}\r\n", track.clock_rate);
+                let parameter_sets: Vec<String> = [(32, "vps"), (33, "sps"), (34, "pps")]
+                    .into_iter()
+                    .filter_map(|(nal_type, name)| {
+                        let nal = nal_units()
+                            .find(|nal| nal.first().map(|h| (h >> 1) & 0x3f) == Some(nal_type))?;
+                        Some(format!("sprop-{name}={}", base64(nal)))
+                    })
+                    .collect();
+                if !parameter_sets.is_empty() {
+                    let _ = write!(
+                        sdp,
+                        "a=fmtp:{payload_type} {}\r\n",
+                        parameter_sets.join("; ")
+                    );
+                }
+            }
+            TrackCodec::Aac => {
+                let _ = write!(
+                    sdp,
+                    "a=rtpmap:{payload_type} MPEG4-GENERIC/{}/{}\r\n",
+                    track.clock_rate, track.channels
+                );
+                let config: String = track
+                    .extradata
+                    .iter()
+                    .map(|byte| format!("{byte:02X}"))
+                    .collect();
+                let _ = write!(
+                    sdp,
+                    "a=fmtp:{payload_type} streamtype=5; profile-level-id=1; mode=AAC-hbr; sizelength=13; indexlength=3; indexdeltalength=3; config={config}\r\n"
+                );
+            }
+            TrackCodec::Pcmu | TrackCodec::Pcma => {
+                let name = if track.codec == TrackCodec::Pcmu {
+                    "PCMU"
+                } else {
+                    "PCMA"
+                };
+                let _ = write!(
+                    sdp,
+                    "a=rtpmap:{payload_type} {name}/{}/{}\r\n",
+                    track.clock_rate, track.channels
+                );
+            }
+            TrackCodec::Opus => {
+                // RFC 7587 always advertises Opus as 48 kHz stereo.
+                let _ = write!(sdp, "a=rtpmap:{payload_type} opus/48000/2\r\n");
+            }
+        }
+        let _ = write!(sdp, "a=control:trackID={index}\r\n");
+    }
+    sdp
+}
+
+/// Splits the access units of one track into RTP packets.
+#[derive(Debug, Clone)]
+pub struct Packetizer {
+    codec: TrackCodec,
+    channels: u32,
+    payload_type: u8,
+    ssrc: u32,
+    sequence: u16,
+    timestamp: u32,
+}
+
+impl Packetizer {
+    pub fn new(track: &RestreamTrack, payload_type: u8, ssrc: u32) -> Self {
+        Self {
+            codec: track.codec,
+            channels: track.channels.max(1),
+            payload_type,
+            ssrc,
+            sequence: 0,
+            timestamp: 0,
+        }
+    }
+
+    /// Sequence number of the next packet.
+    pub fn next_sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    /// RTP timestamp of the latest packet.
+    pub fn timestamp(&self) -> u32 {
+        self.timestamp
+    }
+
+    /// RTP packets carrying one access unit presented at RTP time `timestamp`.
+    pub fn packetize(&mut self, access_unit: &[u8], timestamp: u32) -> Vec<Vec<u8>> {
+        let mut packets = Vec::new();
+        match self.codec {
+            TrackCodec::H264 | TrackCodec::H265 => {
+                let mut nal_units: Vec<&[u8]> = annexb_nal_units(access_unit)
+                    .filter(|nal| !nal.is_empty())
+                    .collect();
+                if nal_units.is_empty() && !access_unit.is_empty() {
+                    nal_units.push(access_unit);
+                }
+                let count = nal_units.len();
+                for (index, nal) in nal_units.into_iter().enumerate() {
+                    let last = index + 1 == count;
+                    self.push_nal_unit(&mut packets, nal, timestamp, last);
+                }
+            }
+            TrackCodec::Aac => {
+                let frame = strip_adts_header(access_unit);
+                // One AU header: 13-bit size and a zero 3-bit index, preceded by its length in bits.
+                let mut payload = Vec::with_capacity(frame.len() + 4);
+                payload.extend_from_slice(&16u16.to_be_bytes());
+                payload.extend_from_slice(&((frame.len() as u16) << 3).to_be_bytes());
+                payload.extend_from_slice(frame);
+                packets.push(self.packet(&payload, timestamp, true));
+            }
+            TrackCodec::Pcmu | TrackCodec::Pcma => {
+                // One byte per sample and channel, so the clock advances by the sample count.
+                let step = MAX_PAYLOAD - MAX_PAYLOAD % self.channels as usize;
+                let mut offset = timestamp;
+                for chunk in access_unit.chunks(step) {
+                    packets.push(self.packet(chunk, offset, false));
+                    offset = offset.wrapping_add((chunk.len() / self.channels as usize) as u32);
+                }
+            }
+            TrackCodec::Opus => packets.push(self.packet(access_unit, timestamp, false)),
+        }
+        packets
+    }
+
+    /// Sends a NAL unit whole, or split into fragmentation units when it exceeds the payload size.
+    fn push_nal_unit(
+        &mut self,
+        packets: &mut Vec<Vec<u8>>,
+        nal: &[u8],
+        timestamp: u32,
+        last: bool,
+    ) {
+        if nal.len() <= MAX_PAYLOAD {
+            packets.push(self.packet(nal, timestamp, last));
+            return;
+        }
+
+        let (header, body): (Vec<u8>, &[u8]) = match self.codec {
+            // FU-A: indicator keeps F and NRI, the FU header carries the original type.
+            TrackCodec::H264 => (vec![(nal[0] & 0xe0) | 28, nal[0] & 0x1f], &nal[1..]),
+            // FU (type 49): two-byte payload header, then the original type.
+            _ => (
+                vec![(nal[0] & 0x81) | (49 << 1), nal[1], (nal[0] >> 1) & 0x3f],
+                &nal[2..],
+            ),
+        };
+        let fu_index = header.len() - 1;
+        let chunks: Vec<&[u8]> = body.chunks(MAX_PAYLOAD - header.len()).collect();
+        let count = chunks.len();
+        for (index, chunk) in chunks.into_iter().enumerate() {
+            let mut payload = header.clone();
+            if index == 0 {
+                payload[fu_index] |= 0x80;
+            }
+            if index + 1 == count {
+                payload[fu_index] |= 0x40;
+            }
+            payload.extend_from_slice(chunk);
+            packets.push(self.packet(&payload, timestamp, last && index + 1 == count));
+        }
+    }
+
+    fn packet(&mut self, payload: &[u8], timestamp: u32, marker: bool) -> Vec<u8> {
+        let mut packet = Vec::with_capacity(12 + payload.len());
+        let marker_bit = if marker { 0x80 } else { 0 };
+        packet.push(0x80);
+        packet.push(marker_bit | self.payload_type);
+        packet.extend_from_slice(&self.sequence.to_be_bytes());
+        packet.extend_from_slice(&timestamp.to_be_bytes());
+        packet.extend_from_slice(&self.ssrc.to_be_bytes());
+        packet.extend_from_slice(payload);
+        self.sequence = self.sequence.wrapping_add(1);
+        self.timestamp = timestamp;
+        packet
+    }
+}
+
+/// Drops an ADTS header, which RTP carries as AU headers instead.
+fn strip_adts_header(frame: &[u8]) -> &[u8] {
+    if frame.len() >= 7 && frame[0] == 0xff && frame[1] & 0xf0 == 0xf0 {
+        let header_len = if frame[1] & 0x01 == 0 { 9 } else { 7 };
+        return frame.get(header_len..).unwrap_or_default();
+    }
+    frame
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let triple = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for position in 0..4 {
+            if position <= chunk.len() {
+                encoded.push(ALPHABET[((triple >> (18 - 6 * position)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
@@ -0,0 +1,681 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::recorder::{
+    SnapshotHub, blocking_reload_timeout, parse_window_time, playlist_has_part, preload_hint_uri,
+    window_playlist,
+};
+
+/// How often a blocked request re-checks the file it is waiting for.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long an on-demand snapshot request waits for the recorder to capture a frame.
+const SNAPSHOT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long a connection may stall reading a request or accepting a response before it is closed.
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Largest request line plus headers accepted, in bytes.
+pub const MAX_HEAD_BYTES: u64 = 16 * 1024;
+
+/// Most header lines accepted in one request.
+pub const MAX_HEADERS: usize = 100;
+
+/// Connections served at once; further clients get `503` until one closes.
+const MAX_CONNECTIONS: usize = 256;
+
+/// Settings for the built-in HTTP server that publishes recorded playlists and segments.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ServerConfig {
+    /// Socket address to listen on, e.g. `0.0.0.0:8080`.
+    pub listen: String,
+    /// Directory served at `/`; usually the common parent of the recordings' playlists.
+    pub root: String,
+    /// Optional authenticated route serving HLS encryption keys under `/keys/`.
+    #[serde(default)]
+    pub keys: Option<KeyRouteConfig>,
+}
+
+/// Location of encryption keys and the token clients must present to fetch them.
+#[derive(Debug, Clone, Deserialize)]
+pub struct KeyRouteConfig {
+    /// Directory served at `/keys/`; recordings' `key_dir`s live below it.
+    pub dir: String,
+    /// Secret expected as `Authorization: Bearer <token>` or a `token` query parameter.
+    pub token: String,
+}
+
+/// A parsed HTTP request line plus the headers the server cares about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    /// HTTP method, e.g. `GET`.
+    pub method: String,
+    /// Decoded path component of the request target.
+    pub path: String,
+    /// Raw query string without the leading `?`.
+    pub query: Option<String>,
+    /// Value of the `Range` header, when present.
+    pub range: Option<String>,
+    /// Value of the `Authorization` header, when present.
+    pub authorization: Option<String>,
+}
+
+/// Error payload of [`Request::read`] for a head longer than [`MAX_HEAD_BYTES`] or with more
+/// than [`MAX_HEADERS`] headers.
+#[derive(Debug)]
+pub struct HeadTooLarge;
+
+impl fmt::Display for HeadTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("request head too large")
+    }
+}
+
+impl std::error::Error for HeadTooLarge {}
+
+impl HeadTooLarge {
+    /// Whether `error` was raised for an oversized request head.
+    pub fn caused(error: &io::Error) -> bool {
+        error.get_ref().is_some_and(|inner| inner.is::<Self>())
+    }
+}
+
+impl Request {
+    /// Reads a request head from `reader`, returning `None` when the peer closed the connection.
+    ///
+    /// Reading stops after [`MAX_HEAD_BYTES`], so a client cannot make the server buffer an
+    /// endless line; such heads fail with [`HeadTooLarge`].
+    pub fn read(reader: &mut impl BufRead) -> io::Result<Option<Self>> {
+        let mut budget = MAX_HEAD_BYTES;
+        let Some(line) = read_head_line(reader, &mut budget)? else {
+            return Ok(None);
+        };
+
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "malformed request line",
+            ));
+        };
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, Some(query.to_string())),
+            None => (target, None),
+        };
+
+        let mut range = None;
+        let mut authorization = None;
+        let mut headers = 0;
+        while let Some(header) = read_head_line(reader, &mut budget)? {
+            if header.trim().is_empty() {
+                break;
+            }
+            headers += 1;
+            if headers > MAX_HEADERS {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, HeadTooLarge));
+            }
+            let Some((name, value)) = header.split_once(':') else {
+                continue;
+            };
+            let name = name.trim();
+            if name.eq_ignore_ascii_case("range") {
+                range = Some(value.trim().to_string());
+            } else if name.eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_string());
+            }
+        }
+
+        Ok(Some(Self {
+            method: method.to_string(),
+            path: percent_decode(path),
+            query,
+            range,
+            authorization,
+        }))
+    }
+
+    /// Returns the value of query parameter `name`.
+    pub fn query_param(&self, name: &str) -> Option<&str> {
+        self.query.as_deref()?.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (key == name).then_some(value)
+        })
+    }
+
+    /// Checks the bearer token or `token` query parameter against `token`.
+    pub fn is_authorized(&self, token: &str) -> bool {
+        let presented = self
+            .authorization
+            .as_deref()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .or_else(|| self.query_param("token"));
+        presented.is_some_and(|presented| constant_time_eq(presented.as_bytes(), token.as_bytes()))
+    }
+}
+
+/// Reads one line of a request head, taking at most `budget` bytes, which is reduced by what was
+/// read. Returns `None` at the end of the stream.
+fn read_head_line(reader: &mut impl BufRead, budget: &mut u64) -> io::Result<Option<String>> {
+    if *budget == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, HeadTooLarge));
+    }
+    let mut line = Vec::new();
+    let read = reader.by_ref().take(*budget).read_until(b'\n', &mut line)?;
+    *budget -= read as u64;
+    if read == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with(b"\n") && *budget == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, HeadTooLarge));
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+/// Compares secrets without returning early on the first differing byte.
+fn constant_time_eq(left: &[u8], right: &[u8]) -> bool {
+    if left.len() != right.len() {
+        return false;
+    }
+    left.iter()
+        .zip(right)
+        .fold(0u8, |difference, (a, b)| difference | (a ^ b))
+        == 0
+}
+
+/// Binds the listener and serves requests until the process exits, one thread per connection.
+///
+/// At most [`MAX_CONNECTIONS`] connections are served at once. Cameras registered with
+/// `snapshots` are served at `/cameras/{id}/snapshot.jpg`.
+pub fn serve(config: &ServerConfig, snapshots: SnapshotHub) -> io::Result<()> {
+    let listener = TcpListener::bind(&config.listen)?;
+    println!("Serving {} on http://{}", config.root, config.listen);
+
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(error) => {
+                eprintln!("Failed to accept connection: {error}");
+                continue;
+            }
+        };
+        // Idle or stalled clients would otherwise hold their thread forever.
+        if let Err(error) = stream
+            .set_read_timeout(Some(CONNECTION_TIMEOUT))
+            .and_then(|()| stream.set_write_timeout(Some(CONNECTION_TIMEOUT)))
+        {
+            eprintln!("Failed to configure connection: {error}");
+            continue;
+        }
+        let Some(slot) = ConnectionSlot::claim(&active) else {
+            let _ = write_status(&mut stream, 503, "Service Unavailable");
+            continue;
+        };
+        let config = config.clone();
+        let snapshots = snapshots.clone();
+        thread::spawn(move || {
+            let _slot = slot;
+            if let Err(error) = handle_connection(stream, &config, &snapshots)
+                && !matches!(
+                    error.kind(),
+                    io::ErrorKind::BrokenPipe | io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                )
+            {
+                eprintln!("HTTP connection error: {error}");
+            }
+        });
+    }
+    Ok(())
+}
+
+/// One of the [`MAX_CONNECTIONS`] connections served at once, released when dropped.
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl ConnectionSlot {
+    fn claim(active: &Arc<AtomicUsize>) -> Option<Self> {
+        active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| {
+                (count < MAX_CONNECTIONS).then_some(count + 1)
+            })
+            .ok()
+            .map(|_| Self(Arc::clone(active)))
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    config: &ServerConfig,
+    snapshots: &SnapshotHub,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut stream = stream;
+    loop {
+        let request = match Request::read(&mut reader) {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(error) if HeadTooLarge::caused(&error) => {
+                return write_status(&mut stream, 431, "Request Header Fields Too Large");
+            }
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                return write_status(&mut stream, 400, "Bad Request");
+            }
+            Err(error) => return Err(error),
+        };
+        respond(&mut stream, config, snapshots, &request)?;
+    }
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    config: &ServerConfig,
+    snapshots: &SnapshotHub,
+    request: &Request,
+) -> io::Result<()> {
+    if request.method != "GET" && request.method != "HEAD" {
+        return write_status(stream, 405, "Method Not Allowed");
+    }
+
+    if let Some(key_path) = request.path.strip_prefix("/keys/") {
+        let Some(keys) = &config.keys else {
+            return write_status(stream, 404, "Not Found");
+        };
+        return serve_key(stream, keys, key_path, request);
+    }
+
+    if let Some(camera) = request
+        .path
+        .strip_prefix("/cameras/")
+        .and_then(|rest| rest.strip_suffix("/snapshot.jpg"))
+    {
+        return serve_snapshot(stream, snapshots, camera, request);
+    }
+
+    let Some(path) = resolve_path(Path::new(&config.root), &request.path) else {
+        return write_status(stream, 404, "Not Found");
+    };
+
+    if path.extension().is_some_and(|ext| ext == "m3u8") {
+        if request.query_param("start").is_some() || request.query_param("end").is_some() {
+            return serve_window(stream, &path, request);
+        }
+        return serve_playlist(stream, &path, request);
+    }
+    serve_file(stream, &path, request)
+}
+
+/// Serves a playlist, holding LL-HLS blocking reload requests until the asked-for part exists.
+fn serve_playlist(stream: &mut TcpStream, path: &Path, request: &Request) -> io::Result<()> {
+    let msn = request
+        .query_param("_HLS_msn")
+        .and_then(|value| value.parse().ok());
+    let part = request
+        .query_param("_HLS_part")
+        .and_then(|value| value.parse().ok());
+
+    let Ok(mut playlist) = fs::read_to_string(path) else {
+        return write_status(stream, 404, "Not Found");
+    };
+
+    if let Some(msn) = msn {
+        let deadline = Instant::now() + blocking_reload_timeout(&playlist);
+        while !playlist_has_part(&playlist, msn, part) {
+            if Instant::now() >= deadline {
+                return write_status(stream, 503, "Service Unavailable");
+            }
+            thread::sleep(POLL_INTERVAL);
+            playlist = fs::read_to_string(path).unwrap_or(playlist);
+        }
+    }
+
+    write_body(
+        stream,
+        request,
+        200,
+        "OK",
+        content_type(path),
+        &[("Cache-Control", "no-cache".to_string())],
+        playlist.as_bytes(),
+    )
+}
+
+/// Serves a VOD playlist of the recording between the `start` and `end` query parameters, given
+/// as epoch seconds or ISO 8601 date-times.
+///
+/// The playlist is served from the media playlist's own URL, so segment URIs resolve alike.
+fn serve_window(stream: &mut TcpStream, path: &Path, request: &Request) -> io::Result<()> {
+    let boundary = |name| {
+        request
+            .query_param(name)
+            .and_then(|value| parse_window_time(&percent_decode(value)))
+    };
+    let (Some(start), Some(end)) = (boundary("start"), boundary("end")) else {
+        return write_status(stream, 400, "Bad Request");
+    };
+    if end <= start {
+        return write_status(stream, 400, "Bad Request");
+    }
+    // Key rotation is not recorded in the index, so encrypted windows cannot be rebuilt.
+    match fs::read_to_string(path) {
+        Ok(playlist) if playlist.contains("#EXT-X-KEY:") => {
+            return write_status(stream, 501, "Not Implemented");
+        }
+        Ok(_) => {}
+        Err(_) => return write_status(stream, 404, "Not Found"),
+    }
+    let Ok(Some(playlist)) = window_playlist(path, start, end) else {
+        return write_status(stream, 404, "Not Found");
+    };
+
+    write_body(
+        stream,
+        request,
+        200,
+        "OK",
+        content_type(path),
+        &[("Cache-Control", "no-cache".to_string())],
+        playlist.as_bytes(),
+    )
+}
+
+/// Streams a media file, honouring single byte-range requests used by LL-HLS parts.
+///
+/// A range starting at the end of the segment an LL-HLS playlist next to it names in its preload
+/// hint waits for the data, which is how preload hints are fulfilled; other files answer `416`
+/// right away. Malformed and multi-range headers are ignored and
+/// the whole file is sent.
+fn serve_file(stream: &mut TcpStream, path: &Path, request: &Request) -> io::Result<()> {
+    let Ok(mut file) = File::open(path) else {
+        return write_status(stream, 404, "Not Found");
+    };
+    let mut length = file.metadata()?.len();
+
+    let Some(range) = request.range.as_deref().and_then(parse_range) else {
+        write_head(stream, 200, "OK", content_type(path), &[], length)?;
+        return send_file(stream, request, file, length);
+    };
+
+    if let ByteRange::From(start, _) = range
+        && start >= length
+        && is_preload_target(path)
+    {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while start >= length && Instant::now() < deadline {
+            thread::sleep(POLL_INTERVAL);
+            length = file.metadata()?.len();
+        }
+    }
+    let Some((start, end)) = range.resolve(length) else {
+        return write!(
+            stream,
+            "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{length}\r\nContent-Length: 0\r\nAccess-Control-Allow-Origin: *\r\n\r\n"
+        );
+    };
+
+    file.seek(SeekFrom::Start(start))?;
+    write_head(
+        stream,
+        206,
+        "Partial Content",
+        content_type(path),
+        &[("Content-Range", format!("bytes {start}-{end}/{length}"))],
+        end - start + 1,
+    )?;
+    send_file(stream, request, file, end - start + 1)
+}
+
+/// Whether an LL-HLS playlist in the directory of `path` announces it as the segment being
+/// written.
+fn is_preload_target(path: &Path) -> bool {
+    let Some(directory) = path.parent() else {
+        return false;
+    };
+    let Ok(entries) = fs::read_dir(directory) else {
+        return false;
+    };
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|playlist| playlist.extension().is_some_and(|ext| ext == "m3u8"))
+        .filter_map(|playlist| fs::read_to_string(playlist).ok())
+        .any(|playlist| preload_hint_uri(&playlist).is_some_and(|uri| directory.join(uri) == path))
+}
+
+/// Serves an encryption key to authenticated clients only.
+fn serve_key(
+    stream: &mut TcpStream,
+    keys: &KeyRouteConfig,
+    key_path: &str,
+    request: &Request,
+) -> io::Result<()> {
+    if !request.is_authorized(&keys.token) {
+        return write!(
+            stream,
+            "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Bearer\r\nContent-Length: 0\r\nAccess-Control-Allow-Origin: *\r\n\r\n"
+        );
+    }
+    let Some(path) = resolve_path(Path::new(&keys.dir), key_path) else {
+        return write_status(stream, 404, "Not Found");
+    };
+    let Ok(key) = fs::read(&path) else {
+        return write_status(stream, 404, "Not Found");
+    };
+
+    write_body(
+        stream,
+        request,
+        200,
+        "OK",
+        content_type(&path),
+        &[("Cache-Control", "no-store".to_string())],
+        &key,
+    )
+}
+
+/// Serves a camera's `latest.jpg`.
+///
+/// With a `fresh` query parameter, or before the first snapshot exists, the recorder is asked to
+/// capture the next decoded frame and the request waits for it.
+fn serve_snapshot(
+    stream: &mut TcpStream,
+    snapshots: &SnapshotHub,
+    camera: &str,
+    request: &Request,
+) -> io::Result<()> {
+    let Some(slot) = snapshots.slot(&percent_decode(camera)) else {
+        return write_status(stream, 404, "Not Found");
+    };
+
+    if request.query_param("fresh").is_some() || !slot.latest_path().exists() {
+        let taken = slot.taken();
+        slot.request();
+        let deadline = Instant::now() + SNAPSHOT_TIMEOUT;
+        while slot.taken() == taken {
+            if Instant::now() >= deadline {
+                return write_status(stream, 503, "Service Unavailable");
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+    let Ok(jpeg) = fs::read(slot.latest_path()) else {
+        return write_status(stream, 404, "Not Found");
+    };
+
+    write_body(
+        stream,
+        request,
+        200,
+        "OK",
+        content_type(slot.latest_path()),
+        &[("Cache-Control", "no-cache".to_string())],
+        &jpeg,
+    )
+}
+
+/// A single byte range requested through a `Range` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteRange {
+    /// `bytes=<start>-[<end>]`, with inclusive offsets.
+    From(u64, Option<u64>),
+    /// `bytes=-<length>`, the last `length` bytes.
+    Suffix(u64),
+}
+
+impl ByteRange {
+    /// Inclusive offsets of the range within a file of `length` bytes, or `None` when it is not
+    /// satisfiable.
+    pub fn resolve(self, length: u64) -> Option<(u64, u64)> {
+        match self {
+            Self::From(start, _) if start >= length => None,
+            Self::From(start, end) => {
+                Some((start, end.map_or(length - 1, |end| end.min(length - 1))))
+            }
+            Self::Suffix(suffix) if suffix == 0 || length == 0 => None,
+            Self::Suffix(suffix) => Some((length.saturating_sub(suffix), length - 1)),
+        }
+    }
+}
+
+/// Parses a single-range `bytes=<start>-[<end>]` or `bytes=-<length>` header.
+pub fn parse_range(header: &str) -> Option<ByteRange> {
+    let (start, end) = header.strip_prefix("bytes=")?.split_once('-')?;
+    let end = match end.trim() {
+        "" => None,
+        end => Some(end.parse().ok()?),
+    };
+    match (start.trim(), end) {
+        ("", Some(suffix)) => Some(ByteRange::Suffix(suffix)),
+        ("", None) => None,
+        (start, end) => {
+            let start = start.parse().ok()?;
+            match end {
+                Some(end) if end < start => None,
+                _ => Some(ByteRange::From(start, end)),
+            }
+        }
+    }
+}
+
+/// Maps a request path onto `root`, rejecting anything that could escape it.
+pub fn resolve_path(root: &Path, request_path: &str) -> Option<PathBuf> {
+    let relative = Path::new(request_path.trim_start_matches('/'));
+    if relative
+        .components()
+        .any(|component| !matches!(component, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(root.join(relative))
+}
+
+/// MIME type for the files a recorder produces.
+pub fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("m3u8") => "application/vnd.apple.mpegurl",
+        Some("ts") => "video/mp2t",
+        Some("m4s") => "video/iso.segment",
+        Some("mp4") => "video/mp4",
+        Some("jpg") => "image/jpeg",
+        Some("vtt") => "text/vtt",
+        _ => "application/octet-stream",
+    }
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = bytes
+            .get(index + 1..index + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[index], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn write_status(stream: &mut TcpStream, status: u16, reason: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status} {reason}\r\nContent-Length: 0\r\nAccess-Control-Allow-Origin: *\r\n\r\n"
+    )
+}
+
+fn write_head(
+    stream: &mut TcpStream,
+    status: u16,
+    reason: &str,
+    content_type: &str,
+    headers: &[(&str, String)],
+    length: u64,
+) -> io::Result<()> {
+    let mut head = format!(
+        "HTTP/1.1 {status} {reason}\r\nContent-Type: {content_type}\r\nContent-Length: {length}\r\nAccept-Ranges: bytes\r\nAccess-Control-Allow-Origin: *\r\n"
+    );
+    for (name, value) in headers {
+        head.push_str(&format!("{name}: {value}\r\n"));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())
+}
+
+fn write_body(
+    stream: &mut TcpStream,
+    request: &Request,
+    status: u16,
+    reason: &str,
+    content_type: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+) -> io::Result<()> {
+    write_head(
+        stream,
+        status,
+        reason,
+        content_type,
+        headers,
+        body.len() as u64,
+    )?;
+    if request.method != "HEAD" {
+        stream.write_all(body)?;
+    }
+    stream.flush()
+}
+
+/// Sends the next `length` bytes of `file` as a response body without buffering them whole.
+fn send_file(stream: &mut TcpStream, request: &Request, file: File, length: u64) -> io::Result<()> {
+    if request.method != "HEAD" && io::copy(&mut file.take(length), stream)? < length {
+        // The file shrank after its length was announced, so the response cannot be completed.
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "file ended before the announced length",
+        ));
+    }
+    stream.flush()
+}
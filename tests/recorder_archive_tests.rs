@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use otnvr::recorder::{ArchiveFormat, ArchiveOptions, session_name};
+
+#[test]
+fn single_archive_writes_crash_safe_fragmented_mp4() {
+    let options = ArchiveOptions::default();
+
+    assert_eq!(options.muxer(), "mp4");
+    assert_eq!(
+        options.muxer_options(),
+        vec![
+            ("flush_packets", "1".to_string()),
+            (
+                "movflags",
+                "frag_keyframe+empty_moov+default_base_moof".to_string()
+            ),
+        ]
+    );
+    assert_eq!(
+        options.file_template(Path::new("archive/camera.mp4"), "20240501T120000Z"),
+        "archive/camera_20240501T120000Z.mp4"
+    );
+}
+
+#[test]
+fn consecutive_single_archives_get_distinct_files() {
+    let options = ArchiveOptions {
+        format: ArchiveFormat::Mkv,
+        rotate_minutes: None,
+    };
+    let path = Path::new("archive/camera");
+
+    let first = options.file_template(path, &session_name(1_714_564_800.0));
+    // Restarted a minute later.
+    let second = options.file_template(path, &session_name(1_714_564_860.0));
+    assert_eq!(first, "archive/camera_20240501T120000Z.mkv");
+    assert_eq!(second, "archive/camera_20240501T120100Z.mkv");
+}
+
+#[test]
+fn rotation_uses_the_segment_muxer_aligned_to_the_clock() {
+    let options = ArchiveOptions {
+        format: ArchiveFormat::Mkv,
+        rotate_minutes: Some(60),
+    };
+
+    assert_eq!(options.muxer(), "segment");
+    let muxer_options = options.muxer_options();
+    let value = |name: &str| {
+        muxer_options
+            .iter()
+            .find(|(option, _)| *option == name)
+            .map(|(_, value)| value.as_str())
+    };
+    assert_eq!(value("segment_format"), Some("matroska"));
+    assert_eq!(
+        value("segment_format_options"),
+        Some("cluster_time_limit=1000")
+    );
+    assert_eq!(value("segment_time"), Some("3600"));
+    assert_eq!(value("segment_atclocktime"), Some("1"));
+    assert_eq!(value("strftime"), Some("1"));
+}
+
+#[test]
+fn rotated_file_names_carry_the_start_time() {
+    let options = ArchiveOptions {
+        format: ArchiveFormat::Mp4,
+        rotate_minutes: Some(15),
+    };
+
+    assert_eq!(
+        options.file_template(Path::new("archive/camera.mp4"), "20240501T120000Z"),
+        "archive/camera_%Y%m%d-%H%M%S.mp4"
+    );
+    assert_eq!(
+        options.file_template(Path::new("archive/camera"), "20240501T120000Z"),
+        "archive/camera_%Y%m%d-%H%M%S.mp4"
+    );
+    assert_eq!(
+        options.file_template(Path::new("archive/%Y-%m-%d/%H%M.mp4"), "20240501T120000Z"),
+        "archive/%Y-%m-%d/%H%M.mp4"
+    );
+}
@@ -0,0 +1,94 @@
+use otnvr::recorder::{SegmentRecord, coverage_report};
+
+/// 2024-05-01T00:00:00Z.
+const MIDNIGHT: f64 = 1_714_521_600.0;
+
+/// Back-to-back four-second segments covering `start..end`.
+fn segments(first_sequence: u64, start: f64, end: f64) -> Vec<SegmentRecord> {
+    let count = ((end - start) / 4.0) as u64;
+    (0..count)
+        .map(|i| SegmentRecord {
+            uri: format!("stream_{:05}.ts", first_sequence + i),
+            sequence: first_sequence + i,
+            start: start + 4.0 * i as f64,
+            duration: 4.0,
+            discontinuity: false,
+        })
+        .collect()
+}
+
+#[test]
+fn gaps_are_listed_and_long_ones_flagged_as_events() {
+    let mut records = segments(0, MIDNIGHT, MIDNIGHT + 600.0);
+    // A 20-second hiccup, then a 10-minute outage.
+    records.extend(segments(150, MIDNIGHT + 620.0, MIDNIGHT + 1200.0));
+    records.extend(segments(295, MIDNIGHT + 1800.0, MIDNIGHT + 3600.0));
+
+    let report = coverage_report(&records, None, None, 60.0).expect("report");
+
+    assert_eq!(report.start, MIDNIGHT);
+    assert_eq!(report.end, MIDNIGHT + 3600.0);
+    assert_eq!(report.gaps.len(), 2);
+    assert_eq!(report.gaps[0].duration, 20.0);
+    assert!(!report.gaps[0].event);
+    assert_eq!(report.gaps[1].start, MIDNIGHT + 1200.0);
+    assert_eq!(report.gaps[1].duration, 600.0);
+    assert_eq!(report.events().count(), 1);
+    assert_eq!(report.recorded_seconds, 2980.0);
+    assert!((report.uptime_percent - 2980.0 / 36.0).abs() < 1e-9);
+}
+
+#[test]
+fn outage_up_to_the_window_end_is_a_trailing_gap() {
+    let records = segments(0, MIDNIGHT, MIDNIGHT + 600.0);
+
+    // The camera went down at 00:10 and is still down at 00:30.
+    let report = coverage_report(&records, None, Some(MIDNIGHT + 1800.0), 60.0).expect("report");
+
+    assert_eq!(report.end, MIDNIGHT + 1800.0);
+    assert_eq!(report.gaps.len(), 1);
+    assert_eq!(report.gaps[0].start, MIDNIGHT + 600.0);
+    assert_eq!(report.gaps[0].duration, 1200.0);
+    assert!(report.gaps[0].event);
+    assert!((report.uptime_percent - 100.0 / 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn days_break_down_partial_and_missing_hours() {
+    let mut records = segments(0, MIDNIGHT, MIDNIGHT + 3600.0);
+    records.extend(segments(900, MIDNIGHT + 7200.0, MIDNIGHT + 9000.0));
+
+    let report = coverage_report(
+        &records,
+        Some(MIDNIGHT),
+        Some(MIDNIGHT + 86_400.0 + 3600.0),
+        60.0,
+    )
+    .expect("report");
+
+    assert_eq!(report.days.len(), 2);
+    let first = &report.days[0];
+    assert_eq!(first.date, "2024-05-01");
+    assert_eq!(first.recorded_seconds, 5400.0);
+    assert_eq!(first.partial_hours, vec![2]);
+    assert_eq!(first.missing_hours.len(), 22);
+    assert!(!first.missing_hours.contains(&0));
+    // Only the first hour of the second day lies inside the window.
+    let second = &report.days[1];
+    assert_eq!(second.date, "2024-05-02");
+    assert_eq!(second.missing_hours, vec![0]);
+    assert_eq!(second.uptime_percent, 0.0);
+    // The trailing outage counts as a gap too.
+    assert_eq!(report.gaps.last().expect("gap").end, MIDNIGHT + 90_000.0);
+}
+
+#[test]
+fn empty_index_or_window_has_no_report() {
+    let records = segments(0, MIDNIGHT, MIDNIGHT + 40.0);
+
+    assert_eq!(coverage_report(&[], None, None, 60.0), None);
+    assert_eq!(
+        coverage_report(&records, Some(MIDNIGHT + 10.0), Some(MIDNIGHT), 60.0),
+        None
+    );
+}
@@ -0,0 +1,42 @@
+use std::path::Path;
+
+use otnvr::recorder::{key_file_name, key_info_contents, rotation_index};
+
+#[test]
+fn key_file_names_are_numbered_per_rotation_period() {
+    assert_eq!(key_file_name("stream", 0), "stream_00000.key");
+    assert_eq!(key_file_name("stream", 42), "stream_00042.key");
+}
+
+#[test]
+fn key_info_lists_uri_path_and_optional_iv() {
+    let key_path = Path::new("/var/keys/cam/stream_00001.key");
+
+    assert_eq!(
+        key_info_contents("/keys/cam/stream_00001.key", key_path, None),
+        "/keys/cam/stream_00001.key\n/var/keys/cam/stream_00001.key\n"
+    );
+    assert_eq!(
+        key_info_contents(
+            "/keys/cam/stream_00001.key",
+            key_path,
+            Some("0123456789abcdef0123456789abcdef")
+        ),
+        "/keys/cam/stream_00001.key\n/var/keys/cam/stream_00001.key\n0123456789abcdef0123456789abcdef\n"
+    );
+}
+
+#[test]
+fn rotation_index_advances_every_n_segments() {
+    assert_eq!(rotation_index(0, Some(5)), 0);
+    assert_eq!(rotation_index(4, Some(5)), 0);
+    assert_eq!(rotation_index(5, Some(5)), 1);
+    assert_eq!(rotation_index(17, Some(5)), 3);
+    assert_eq!(rotation_index(3, Some(1)), 3);
+}
+
+#[test]
+fn rotation_index_stays_fixed_without_rotation() {
+    assert_eq!(rotation_index(1000, None), 0);
+    assert_eq!(rotation_index(1000, Some(0)), 0);
+}
@@ -0,0 +1,36 @@
+use ffmpeg_next::Rational;
+use otnvr::recorder::{FrameRateConverter, FrameRateMode};
+
+/// Feeds a constant-rate sequence of `count` frames in a 1/90000 time base.
+fn feed(converter: &mut FrameRateConverter, fps: i64, count: i64) -> Vec<i64> {
+    let step = 90_000 / fps;
+    (0..count)
+        .flat_map(|index| converter.timestamps(1_000 + index * step))
+        .collect()
+}
+
+#[test]
+fn max_fps_drops_excess_frames_and_keeps_timestamps() {
+    let mut converter = FrameRateConverter::new(FrameRateMode::Max(10.0), Rational(1, 90_000));
+
+    let timestamps = feed(&mut converter, 30, 9);
+
+    assert_eq!(timestamps, vec![1_000, 10_000, 19_000]);
+}
+
+#[test]
+fn target_fps_duplicates_frames_onto_a_fixed_grid() {
+    let mut converter = FrameRateConverter::new(FrameRateMode::Target(10.0), Rational(1, 90_000));
+
+    let timestamps = feed(&mut converter, 5, 3);
+
+    assert_eq!(timestamps, vec![1_000, 10_000, 19_000, 28_000, 37_000]);
+}
+
+#[test]
+fn target_fps_skips_padding_across_long_stalls() {
+    let mut converter = FrameRateConverter::new(FrameRateMode::Target(5.0), Rational(1, 90_000));
+
+    assert_eq!(converter.timestamps(0), vec![0]);
+    assert_eq!(converter.timestamps(18_000 * 10), vec![180_000]);
+}
@@ -0,0 +1,151 @@
+use std::time::Duration;
+
+use otnvr::recorder::{
+    LlHlsPlaylist, PartialSegment, blocking_reload_timeout, playlist_has_part, preload_hint_uri,
+    segment_file_name,
+};
+
+fn part(offset: u64, length: u64, independent: bool) -> PartialSegment {
+    PartialSegment {
+        duration: 0.5,
+        offset,
+        length,
+        independent,
+    }
+}
+
+fn live_playlist() -> LlHlsPlaylist {
+    let mut playlist = LlHlsPlaylist::new("stream_init.mp4", 1.0, 0.5, 5);
+    playlist.start_segment("stream_00000.m4s");
+    playlist.add_part(part(0, 100, true));
+    playlist.add_part(part(100, 80, false));
+    playlist.finish_segment();
+    playlist.start_segment("stream_00001.m4s");
+    playlist.add_part(part(0, 120, true));
+    playlist
+}
+
+#[test]
+fn ll_hls_playlist_lists_parts_and_preload_hint() {
+    let rendered = live_playlist().render();
+
+    assert_eq!(
+        rendered,
+        "#EXTM3U\n\
+         #EXT-X-VERSION:9\n\
+         #EXT-X-TARGETDURATION:1\n\
+         #EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK=1.500\n\
+         #EXT-X-PART-INF:PART-TARGET=0.500\n\
+         #EXT-X-MEDIA-SEQUENCE:0\n\
+         #EXT-X-MAP:URI=\"stream_init.mp4\"\n\
+         #EXT-X-PART:DURATION=0.500,URI=\"stream_00000.m4s\",BYTERANGE=\"100@0\",INDEPENDENT=YES\n\
+         #EXT-X-PART:DURATION=0.500,URI=\"stream_00000.m4s\",BYTERANGE=\"80@100\"\n\
+         #EXTINF:1.000,\n\
+         stream_00000.m4s\n\
+         #EXT-X-PART:DURATION=0.500,URI=\"stream_00001.m4s\",BYTERANGE=\"120@0\",INDEPENDENT=YES\n\
+         #EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"stream_00001.m4s\",BYTERANGE-START=120\n"
+    );
+}
+
+#[test]
+fn ll_hls_playlist_slides_window_and_ends() {
+    let mut playlist = LlHlsPlaylist::new("init.mp4", 1.0, 0.5, 1);
+    for index in 0..3 {
+        playlist.start_segment(&format!("seg_{index}.m4s"));
+        playlist.add_part(part(0, 10, true));
+        playlist.add_part(part(10, 10, false));
+        playlist.finish_segment();
+    }
+    playlist.end();
+
+    let rendered = playlist.render();
+
+    assert!(rendered.contains("#EXT-X-MEDIA-SEQUENCE:2\n"));
+    assert!(!rendered.contains("seg_1.m4s"));
+    assert!(rendered.ends_with("seg_2.m4s\n#EXT-X-ENDLIST\n"));
+    assert!(!rendered.contains("PRELOAD-HINT"));
+}
+
+#[test]
+fn ll_hls_playlist_dates_segments() {
+    let mut playlist = LlHlsPlaylist::new("init.mp4", 1.0, 0.5, 5);
+    playlist.start_segment("seg_0.m4s");
+    playlist.set_program_date_time(1_714_564_800.0);
+    playlist.add_part(part(0, 10, true));
+    playlist.finish_segment();
+    playlist.start_segment("seg_1.m4s");
+    playlist.set_program_date_time(1_714_564_801.0);
+    playlist.add_part(part(0, 10, true));
+
+    let rendered = playlist.render();
+
+    assert!(rendered.contains(
+        "#EXT-X-PROGRAM-DATE-TIME:2024-05-01T12:00:00.000Z\n#EXT-X-PART:DURATION=0.500,URI=\"seg_0.m4s\""
+    ));
+    assert!(rendered.contains(
+        "#EXT-X-PROGRAM-DATE-TIME:2024-05-01T12:00:01.000Z\n#EXT-X-PART:DURATION=0.500,URI=\"seg_1.m4s\""
+    ));
+}
+
+#[test]
+fn ll_hls_playlist_tags_discontinuities() {
+    let mut playlist = LlHlsPlaylist::new("init.mp4", 1.0, 0.5, 1);
+    for index in 0..3 {
+        playlist.start_segment(&format!("seg_{index}.m4s"));
+        if index > 0 {
+            playlist.set_discontinuity();
+        }
+        playlist.add_part(part(0, 10, true));
+        playlist.finish_segment();
+    }
+    playlist.start_segment("seg_3.m4s");
+    playlist.add_part(part(0, 10, true));
+
+    let rendered = playlist.render();
+
+    assert!(rendered.contains("#EXT-X-MEDIA-SEQUENCE:2\n#EXT-X-DISCONTINUITY-SEQUENCE:1\n"));
+    assert!(
+        rendered.contains("#EXT-X-DISCONTINUITY\n#EXT-X-PART:DURATION=0.500,URI=\"seg_2.m4s\"")
+    );
+    assert!(
+        !rendered.contains("#EXT-X-DISCONTINUITY\n#EXT-X-PART:DURATION=0.500,URI=\"seg_3.m4s\"")
+    );
+}
+
+#[test]
+fn playlist_has_part_tracks_live_edge() {
+    let rendered = live_playlist().render();
+
+    assert!(playlist_has_part(&rendered, 0, None));
+    assert!(playlist_has_part(&rendered, 0, Some(5)));
+    assert!(playlist_has_part(&rendered, 1, Some(0)));
+    assert!(!playlist_has_part(&rendered, 1, Some(1)));
+    assert!(!playlist_has_part(&rendered, 1, None));
+    assert!(!playlist_has_part(&rendered, 2, Some(0)));
+    assert!(playlist_has_part("#EXTM3U\n#EXT-X-ENDLIST\n", 9, None));
+}
+
+#[test]
+fn preload_hint_names_the_segment_being_written() {
+    let rendered = live_playlist().render();
+
+    assert_eq!(preload_hint_uri(&rendered), Some("stream_00001.m4s"));
+    assert_eq!(preload_hint_uri("#EXTM3U\n#EXT-X-ENDLIST\n"), None);
+}
+
+#[test]
+fn blocking_reload_timeout_is_three_target_durations() {
+    let rendered = live_playlist().render();
+
+    assert_eq!(blocking_reload_timeout(&rendered), Duration::from_secs(3));
+}
+
+#[test]
+fn segment_file_name_expands_printf_counter() {
+    assert_eq!(
+        segment_file_name("out/stream_%05d.m4s", 42),
+        "out/stream_00042.m4s"
+    );
+    assert_eq!(segment_file_name("seg%d.m4s", 7), "seg7.m4s");
+    assert_eq!(segment_file_name("fixed.m4s", 7), "fixed.m4s");
+}
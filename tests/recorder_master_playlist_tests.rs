@@ -0,0 +1,134 @@
+use std::path::{Path, PathBuf};
+
+use otnvr::recorder::{
+    AudioOptions, HlsOutput, Packaging, VariantStream, VideoCodec, audio_codec_string,
+    h264_codec_string, hevc_codec_string, master_playlist, variant_uri,
+};
+
+fn sized_output(width: Option<u32>, height: Option<u32>) -> HlsOutput {
+    HlsOutput {
+        playlist_path: PathBuf::from("out/stream.m3u8"),
+        segment_duration: None,
+        playlist_size: None,
+        segment_filename: None,
+        video_codec: VideoCodec::H264,
+        audio: AudioOptions::default(),
+        width,
+        height,
+        video_bitrate: None,
+        low_latency: None,
+        packaging: Packaging::Hls,
+        segment_format: None,
+        encryption: None,
+        program_date_time: false,
+        push: Vec::new(),
+        master_playlist: None,
+        append: false,
+        thumbnails: None,
+    }
+}
+
+#[test]
+fn master_playlist_lists_variants_with_attributes() {
+    let variants = [
+        VariantStream {
+            uri: "stream_720p.m3u8".to_string(),
+            bandwidth: 2_596_000,
+            resolution: Some((1280, 720)),
+            codecs: vec!["avc1.64001f".to_string(), "mp4a.40.2".to_string()],
+        },
+        VariantStream {
+            uri: "stream_audio.m3u8".to_string(),
+            bandwidth: 96_000,
+            resolution: None,
+            codecs: Vec::new(),
+        },
+    ];
+
+    let playlist = master_playlist(&variants);
+
+    assert_eq!(
+        playlist,
+        "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-INDEPENDENT-SEGMENTS\n\
+         #EXT-X-STREAM-INF:BANDWIDTH=2596000,RESOLUTION=1280x720,CODECS=\"avc1.64001f,mp4a.40.2\"\n\
+         stream_720p.m3u8\n\
+         #EXT-X-STREAM-INF:BANDWIDTH=96000\n\
+         stream_audio.m3u8\n"
+    );
+}
+
+#[test]
+fn variant_uri_is_relative_to_master_directory() {
+    let master = Path::new("out/cam/stream.m3u8");
+
+    assert_eq!(
+        variant_uri(master, Path::new("out/cam/stream_720p.m3u8")),
+        "stream_720p.m3u8"
+    );
+    assert_eq!(
+        variant_uri(master, Path::new("out/cam/low/index.m3u8")),
+        "low/index.m3u8"
+    );
+    assert_eq!(
+        variant_uri(master, Path::new("elsewhere/index.m3u8")),
+        "elsewhere/index.m3u8"
+    );
+}
+
+#[test]
+fn h264_codec_string_reads_annexb_and_avcc() {
+    let annexb = [
+        0, 0, 0, 1, 0x67, 0x64, 0x00, 0x1f, 0xac, 0, 0, 1, 0x68, 0xeb,
+    ];
+    let avcc = [1, 0x4d, 0x40, 0x28, 0xff, 0xe1];
+
+    assert_eq!(h264_codec_string(&annexb).as_deref(), Some("avc1.64001f"));
+    assert_eq!(h264_codec_string(&avcc).as_deref(), Some("avc1.4d4028"));
+    assert_eq!(h264_codec_string(&[0, 0, 1, 0x68, 0xeb]), None);
+}
+
+#[test]
+fn hevc_codec_string_reads_profile_tier_level() {
+    // Main profile, main tier, level 3.1 (93), progressive source flag set.
+    let extradata = [
+        0, 0, 0, 1, 0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00, 0x03,
+        0x00, 0x00, 0x03, 0x00, 0x5d,
+    ];
+
+    assert_eq!(
+        hevc_codec_string(&extradata).as_deref(),
+        Some("hvc1.1.6.L93.90")
+    );
+}
+
+#[test]
+fn audio_codec_string_maps_hls_codecs() {
+    assert_eq!(audio_codec_string("aac", 1).as_deref(), Some("mp4a.40.2"));
+    assert_eq!(audio_codec_string("aac", 4).as_deref(), Some("mp4a.40.5"));
+    assert_eq!(audio_codec_string("opus", 0).as_deref(), Some("opus"));
+    assert_eq!(audio_codec_string("pcm_alaw", 0), None);
+}
+
+#[test]
+fn output_size_keeps_source_aspect_and_even_dimensions() {
+    assert_eq!(
+        sized_output(None, None).output_size(1920, 1080),
+        (1920, 1080)
+    );
+    assert_eq!(
+        sized_output(None, Some(720)).output_size(1920, 1080),
+        (1280, 720)
+    );
+    assert_eq!(
+        sized_output(Some(640), None).output_size(1920, 1080),
+        (640, 360)
+    );
+    assert_eq!(
+        sized_output(None, Some(480)).output_size(2592, 1944),
+        (640, 480)
+    );
+    assert_eq!(
+        sized_output(None, Some(361)).output_size(1920, 1080),
+        (640, 360)
+    );
+}
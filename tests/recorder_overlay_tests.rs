@@ -0,0 +1,59 @@
+use ffmpeg_next::Rational;
+use otnvr::recorder::{
+    OverlayClock, SourceClock, TimestampAnomaly, TimestampSanitizer, overlay_text,
+};
+
+#[test]
+fn overlay_text_substitutes_camera_name() {
+    let text = overlay_text("{name} %Y", "Gate", 1_700_000_000.0);
+
+    assert_eq!(text, "%{pts:localtime:1700000000.000:Gate %Y}");
+}
+
+#[test]
+fn overlay_text_escapes_expansion_separators() {
+    let text = overlay_text("%H:%M:%S", "Gate", 12.5);
+
+    assert_eq!(text, "%{pts:localtime:12.500:%H\\:%M\\:%S}");
+}
+
+#[test]
+fn overlay_text_doubles_percent_signs_in_camera_name() {
+    let text = overlay_text("{name}", "100% Yard", 0.0);
+
+    assert_eq!(text, "%{pts:localtime:0.000:100%% Yard}");
+}
+
+#[test]
+fn overlay_clock_follows_a_rebased_gap() {
+    const START: f64 = 1_700_000_000.0;
+    // 30 fps on a 90 kHz clock.
+    const STEP: i64 = 3_000;
+    let mut sanitizer = TimestampSanitizer::new(&[Rational(1, 90_000)]);
+    let mut source_clock = SourceClock::new(1, Some(START));
+    let mut overlay = OverlayClock::new(START);
+
+    for frame in 0..=30 {
+        sanitizer.sanitize(0, Some(frame * STEP), Some(frame * STEP));
+    }
+    // The camera skips 30 seconds; the sanitizer cuts the jump out of the timestamps.
+    let source_pts = 31 * STEP + 30 * 90_000;
+    let (pts, _, correction) = sanitizer.sanitize(0, Some(source_pts), Some(source_pts));
+    assert_eq!(
+        correction.map(|correction| correction.anomaly),
+        Some(TimestampAnomaly::Gap)
+    );
+    let seconds = pts.unwrap() as f64 / 90_000.0;
+    let (epoch_seconds, _) = source_clock.wall_clock(0, source_pts as f64 / 90_000.0, None);
+    overlay.rebase(seconds, epoch_seconds);
+
+    // Frames decoded before the rebased packet keep the original start.
+    assert_eq!(overlay.frame(seconds - 1.0 / 30.0), None);
+    let start = overlay.frame(seconds).unwrap();
+    assert!((start - (START + 30.0)).abs() < 1e-6);
+    assert_eq!(
+        overlay_text("%T", "Gate", overlay.start_epoch_seconds()),
+        "%{pts:localtime:1700000030.000:%T}"
+    );
+    assert_eq!(overlay.frame(seconds + 1.0), None);
+}
@@ -0,0 +1,41 @@
+use otnvr::recorder::{MaskBitmap, rgb_to_yuv};
+
+#[test]
+fn rasterize_covers_pixels_inside_rectangle() {
+    let points = [[0.25, 0.25], [0.75, 0.25], [0.75, 0.75], [0.25, 0.75]];
+
+    let bitmap = MaskBitmap::rasterize(&points, 8, 8);
+
+    assert_eq!(bitmap.area(), 16);
+    assert!(bitmap.contains(2, 2));
+    assert!(bitmap.contains(5, 5));
+    assert!(!bitmap.contains(1, 2));
+    assert!(!bitmap.contains(6, 5));
+}
+
+#[test]
+fn rasterize_follows_triangle_edges() {
+    let points = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+
+    let bitmap = MaskBitmap::rasterize(&points, 4, 4);
+
+    assert!(bitmap.contains(0, 0));
+    assert!(bitmap.contains(2, 0));
+    assert!(!bitmap.contains(3, 3));
+    assert_eq!(bitmap.area(), 6);
+}
+
+#[test]
+fn rasterize_ignores_degenerate_polygons() {
+    let bitmap = MaskBitmap::rasterize(&[[0.1, 0.1], [0.9, 0.9]], 16, 16);
+
+    assert_eq!(bitmap.area(), 0);
+    assert!(!bitmap.contains(8, 8));
+}
+
+#[test]
+fn rgb_to_yuv_maps_black_and_white_to_video_range() {
+    assert_eq!(rgb_to_yuv([0, 0, 0], false), [16, 128, 128]);
+    assert_eq!(rgb_to_yuv([255, 255, 255], false), [235, 128, 128]);
+    assert_eq!(rgb_to_yuv([0, 0, 0], true), [0, 128, 128]);
+}
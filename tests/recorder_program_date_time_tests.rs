@@ -0,0 +1,66 @@
+use otnvr::recorder::{
+    WallClock, first_program_date_time, format_program_date_time, parse_program_date_time,
+    rewrite_program_date_times,
+};
+
+#[test]
+fn format_program_date_time_writes_utc_with_milliseconds() {
+    assert_eq!(format_program_date_time(0.0), "1970-01-01T00:00:00.000Z");
+    assert_eq!(
+        format_program_date_time(1_714_564_800.25),
+        "2024-05-01T12:00:00.250Z"
+    );
+    assert_eq!(
+        format_program_date_time(951_782_400.0),
+        "2000-02-29T00:00:00.000Z"
+    );
+}
+
+#[test]
+fn parse_program_date_time_accepts_ffmpeg_offsets() {
+    assert_eq!(
+        parse_program_date_time("2024-05-01T12:00:00.250Z"),
+        Some(1_714_564_800.25)
+    );
+    assert_eq!(
+        parse_program_date_time("2024-05-01T14:00:00.000+0200"),
+        Some(1_714_564_800.0)
+    );
+    assert_eq!(
+        parse_program_date_time("2024-05-01T07:30:00.000-04:30"),
+        Some(1_714_564_800.0)
+    );
+    assert_eq!(parse_program_date_time("yesterday"), None);
+}
+
+#[test]
+fn wall_clock_follows_the_latest_anchor() {
+    let mut clock = WallClock::new();
+    assert_eq!(clock.epoch_at(1.0), None);
+
+    clock.anchor(0.0, 1000.0);
+    assert_eq!(clock.epoch_at(4.0), Some(1004.0));
+
+    // A discontinuity at media time 10 resumes after a 30 second outage.
+    clock.anchor(10.0, 1040.0);
+    assert_eq!(clock.epoch_at(8.0), Some(1008.0));
+    assert_eq!(clock.epoch_at(12.0), Some(1042.0));
+}
+
+#[test]
+fn rewrite_program_date_times_replaces_muxer_dates() {
+    let playlist = "#EXTM3U\n#EXT-X-MEDIA-SEQUENCE:0\n\
+        #EXT-X-PROGRAM-DATE-TIME:2024-05-01T14:00:00.000+0200\n#EXTINF:4.000,\nstream_00000.ts\n\
+        #EXT-X-PROGRAM-DATE-TIME:2024-05-01T14:00:04.000+0200\n#EXTINF:4.000,\nstream_00001.ts\n";
+    let origin = first_program_date_time(playlist).expect("dated playlist");
+
+    let mut clock = WallClock::new();
+    clock.anchor(0.0, 1_714_564_800.5);
+
+    assert_eq!(
+        rewrite_program_date_times(playlist, origin, &clock),
+        "#EXTM3U\n#EXT-X-MEDIA-SEQUENCE:0\n\
+        #EXT-X-PROGRAM-DATE-TIME:2024-05-01T12:00:00.500Z\n#EXTINF:4.000,\nstream_00000.ts\n\
+        #EXT-X-PROGRAM-DATE-TIME:2024-05-01T12:00:04.500Z\n#EXTINF:4.000,\nstream_00001.ts\n"
+    );
+}
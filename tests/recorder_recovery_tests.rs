@@ -0,0 +1,272 @@
+use std::fs;
+use std::path::PathBuf;
+
+use otnvr::recorder::{
+    SegmentRepair, fmp4_playable_length, next_segment_uri, recover_playlist, reopen_playlist,
+    repair_segment, split_playlist, ts_playable_length, ts_timestamp_span,
+};
+
+/// Fresh directory under the system temp dir, unique to this process and test.
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("otnvr-recovery-{name}-{}", std::process::id()));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(&dir).expect("create scratch dir");
+    dir
+}
+
+/// One MPEG-TS packet starting a video PES with the given 90 kHz PTS.
+fn video_packet(pts: u64) -> Vec<u8> {
+    let mut packet = vec![0x47, 0x41, 0x00, 0x10, 0, 0, 1, 0xe0, 0, 0, 0x80, 0x80, 5];
+    packet.extend_from_slice(&[
+        0x21 | (((pts >> 30) as u8 & 0x07) << 1),
+        (pts >> 22) as u8,
+        (((pts >> 15) as u8) << 1) | 1,
+        (pts >> 7) as u8,
+        ((pts as u8) << 1) | 1,
+    ]);
+    packet.resize(188, 0xff);
+    packet
+}
+
+fn mp4_box(kind: &[u8; 4], payload: usize) -> Vec<u8> {
+    let mut data = ((8 + payload) as u32).to_be_bytes().to_vec();
+    data.extend_from_slice(kind);
+    data.resize(8 + payload, 0);
+    data
+}
+
+#[test]
+fn ts_length_stops_at_torn_or_unsynced_packets() {
+    let mut data = [video_packet(0), video_packet(3_000)].concat();
+    data.extend_from_slice(&[0x47, 0x00, 0x00]);
+
+    assert_eq!(ts_playable_length(&data), 376);
+
+    data[188] = 0x00;
+
+    assert_eq!(ts_playable_length(&data), 188);
+}
+
+#[test]
+fn fmp4_length_ends_with_the_last_complete_fragment() {
+    let mut data = [
+        mp4_box(b"moof", 16),
+        mp4_box(b"mdat", 32),
+        mp4_box(b"moof", 16),
+    ]
+    .concat();
+    data.extend_from_slice(&mp4_box(b"mdat", 32)[..20]);
+
+    assert_eq!(fmp4_playable_length(&data), 64);
+    assert_eq!(fmp4_playable_length(&mp4_box(b"moof", 16)), 0);
+}
+
+#[test]
+fn ts_span_comes_from_video_timestamps() {
+    let data = [video_packet(90_000), video_packet(450_000)].concat();
+
+    assert_eq!(ts_timestamp_span(&data), Some(4.0));
+    assert_eq!(ts_timestamp_span(&video_packet(90_000)), None);
+}
+
+#[test]
+fn repair_truncates_torn_segments_and_rejects_empty_ones() {
+    let dir = scratch_dir("repair");
+    let torn = dir.join("torn.ts");
+    let mut data = video_packet(0);
+    data.extend_from_slice(&[0x47, 0x01]);
+    fs::write(&torn, &data).expect("write segment");
+    let empty = dir.join("empty.ts");
+    fs::write(&empty, b"").expect("write segment");
+
+    assert_eq!(
+        repair_segment(&torn).expect("repair"),
+        SegmentRepair::Truncated
+    );
+    assert_eq!(fs::metadata(&torn).expect("metadata").len(), 188);
+    assert_eq!(
+        repair_segment(&torn).expect("repair"),
+        SegmentRepair::Intact
+    );
+    assert_eq!(
+        repair_segment(&empty).expect("repair"),
+        SegmentRepair::Broken
+    );
+    assert_eq!(
+        repair_segment(&dir.join("missing.ts")).expect("repair"),
+        SegmentRepair::Broken
+    );
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn interrupted_playlist_is_repaired_and_continued() {
+    let dir = scratch_dir("playlist");
+    let playlist_path = dir.join("stream.m3u8");
+    fs::write(
+        &playlist_path,
+        "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:3\n\
+         #EXTINF:4.000000,\nstream_00003.ts\n\
+         #EXTINF:4.000000,\nstream_00004.ts\n\
+         #EXTINF:4.000000,\nstream_00005.ts\n",
+    )
+    .expect("write playlist");
+    fs::write(dir.join("stream_00003.ts"), video_packet(0)).expect("write segment");
+    fs::write(dir.join("stream_00004.ts"), video_packet(0)).expect("write segment");
+    // The last listed segment never reached the disk.
+    fs::write(dir.join("stream_00005.ts"), b"").expect("write segment");
+    // The segment in progress is cut mid-packet.
+    let mut orphan = [video_packet(0), video_packet(180_000)].concat();
+    orphan.extend_from_slice(&[0x47, 0x00]);
+    fs::write(dir.join("stream_00006.ts"), &orphan).expect("write segment");
+    let template = dir.join("stream_%05d.ts").to_string_lossy().to_string();
+
+    let recovery = recover_playlist(&playlist_path, &template)
+        .expect("recover")
+        .expect("interrupted playlist");
+
+    // The empty segment is dropped, so the orphan is not contiguous and is set aside.
+    assert_eq!(recovery.next_sequence, 5);
+    assert_eq!(recovery.segments, 2);
+    assert_eq!(
+        recovery.dropped,
+        vec![dir.join("stream_00005.ts"), dir.join("stream_00006.ts")]
+    );
+    assert!(dir.join("stream_00006.ts.partial").exists());
+    let playlist = fs::read_to_string(&playlist_path).expect("read playlist");
+    assert!(playlist.ends_with("#EXTINF:4.000000,\nstream_00004.ts\n"));
+    assert!(!playlist.contains("stream_00005.ts"));
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn segment_in_progress_is_listed_after_an_intact_tail() {
+    let dir = scratch_dir("orphan");
+    let playlist_path = dir.join("stream.m3u8");
+    fs::write(
+        &playlist_path,
+        "#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:0\n\
+         #EXTINF:4.000000,\nstream_00000.ts\n",
+    )
+    .expect("write playlist");
+    fs::write(dir.join("stream_00000.ts"), video_packet(0)).expect("write segment");
+    let mut orphan = [video_packet(0), video_packet(180_000)].concat();
+    orphan.extend_from_slice(&[0x47, 0x00]);
+    fs::write(dir.join("stream_00001.ts"), &orphan).expect("write segment");
+    let template = dir.join("stream_%05d.ts").to_string_lossy().to_string();
+
+    let recovery = recover_playlist(&playlist_path, &template)
+        .expect("recover")
+        .expect("interrupted playlist");
+
+    assert_eq!(recovery.next_sequence, 2);
+    assert_eq!(recovery.repaired, vec![dir.join("stream_00001.ts")]);
+    let playlist = fs::read_to_string(&playlist_path).expect("read playlist");
+    assert!(playlist.ends_with("#EXTINF:2.000000,\nstream_00001.ts\n"));
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn finished_playlists_are_left_alone() {
+    let dir = scratch_dir("finished");
+    let playlist_path = dir.join("stream.m3u8");
+    fs::write(
+        &playlist_path,
+        "#EXTM3U\n#EXTINF:4.000000,\nstream_00000.ts\n#EXT-X-ENDLIST\n",
+    )
+    .expect("write playlist");
+    let template = dir.join("stream_%05d.ts").to_string_lossy().to_string();
+
+    assert_eq!(
+        recover_playlist(&playlist_path, &template).expect("recover"),
+        None
+    );
+    assert_eq!(
+        recover_playlist(&dir.join("missing.m3u8"), &template).expect("recover"),
+        None
+    );
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn split_playlist_separates_earlier_segments() {
+    let playlist = "#EXTM3U\n#EXT-X-MEDIA-SEQUENCE:3\n\
+        #EXT-X-PROGRAM-DATE-TIME:2024-01-01T00:00:00.000+0000\n#EXTINF:4.0,\nstream_00003.ts\n\
+        #EXT-X-DISCONTINUITY\n#EXT-X-PROGRAM-DATE-TIME:2024-01-02T00:00:00.000+0000\n#EXTINF:4.0,\nstream_00004.ts\n";
+
+    let (earlier, current) = split_playlist(playlist, 4);
+
+    assert!(earlier.ends_with("stream_00003.ts\n"));
+    assert!(current.starts_with("#EXT-X-DISCONTINUITY\n"));
+    assert_eq!(split_playlist(playlist, 9), (playlist, ""));
+}
+
+#[test]
+fn finished_playlist_is_reopened_for_appending() {
+    let dir = scratch_dir("reopen");
+    let playlist_path = dir.join("stream.m3u8");
+    fs::write(
+        &playlist_path,
+        "#EXTM3U\n#EXT-X-MEDIA-SEQUENCE:2\n\
+         #EXTINF:4.000000,\nstream_00002.ts\n#EXTINF:4.000000,\nstream_00003.ts\n\
+         #EXT-X-ENDLIST\n",
+    )
+    .expect("write playlist");
+
+    assert_eq!(reopen_playlist(&playlist_path).expect("reopen"), Some(4));
+    let playlist = fs::read_to_string(&playlist_path).expect("read playlist");
+    assert!(playlist.ends_with("stream_00003.ts\n"));
+    // An unfinished playlist belongs to crash recovery, a missing one starts afresh.
+    assert_eq!(reopen_playlist(&playlist_path).expect("reopen"), None);
+    assert_eq!(
+        reopen_playlist(&dir.join("missing.m3u8")).expect("reopen"),
+        None
+    );
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn next_segment_uri_keeps_session_and_padding() {
+    assert_eq!(
+        next_segment_uri("stream_20240501T120000Z_00041.ts", 41).as_deref(),
+        Some("stream_20240501T120000Z_00042.ts")
+    );
+    assert_eq!(
+        next_segment_uri("video/part9.mp4", 9).as_deref(),
+        Some("video/part10.mp4")
+    );
+    assert_eq!(next_segment_uri("stream_00004.mp4", 5), None);
+}
+
+#[test]
+fn segment_in_progress_of_a_session_is_found_from_the_last_listed_name() {
+    let dir = scratch_dir("session");
+    let playlist_path = dir.join("stream.m3u8");
+    fs::write(
+        &playlist_path,
+        "#EXTM3U\n#EXT-X-MEDIA-SEQUENCE:7\n\
+         #EXTINF:4.000000,\nstream_20240501T120000Z_00007.ts\n",
+    )
+    .expect("write playlist");
+    fs::write(
+        dir.join("stream_20240501T120000Z_00007.ts"),
+        video_packet(0),
+    )
+    .expect("write segment");
+    let orphan = [video_packet(0), video_packet(90_000)].concat();
+    fs::write(dir.join("stream_20240501T120000Z_00008.ts"), &orphan).expect("write segment");
+    // The new session names its segments differently.
+    let template = dir
+        .join("stream_20240502T080000Z_%05d.ts")
+        .to_string_lossy()
+        .to_string();
+
+    let recovery = recover_playlist(&playlist_path, &template)
+        .expect("recover")
+        .expect("interrupted playlist");
+
+    assert_eq!(recovery.next_sequence, 9);
+    let playlist = fs::read_to_string(&playlist_path).expect("read playlist");
+    assert!(playlist.ends_with("#EXTINF:1.000000,\nstream_20240501T120000Z_00008.ts\n"));
+    fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use otnvr::recorder::{
+    PlaylistEntry, SegmentRecord, insert_discontinuities, locate_segment, media_playlist_entries,
+    read_segment_index, segment_index_path,
+};
+
+fn record(sequence: u64, start: f64) -> SegmentRecord {
+    SegmentRecord {
+        uri: format!("stream_{sequence:05}.ts"),
+        sequence,
+        start,
+        duration: 4.0,
+        discontinuity: false,
+    }
+}
+
+#[test]
+fn segment_index_path_sits_next_to_the_playlist() {
+    assert_eq!(
+        segment_index_path(Path::new("out/cam/stream.m3u8")),
+        PathBuf::from("out/cam/stream.index.jsonl")
+    );
+}
+
+#[test]
+fn media_playlist_entries_lists_segments_and_discontinuities() {
+    let playlist = "#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:7\n\
+        #EXTINF:4.000000,\nstream_00007.ts\n\
+        #EXT-X-DISCONTINUITY\n#EXT-X-PROGRAM-DATE-TIME:2024-05-01T12:00:00.000Z\n\
+        #EXTINF:3.500000,\nstream_00008.ts\n#EXT-X-ENDLIST\n";
+
+    assert_eq!(
+        media_playlist_entries(playlist),
+        (
+            7,
+            vec![
+                PlaylistEntry {
+                    uri: "stream_00007.ts".to_string(),
+                    duration: 4.0,
+                    discontinuity: false,
+                },
+                PlaylistEntry {
+                    uri: "stream_00008.ts".to_string(),
+                    duration: 3.5,
+                    discontinuity: true,
+                },
+            ]
+        )
+    );
+}
+
+#[test]
+fn locate_segment_finds_the_offset_within_a_segment() {
+    let records = vec![record(0, 1000.0), record(1, 1004.0), record(2, 1020.0)];
+
+    let (found, offset) = locate_segment(&records, 1005.5).expect("covered instant");
+    assert_eq!(found.sequence, 1);
+    assert_eq!(offset, 1.5);
+    // Nothing was recorded between 1008 and 1020.
+    assert!(locate_segment(&records, 1010.0).is_none());
+    assert!(locate_segment(&records, 999.0).is_none());
+}
+
+#[test]
+fn read_segment_index_skips_truncated_lines() {
+    let path = std::env::temp_dir().join(format!("otnvr-index-{}.jsonl", std::process::id()));
+    std::fs::write(
+        &path,
+        "{\"uri\":\"stream_00000.ts\",\"sequence\":0,\"start\":1000.0,\"duration\":4.0}\n{\"uri\":\"stream_0",
+    )
+    .expect("write index");
+
+    let records = read_segment_index(&path).expect("read index");
+    std::fs::remove_file(&path).ok();
+
+    assert_eq!(records, vec![record(0, 1000.0)]);
+}
+
+#[test]
+fn insert_discontinuities_tags_segments_and_counts_dropped_ones() {
+    let playlist = "#EXTM3U\n#EXT-X-MEDIA-SEQUENCE:5\n\
+        #EXTINF:4.000000,\nstream_00005.ts\n\
+        #EXTINF:4.000000,\nstream_00006.ts\n";
+    let discontinuous = BTreeSet::from([2, 6]);
+
+    assert_eq!(
+        insert_discontinuities(playlist, &discontinuous),
+        "#EXTM3U\n#EXT-X-MEDIA-SEQUENCE:5\n#EXT-X-DISCONTINUITY-SEQUENCE:1\n\
+        #EXTINF:4.000000,\nstream_00005.ts\n\
+        #EXT-X-DISCONTINUITY\n#EXTINF:4.000000,\nstream_00006.ts\n"
+    );
+}
@@ -0,0 +1,69 @@
+use std::path::Path;
+
+use otnvr::recorder::{SnapshotHub, SnapshotSchedule, snapshot_archive_name, snapshot_size};
+
+#[test]
+fn snapshot_size_keeps_aspect_ratio_with_even_dimensions() {
+    assert_eq!(snapshot_size(1920, 1080, None), (1920, 1080));
+    assert_eq!(snapshot_size(1920, 1080, Some(640)), (640, 360));
+    assert_eq!(snapshot_size(704, 576, Some(321)), (320, 262));
+}
+
+#[test]
+fn schedule_takes_periodic_and_requested_snapshots() {
+    let mut schedule = SnapshotSchedule::new(Some(5.0));
+
+    assert!(schedule.take(0.0, false));
+    assert!(!schedule.take(4.9, false));
+    assert!(schedule.take(5.0, false));
+    // An on-demand snapshot restarts the interval.
+    assert!(schedule.take(6.0, true));
+    assert!(!schedule.take(10.5, false));
+    assert!(schedule.take(11.0, false));
+}
+
+#[test]
+fn schedule_without_interval_only_takes_requested_snapshots() {
+    let mut schedule = SnapshotSchedule::new(None);
+
+    assert!(!schedule.take(0.0, false));
+    assert!(schedule.take(1.0, true));
+    assert!(!schedule.take(100.0, false));
+    assert!(!SnapshotSchedule::new(Some(0.0)).take(0.0, false));
+}
+
+#[test]
+fn hub_hands_requests_to_the_registered_slot() {
+    let hub = SnapshotHub::new();
+    let slot = hub.register("gate", "out/gate/latest.jpg".into());
+
+    let served = hub.slot("gate").expect("registered slot");
+    assert_eq!(served.latest_path(), Path::new("out/gate/latest.jpg"));
+    assert!(hub.slot("yard").is_none());
+
+    assert!(!slot.take_request());
+    served.request();
+    assert!(slot.take_request());
+    assert!(!slot.take_request());
+    assert_eq!(served.taken(), 0);
+}
+
+#[test]
+fn archived_snapshots_are_named_by_utc_time() {
+    assert_eq!(
+        snapshot_archive_name(1_714_564_800.0),
+        "snapshot_20240501T120000.000Z.jpg"
+    );
+}
+
+#[test]
+fn snapshots_within_one_second_keep_distinct_names() {
+    assert_eq!(
+        snapshot_archive_name(1_714_564_800.25),
+        "snapshot_20240501T120000.250Z.jpg"
+    );
+    assert_ne!(
+        snapshot_archive_name(1_714_564_800.25),
+        snapshot_archive_name(1_714_564_800.5)
+    );
+}
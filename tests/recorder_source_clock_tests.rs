@@ -0,0 +1,56 @@
+use otnvr::recorder::{ClockSource, SourceClock, producer_reference_time};
+
+fn prft(wallclock_micros: i64) -> Vec<u8> {
+    let mut data = wallclock_micros.to_ne_bytes().to_vec();
+    // `flags` field of `AVProducerReferenceTime`.
+    data.extend_from_slice(&24i32.to_ne_bytes());
+    data
+}
+
+#[test]
+fn producer_reference_time_reads_microseconds() {
+    assert_eq!(
+        producer_reference_time(&prft(1_714_564_800_250_000)),
+        Some(1_714_564_800.25)
+    );
+    assert_eq!(producer_reference_time(&prft(0)), None);
+    assert_eq!(producer_reference_time(&[1, 2, 3]), None);
+}
+
+#[test]
+fn sender_report_mapping_carries_over_to_later_packets() {
+    let mut clock = SourceClock::new(2, None);
+
+    assert_eq!(
+        clock.wall_clock(0, 10.0, Some(1000.0)),
+        (1000.0, ClockSource::Rtcp)
+    );
+    assert_eq!(clock.wall_clock(0, 10.5, None), (1000.5, ClockSource::Rtcp));
+    // Streams share the RTCP-aligned timeline, so audio inherits the video mapping.
+    assert_eq!(
+        clock.wall_clock(1, 10.25, None),
+        (1000.25, ClockSource::Rtcp)
+    );
+    // A newer sender report corrects drift.
+    assert_eq!(
+        clock.wall_clock(0, 20.0, Some(1010.125)),
+        (1010.125, ClockSource::Rtcp)
+    );
+}
+
+#[test]
+fn session_start_time_is_used_before_per_packet_mappings() {
+    let mut clock = SourceClock::new(1, Some(5000.0));
+
+    assert_eq!(clock.wall_clock(0, 2.0, None), (5002.0, ClockSource::Rtcp));
+}
+
+#[test]
+fn local_clock_is_the_last_resort() {
+    let mut clock = SourceClock::new(1, None);
+
+    let (first, source) = clock.wall_clock(0, 100.0, None);
+    assert_eq!(source, ClockSource::Local);
+    let (second, _) = clock.wall_clock(0, 101.0, None);
+    assert!((second - first - 1.0).abs() < 1e-9);
+}
@@ -0,0 +1,73 @@
+use ffmpeg_next::media;
+use otnvr::recorder::{RecorderError, StreamInfo, StreamSelection, StreamSelector};
+
+fn camera_streams() -> Vec<StreamInfo> {
+    let stream = |index, medium, codec: &str, language: Option<&str>| StreamInfo {
+        index,
+        medium,
+        codec: codec.to_string(),
+        language: language.map(str::to_string),
+    };
+
+    vec![
+        stream(0, media::Type::Video, "h264", None),
+        stream(1, media::Type::Video, "mjpeg", None),
+        stream(2, media::Type::Audio, "pcm_alaw", Some("eng")),
+        stream(3, media::Type::Audio, "aac", Some("deu")),
+        stream(4, media::Type::Data, "none", None),
+    ]
+}
+
+#[test]
+fn default_selection_keeps_every_audio_and_video_stream() {
+    let selection = StreamSelection::default();
+
+    let selected = selection.select(&camera_streams()).expect("selection");
+
+    assert_eq!(selected, vec![0, 1, 2, 3]);
+}
+
+#[test]
+fn first_video_and_no_audio() {
+    let selection = StreamSelection {
+        video: StreamSelector::First,
+        audio: StreamSelector::None,
+    };
+
+    let selected = selection.select(&camera_streams()).expect("selection");
+
+    assert_eq!(selected, vec![0]);
+}
+
+#[test]
+fn matchers_select_by_index_codec_and_language() {
+    let selection = StreamSelection {
+        video: StreamSelector::Codec("MJPEG".to_string()),
+        audio: StreamSelector::Language("deu".to_string()),
+    };
+    assert_eq!(
+        selection.select(&camera_streams()).expect("selection"),
+        vec![1, 3]
+    );
+
+    let selection = StreamSelection {
+        video: StreamSelector::Index(0),
+        audio: StreamSelector::Index(2),
+    };
+    assert_eq!(
+        selection.select(&camera_streams()).expect("selection"),
+        vec![0, 2]
+    );
+}
+
+#[test]
+fn unmatched_selector_reports_stream_not_found() {
+    let selection = StreamSelection {
+        video: StreamSelector::Index(2),
+        audio: StreamSelector::All,
+    };
+
+    let error = selection.select(&camera_streams()).unwrap_err();
+
+    assert!(matches!(error, RecorderError::StreamNotFound(_)));
+}
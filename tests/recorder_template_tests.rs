@@ -1,6 +1,10 @@
 use std::path::PathBuf;
 
-use otnvr::recorder::{HlsOutput, VideoCodec, derive_segment_template};
+use otnvr::recorder::{
+    AudioOptions, HlsOutput, Packaging, SESSION_PLACEHOLDER, SegmentFormat, VideoCodec,
+    derive_dash_segment_names, derive_init_segment_name, derive_segment_template, session_name,
+    wallclock_segment_template,
+};
 
 fn base_hls_output() -> HlsOutput {
     HlsOutput {
@@ -9,6 +13,19 @@ fn base_hls_output() -> HlsOutput {
         playlist_size: Some(10),
         segment_filename: None,
         video_codec: VideoCodec::H264,
+        audio: AudioOptions::default(),
+        width: None,
+        height: None,
+        video_bitrate: None,
+        low_latency: None,
+        packaging: Packaging::Hls,
+        segment_format: None,
+        encryption: None,
+        program_date_time: false,
+        push: Vec::new(),
+        master_playlist: None,
+        append: false,
+        thumbnails: None,
     }
 }
 
@@ -31,6 +48,42 @@ fn derive_segment_template_builds_default_pattern_next_to_playlist() {
     assert_eq!(template, "output/stream_%05d.ts");
 }
 
+#[test]
+fn appending_outputs_name_segments_after_the_session() {
+    let mut hls = base_hls_output();
+    hls.append = true;
+
+    let template = derive_segment_template(&hls);
+
+    assert_eq!(
+        template,
+        format!("output/stream_{SESSION_PLACEHOLDER}_%05d.ts")
+    );
+    assert_eq!(session_name(1_714_564_800.7), "20240501T120000Z");
+
+    hls.segment_filename = Some("custom/segment_%03d.ts".to_string());
+
+    assert_eq!(derive_segment_template(&hls), "custom/segment_%03d.ts");
+}
+
+#[test]
+fn wallclock_templates_become_strftime_patterns() {
+    let mut hls = base_hls_output();
+    hls.segment_filename = Some("output/stream_{wallclock}_%05d.ts".to_string());
+
+    let template = derive_segment_template(&hls);
+
+    assert_eq!(
+        wallclock_segment_template(&template).as_deref(),
+        Some("output/stream_%Y%m%dT%H%M%S%z_%%05d.ts")
+    );
+    assert_eq!(
+        wallclock_segment_template("output/100%_{wallclock}.ts").as_deref(),
+        Some("output/100%%_%Y%m%dT%H%M%S%z.ts")
+    );
+    assert_eq!(wallclock_segment_template("output/stream_%05d.ts"), None);
+}
+
 #[test]
 fn derive_segment_template_handles_playlist_without_parent() {
     let hls = HlsOutput {
@@ -39,6 +92,19 @@ fn derive_segment_template_handles_playlist_without_parent() {
         playlist_size: None,
         segment_filename: None,
         video_codec: VideoCodec::H264,
+        audio: AudioOptions::default(),
+        width: None,
+        height: None,
+        video_bitrate: None,
+        low_latency: None,
+        packaging: Packaging::Hls,
+        segment_format: None,
+        encryption: None,
+        program_date_time: false,
+        push: Vec::new(),
+        master_playlist: None,
+        append: false,
+        thumbnails: None,
     };
 
     let template = derive_segment_template(&hls);
@@ -66,3 +132,108 @@ fn derive_segment_template_overrides_custom_extension_for_h265() {
 
     assert_eq!(template, "custom/segment_%03d.m4s");
 }
+
+#[test]
+fn derive_segment_template_keeps_ts_extension_for_copy() {
+    let mut hls = base_hls_output();
+    hls.video_codec = VideoCodec::Copy;
+
+    let template = derive_segment_template(&hls);
+
+    assert_eq!(template, "output/stream_%05d.ts");
+}
+
+#[test]
+fn derive_dash_segment_names_prefix_manifest_stem() {
+    let mut hls = base_hls_output();
+    hls.playlist_path = PathBuf::from("output/camera.mpd");
+    hls.packaging = Packaging::Dash;
+
+    let (init, media) = derive_dash_segment_names(&hls);
+
+    assert_eq!(init, "camera_init_$RepresentationID$.m4s");
+    assert_eq!(media, "camera_$RepresentationID$_$Number%05d$.m4s");
+}
+
+#[test]
+fn derive_segment_template_uses_m4s_for_fmp4_h264() {
+    let mut hls = base_hls_output();
+    hls.segment_format = Some(SegmentFormat::Fmp4);
+
+    let template = derive_segment_template(&hls);
+
+    assert_eq!(template, "output/stream_%05d.m4s");
+}
+
+#[test]
+fn derive_segment_template_overrides_custom_extension_for_fmp4_h264() {
+    let mut hls = base_hls_output();
+    hls.segment_filename = Some("custom/segment_%03d.ts".to_string());
+    hls.segment_format = Some(SegmentFormat::Fmp4);
+
+    let template = derive_segment_template(&hls);
+
+    assert_eq!(template, "custom/segment_%03d.m4s");
+}
+
+#[test]
+fn derive_segment_template_keeps_mp4_extension_for_fmp4() {
+    let mut hls = base_hls_output();
+    hls.segment_filename = Some("custom/segment_%03d.mp4".to_string());
+    hls.segment_format = Some(SegmentFormat::Fmp4);
+
+    let template = derive_segment_template(&hls);
+
+    assert_eq!(template, "custom/segment_%03d.mp4");
+}
+
+#[test]
+fn derive_segment_template_uses_ts_for_mpegts_h265() {
+    let mut hls = base_hls_output();
+    hls.video_codec = VideoCodec::H265;
+    hls.segment_format = Some(SegmentFormat::Mpegts);
+
+    let template = derive_segment_template(&hls);
+
+    assert_eq!(template, "output/stream_%05d.ts");
+}
+
+#[test]
+fn derive_segment_template_overrides_custom_extension_for_mpegts() {
+    let mut hls = base_hls_output();
+    hls.segment_filename = Some("custom/segment_%03d.m4s".to_string());
+    hls.segment_format = Some(SegmentFormat::Mpegts);
+
+    let template = derive_segment_template(&hls);
+
+    assert_eq!(template, "custom/segment_%03d.ts");
+}
+
+#[test]
+fn derive_segment_template_forces_fmp4_for_low_latency() {
+    let mut hls = base_hls_output();
+    hls.segment_format = Some(SegmentFormat::Mpegts);
+    hls.low_latency = Some(Default::default());
+
+    assert_eq!(hls.effective_segment_format(), SegmentFormat::Fmp4);
+    assert_eq!(derive_segment_template(&hls), "output/stream_%05d.m4s");
+}
+
+#[test]
+fn derive_init_segment_name_uses_playlist_stem() {
+    let hls = base_hls_output();
+
+    assert_eq!(derive_init_segment_name(&hls, false), "stream_init.mp4");
+}
+
+#[test]
+fn continuing_sessions_write_their_own_init_segment() {
+    let hls = base_hls_output();
+
+    let name = derive_init_segment_name(&hls, true);
+    assert_eq!(name, format!("stream_{SESSION_PLACEHOLDER}_init.mp4"));
+    assert_eq!(
+        name.replace(SESSION_PLACEHOLDER, &session_name(1_714_564_800.0)),
+        "stream_20240501T120000Z_init.mp4"
+    );
+}
@@ -0,0 +1,88 @@
+use std::path::{Path, PathBuf};
+
+use otnvr::recorder::{
+    ThumbnailGrid, insert_thumbnail_reference, next_sprite_sheet, sprite_sheet_name,
+    thumbnail_track_path, vtt_timestamp,
+};
+
+#[test]
+fn track_and_sheets_are_named_after_the_playlist() {
+    assert_eq!(
+        thumbnail_track_path(Path::new("out/gate/stream.m3u8")),
+        PathBuf::from("out/gate/stream_thumbnails.vtt")
+    );
+    assert_eq!(sprite_sheet_name("stream", 3), "stream_sprite_00003.jpg");
+}
+
+#[test]
+fn vtt_timestamps_carry_hours_and_milliseconds() {
+    assert_eq!(vtt_timestamp(0.0), "00:00:00.000");
+    assert_eq!(vtt_timestamp(3723.5), "01:02:03.500");
+    assert_eq!(vtt_timestamp(360_000.0), "100:00:00.000");
+}
+
+#[test]
+fn grid_fills_sheets_row_by_row_once_per_interval() {
+    let mut grid = ThumbnailGrid::new(4.0, 2, 2);
+
+    let first = grid.place(0.0).expect("first cell");
+    assert_eq!((first.sheet, first.column, first.row), (0, 0, 0));
+    assert_eq!(grid.place(3.9), None);
+    let second = grid.place(4.1).expect("second cell");
+    assert_eq!((second.sheet, second.column, second.row), (0, 1, 0));
+    assert_eq!((second.start, second.end), (4.0, 8.0));
+    // An interval without frames keeps its cell empty.
+    let fourth = grid.place(12.0).expect("fourth cell");
+    assert_eq!((fourth.sheet, fourth.column, fourth.row), (0, 1, 1));
+    let fifth = grid.place(16.0).expect("fifth cell");
+    assert_eq!((fifth.sheet, fifth.column, fifth.row), (1, 0, 0));
+}
+
+#[test]
+fn continued_grid_starts_after_the_earlier_track() {
+    let mut grid = ThumbnailGrid::new(4.0, 10, 10).continuing(601.5, 2);
+
+    let cell = grid.place(0.0).expect("cell");
+    assert_eq!((cell.sheet, cell.column, cell.row), (2, 0, 0));
+    assert_eq!((cell.start, cell.end), (601.5, 604.0));
+    let next = grid.place(2.5).expect("next cell");
+    assert_eq!((next.sheet, next.column), (2, 1));
+    assert_eq!(next.start, 604.0);
+}
+
+#[test]
+fn cues_point_at_sprite_cells() {
+    let mut grid = ThumbnailGrid::new(2.0, 5, 5);
+    grid.place(0.0);
+    let cell = grid.place(2.0).expect("cell");
+
+    assert_eq!(
+        cell.cue("stream_sprite_00000.jpg", (160, 90)),
+        "00:00:02.000 --> 00:00:04.000\nstream_sprite_00000.jpg#xywh=160,0,160,90\n\n"
+    );
+}
+
+#[test]
+fn next_sprite_sheet_follows_the_last_referenced_sheet() {
+    let track = "WEBVTT\n\n\
+        00:00:00.000 --> 00:00:02.000\nstream_sprite_00000.jpg#xywh=0,0,160,90\n\n\
+        00:03:20.000 --> 00:03:22.000\nstream_sprite_00001.jpg#xywh=0,0,160,90\n\n";
+
+    assert_eq!(next_sprite_sheet(track), 2);
+    assert_eq!(next_sprite_sheet("WEBVTT\n\n"), 0);
+}
+
+#[test]
+fn master_playlists_reference_the_track_once() {
+    let playlist = "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-STREAM-INF:BANDWIDTH=2000000\nstream.m3u8\n";
+
+    let referenced = insert_thumbnail_reference(playlist, "stream_thumbnails.vtt");
+    assert_eq!(
+        referenced,
+        "#EXTM3U\n#EXT-X-SESSION-DATA:DATA-ID=\"com.otnvr.thumbnails\",URI=\"stream_thumbnails.vtt\"\n#EXT-X-VERSION:3\n#EXT-X-STREAM-INF:BANDWIDTH=2000000\nstream.m3u8\n"
+    );
+    assert_eq!(
+        insert_thumbnail_reference(&referenced, "stream_thumbnails.vtt"),
+        referenced
+    );
+}
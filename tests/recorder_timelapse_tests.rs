@@ -0,0 +1,76 @@
+use otnvr::recorder::{
+    SegmentRecord, next_daily_run, parse_time_of_day, previous_day, timelapse_file_name,
+    timelapse_samples,
+};
+
+/// 2024-05-01T00:00:00Z.
+const MIDNIGHT: f64 = 1_714_521_600.0;
+
+fn segment(sequence: u64, start: f64) -> SegmentRecord {
+    SegmentRecord {
+        uri: format!("stream_{sequence:05}.ts"),
+        sequence,
+        start,
+        duration: 10.0,
+        discontinuity: false,
+    }
+}
+
+#[test]
+fn samples_follow_the_grid_and_skip_gaps() {
+    let mut records: Vec<SegmentRecord> = (0..6)
+        .map(|i| segment(i, MIDNIGHT + 10.0 * i as f64))
+        .collect();
+    // Nothing recorded from +60 s to +100 s.
+    records.push(segment(6, MIDNIGHT + 100.0));
+
+    let samples = timelapse_samples(&records, MIDNIGHT + 5.0, MIDNIGHT + 120.0, 25.0);
+    let picked: Vec<(&str, f64)> = samples
+        .iter()
+        .map(|sample| (sample.uri.as_str(), sample.offset))
+        .collect();
+
+    assert_eq!(
+        picked,
+        vec![
+            ("stream_00000.ts", 5.0),
+            ("stream_00003.ts", 0.0),
+            ("stream_00005.ts", 5.0),
+            // +80 s falls into the gap; +105 s is the next grid point.
+            ("stream_00006.ts", 5.0),
+        ]
+    );
+}
+
+#[test]
+fn samples_need_a_positive_interval_and_footage() {
+    let records = vec![segment(0, MIDNIGHT)];
+
+    assert!(timelapse_samples(&records, MIDNIGHT, MIDNIGHT + 10.0, 0.0).is_empty());
+    assert!(timelapse_samples(&records, MIDNIGHT + 20.0, MIDNIGHT + 60.0, 5.0).is_empty());
+    assert!(timelapse_samples(&[], MIDNIGHT, MIDNIGHT + 60.0, 5.0).is_empty());
+}
+
+#[test]
+fn timelapses_are_named_by_camera_and_window() {
+    assert_eq!(
+        timelapse_file_name("gate", MIDNIGHT, MIDNIGHT + 86_400.0),
+        "gate_20240501T000000Z-20240502T000000Z.mp4"
+    );
+}
+
+#[test]
+fn nightly_runs_render_the_previous_day() {
+    let time_of_day = parse_time_of_day("01:30").expect("time of day");
+    assert_eq!(time_of_day, 5400.0);
+    assert_eq!(parse_time_of_day("24:00"), None);
+    assert_eq!(parse_time_of_day("1h"), None);
+
+    assert_eq!(
+        next_daily_run(MIDNIGHT + 3600.0, time_of_day),
+        MIDNIGHT + 5400.0
+    );
+    let run = next_daily_run(MIDNIGHT + 5400.0, time_of_day);
+    assert_eq!(run, MIDNIGHT + 86_400.0 + 5400.0);
+    assert_eq!(previous_day(run), (MIDNIGHT, MIDNIGHT + 86_400.0));
+}
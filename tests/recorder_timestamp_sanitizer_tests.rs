@@ -0,0 +1,98 @@
+use ffmpeg_next::Rational;
+use otnvr::recorder::{TimestampAnomaly, TimestampCorrection, TimestampSanitizer};
+
+fn sanitizer() -> TimestampSanitizer {
+    // 90 kHz video and 8 kHz audio.
+    TimestampSanitizer::new(&[Rational(1, 90_000), Rational(1, 8_000)])
+}
+
+#[test]
+fn monotonic_timestamps_pass_through() {
+    let mut sanitizer = sanitizer();
+
+    for dts in [0, 3_600, 7_200] {
+        assert_eq!(
+            sanitizer.sanitize(0, Some(dts + 3_600), Some(dts)),
+            (Some(dts + 3_600), Some(dts), None)
+        );
+    }
+    assert_eq!(sanitizer.corrections(), 0);
+}
+
+#[test]
+fn backwards_timestamps_are_rebased_after_the_last_packet() {
+    let mut sanitizer = sanitizer();
+    sanitizer.sanitize(0, Some(180_000), Some(180_000));
+    sanitizer.sanitize(0, Some(183_600), Some(183_600));
+
+    let (pts, dts, correction) = sanitizer.sanitize(0, Some(900), Some(900));
+    assert_eq!((pts, dts), (Some(187_200), Some(187_200)));
+    let TimestampCorrection {
+        stream,
+        anomaly,
+        jump_seconds,
+    } = correction.expect("regression corrected");
+    assert_eq!((stream, anomaly), (0, TimestampAnomaly::Regression));
+    assert!((jump_seconds + 2.07).abs() < 1e-9);
+    // Later packets keep the new offset.
+    assert_eq!(
+        sanitizer.sanitize(0, Some(4_500), Some(4_500)),
+        (Some(190_800), Some(190_800), None)
+    );
+    assert_eq!(sanitizer.corrections(), 1);
+}
+
+#[test]
+fn repeated_and_slightly_late_decode_timestamps_are_nudged_silently() {
+    let mut sanitizer = sanitizer();
+    sanitizer.sanitize(0, Some(3_600), Some(0));
+    sanitizer.sanitize(0, Some(10_800), Some(3_600));
+
+    // A duplicated decode timestamp moves one tick past the previous packet.
+    assert_eq!(
+        sanitizer.sanitize(0, Some(7_200), Some(3_600)),
+        (Some(7_200), Some(3_601), None)
+    );
+    // A late packet within the reorder tolerance is nudged likewise, its presentation time
+    // kept no earlier than its decode time.
+    assert_eq!(
+        sanitizer.sanitize(0, Some(900), Some(900)),
+        (Some(3_602), Some(3_602), None)
+    );
+    // The stream itself is not rebased.
+    assert_eq!(
+        sanitizer.sanitize(0, Some(14_400), Some(7_200)),
+        (Some(14_400), Some(7_200), None)
+    );
+    assert_eq!(sanitizer.corrections(), 0);
+}
+
+#[test]
+fn large_forward_jumps_are_removed() {
+    let mut sanitizer = sanitizer();
+    sanitizer.sanitize(1, Some(0), Some(0));
+    sanitizer.sanitize(1, Some(160), Some(160));
+
+    let (_, dts, correction) = sanitizer.sanitize(1, Some(8_000 * 60), Some(8_000 * 60));
+    assert_eq!(dts, Some(320));
+    assert_eq!(
+        correction.map(|correction| correction.anomaly),
+        Some(TimestampAnomaly::Gap)
+    );
+    // The other stream is unaffected.
+    assert_eq!(
+        sanitizer.sanitize(0, Some(0), Some(0)),
+        (Some(0), Some(0), None)
+    );
+}
+
+#[test]
+fn presentation_order_reordering_is_tolerated_without_decode_timestamps() {
+    let mut sanitizer = sanitizer();
+
+    for pts in [0, 10_800, 3_600, 7_200, 21_600] {
+        let (corrected, _, correction) = sanitizer.sanitize(0, Some(pts), None);
+        assert_eq!(corrected, Some(pts));
+        assert!(correction.is_none());
+    }
+}
@@ -0,0 +1,163 @@
+use std::fs;
+
+use otnvr::recorder::{
+    InitSegment, SegmentRecord, init_segment_at, init_segment_uri, init_segments,
+    parse_window_time, place_init_segments, vod_playlist, window_playlist,
+};
+
+fn record(sequence: u64, start: f64) -> SegmentRecord {
+    SegmentRecord {
+        uri: format!("stream_{sequence:05}.ts"),
+        sequence,
+        start,
+        duration: 4.0,
+        discontinuity: false,
+    }
+}
+
+/// 2024-05-01T08:00:00Z.
+const EIGHT: f64 = 1_714_550_400.0;
+
+#[test]
+fn window_selects_overlapping_segments_as_vod() {
+    let records: Vec<_> = (0..5).map(|i| record(i, EIGHT + 4.0 * i as f64)).collect();
+
+    let playlist = vod_playlist(&records, EIGHT + 5.0, EIGHT + 12.0, &[]).expect("playlist");
+
+    assert_eq!(
+        playlist,
+        "#EXTM3U\n#EXT-X-VERSION:3\n#EXT-X-PLAYLIST-TYPE:VOD\n#EXT-X-TARGETDURATION:4\n\
+         #EXT-X-MEDIA-SEQUENCE:1\n#EXT-X-PROGRAM-DATE-TIME:2024-05-01T08:00:04.000Z\n\
+         #EXTINF:4.000000,\nstream_00001.ts\n#EXTINF:4.000000,\nstream_00002.ts\n#EXT-X-ENDLIST\n"
+    );
+    assert_eq!(
+        vod_playlist(&records, EIGHT + 60.0, EIGHT + 120.0, &[]),
+        None
+    );
+}
+
+#[test]
+fn recording_gaps_and_new_sessions_are_discontinuities() {
+    let mut records = vec![record(0, EIGHT), record(1, EIGHT + 4.0)];
+    // The camera was down for a minute.
+    records.push(record(2, EIGHT + 68.0));
+    // An appended session continues the numbering right after a restart.
+    let mut resumed = record(3, EIGHT + 72.0);
+    resumed.discontinuity = true;
+    records.push(resumed);
+
+    let init_segments = [
+        InitSegment {
+            sequence: 0,
+            uri: "stream_init.mp4".to_string(),
+        },
+        InitSegment {
+            sequence: 3,
+            uri: "stream_20240501T080112Z_init.mp4".to_string(),
+        },
+    ];
+
+    let playlist = vod_playlist(&records, EIGHT, EIGHT + 3600.0, &init_segments).expect("playlist");
+
+    assert!(playlist.contains("#EXT-X-VERSION:6\n"));
+    assert!(
+        playlist
+            .contains("#EXT-X-MAP:URI=\"stream_init.mp4\"\n#EXTINF:4.000000,\nstream_00000.ts\n")
+    );
+    assert_eq!(playlist.matches("#EXT-X-MAP:").count(), 2);
+    assert_eq!(playlist.matches("#EXT-X-DISCONTINUITY\n").count(), 2);
+    assert!(playlist.contains(
+        "#EXT-X-DISCONTINUITY\n#EXT-X-PROGRAM-DATE-TIME:2024-05-01T08:01:08.000Z\n\
+         #EXTINF:4.000000,\nstream_00002.ts\n"
+    ));
+    // The appended session is read with its own init segment.
+    assert!(playlist.contains(
+        "#EXT-X-MAP:URI=\"stream_20240501T080112Z_init.mp4\"\n#EXTINF:4.000000,\nstream_00003.ts\n"
+    ));
+    assert_eq!(playlist.matches("#EXT-X-PROGRAM-DATE-TIME:").count(), 3);
+}
+
+#[test]
+fn window_playlist_skips_deleted_segments() {
+    let dir = std::env::temp_dir().join(format!("otnvr-vod-{}", std::process::id()));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(&dir).expect("create scratch dir");
+    let playlist_path = dir.join("stream.m3u8");
+    fs::write(&playlist_path, "#EXTM3U\n#EXT-X-MEDIA-SEQUENCE:2\n").expect("write playlist");
+    let mut index = String::new();
+    for i in 0..3 {
+        index.push_str(&serde_json::to_string(&record(i, EIGHT + 4.0 * i as f64)).unwrap());
+        index.push('\n');
+    }
+    fs::write(dir.join("stream.index.jsonl"), index).expect("write index");
+    fs::write(dir.join("stream_00000.ts"), b"").expect("write segment");
+    fs::write(dir.join("stream_00002.ts"), b"").expect("write segment");
+
+    let playlist = window_playlist(&playlist_path, EIGHT, EIGHT + 60.0)
+        .expect("read recording")
+        .expect("playlist");
+
+    assert!(!playlist.contains("stream_00001.ts"));
+    assert!(playlist.contains("#EXT-X-DISCONTINUITY\n"));
+    assert_eq!(
+        window_playlist(&playlist_path, EIGHT - 60.0, EIGHT - 1.0).expect("read recording"),
+        None
+    );
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn window_boundaries_accept_epoch_seconds_and_dates() {
+    assert_eq!(parse_window_time("1714550400"), Some(EIGHT));
+    assert_eq!(parse_window_time("2024-05-01T10:00:00+0200"), Some(EIGHT));
+    assert_eq!(parse_window_time("2024-05-01T08:00:00Z"), Some(EIGHT));
+    assert_eq!(parse_window_time("yesterday"), None);
+}
+
+#[test]
+fn init_segment_uri_reads_the_map_tag() {
+    assert_eq!(
+        init_segment_uri("#EXTM3U\n#EXT-X-MAP:URI=\"stream_init.mp4\"\n"),
+        Some("stream_init.mp4")
+    );
+    assert_eq!(init_segment_uri("#EXTM3U\n"), None);
+}
+
+#[test]
+fn continued_playlists_keep_each_session_on_its_init_segment() {
+    let earlier = "#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:0\n\
+                   #EXT-X-MAP:URI=\"stream_init.mp4\"\n#EXTINF:4.000000,\nstream_00000.m4s\n\
+                   #EXTINF:4.000000,\nstream_00001.m4s\n";
+    let mut sessions = init_segments(earlier);
+    assert_eq!(
+        sessions,
+        vec![InitSegment {
+            sequence: 0,
+            uri: "stream_init.mp4".to_string(),
+        }]
+    );
+    sessions.push(InitSegment {
+        sequence: 2,
+        uri: "stream_20240501T080112Z_init.mp4".to_string(),
+    });
+    assert_eq!(init_segment_at(&sessions, 1), Some("stream_init.mp4"));
+    assert_eq!(
+        init_segment_at(&sessions, 2),
+        Some("stream_20240501T080112Z_init.mp4")
+    );
+
+    // The muxer continuing the playlist announces only its own init segment, at the top.
+    let written = "#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:0\n\
+                   #EXT-X-MAP:URI=\"stream_20240501T080112Z_init.mp4\"\n\
+                   #EXTINF:4.000000,\nstream_00000.m4s\n#EXTINF:4.000000,\nstream_00001.m4s\n\
+                   #EXT-X-DISCONTINUITY\n#EXTINF:4.000000,\nstream_00002.m4s\n";
+
+    assert_eq!(
+        place_init_segments(written, &sessions),
+        "#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-TARGETDURATION:4\n#EXT-X-MEDIA-SEQUENCE:0\n\
+         #EXT-X-MAP:URI=\"stream_init.mp4\"\n\
+         #EXTINF:4.000000,\nstream_00000.m4s\n#EXTINF:4.000000,\nstream_00001.m4s\n\
+         #EXT-X-DISCONTINUITY\n#EXT-X-MAP:URI=\"stream_20240501T080112Z_init.mp4\"\n\
+         #EXTINF:4.000000,\nstream_00002.m4s\n"
+    );
+}
@@ -0,0 +1,153 @@
+use std::io::Cursor;
+
+use otnvr::rtsp_server::{
+    Packetizer, RestreamHub, RestreamTrack, RtspRequest, TrackCodec, Transport, camera_id,
+    parse_stream_uri, parse_transport, session_description,
+};
+
+fn h264_track() -> RestreamTrack {
+    RestreamTrack {
+        codec: TrackCodec::H264,
+        clock_rate: 90_000,
+        channels: 1,
+        extradata: vec![
+            0, 0, 0, 1, 0x67, 0x64, 0x00, 0x1f, 0xac, 0, 0, 0, 1, 0x68, 0xee, 0x3c, 0x80,
+        ],
+    }
+}
+
+fn aac_track() -> RestreamTrack {
+    RestreamTrack {
+        codec: TrackCodec::Aac,
+        clock_rate: 48_000,
+        channels: 2,
+        extradata: vec![0x11, 0x90],
+    }
+}
+
+#[test]
+fn camera_id_is_a_url_friendly_name() {
+    assert_eq!(camera_id("Front Door"), "front-door");
+    assert_eq!(camera_id("192.168.1.20"), "192-168-1-20");
+    assert_eq!(camera_id("  Garage / East  "), "garage-east");
+    assert_eq!(camera_id("***"), "camera");
+}
+
+#[test]
+fn stream_uri_names_camera_and_track() {
+    assert_eq!(
+        parse_stream_uri("rtsp://nvr:8554/front-door"),
+        Some(("front-door".to_string(), None))
+    );
+    assert_eq!(
+        parse_stream_uri("rtsp://nvr:8554/front-door/trackID=1"),
+        Some(("front-door".to_string(), Some(1)))
+    );
+    assert_eq!(
+        parse_stream_uri("rtsp://nvr/front-door/?token=x"),
+        Some(("front-door".to_string(), None))
+    );
+    assert_eq!(parse_stream_uri("rtsp://nvr:8554/"), None);
+}
+
+#[test]
+fn transport_header_prefers_first_supported_spec() {
+    assert_eq!(
+        parse_transport("RTP/AVP/TCP;unicast;interleaved=2-3"),
+        Some(Transport::Tcp {
+            interleaved: (2, 3)
+        })
+    );
+    assert_eq!(
+        parse_transport("RTP/AVP;multicast;port=5000-5001,RTP/AVP;unicast;client_port=6000-6001"),
+        Some(Transport::Udp {
+            client_ports: (6000, 6001)
+        })
+    );
+    assert_eq!(parse_transport("RAW/RAW/UDP;unicast;client_port=1-2"), None);
+}
+
+#[test]
+fn request_reader_skips_interleaved_rtcp() {
+    let mut input = vec![b'$', 1, 0, 3, 9, 9, 9];
+    input.extend_from_slice(
+        b"PLAY rtsp://nvr/front-door/ RTSP/1.0\r\nCSeq: 4\r\nSession: ABC;timeout=60\r\n\r\n",
+    );
+    let mut reader = Cursor::new(input);
+
+    let request = RtspRequest::read(&mut reader)
+        .expect("read request")
+        .expect("request");
+
+    assert_eq!(request.method, "PLAY");
+    assert_eq!(request.uri, "rtsp://nvr/front-door/");
+    assert_eq!(request.cseq.as_deref(), Some("4"));
+    assert_eq!(request.session.as_deref(), Some("ABC"));
+    assert_eq!(RtspRequest::read(&mut reader).expect("read eof"), None);
+}
+
+#[test]
+fn session_description_lists_tracks_with_parameter_sets() {
+    let sdp = session_description("front-door", &[h264_track(), aac_track()]);
+
+    assert!(sdp.contains("m=video 0 RTP/AVP 96\r\n"));
+    assert!(sdp.contains("a=rtpmap:96 H264/90000\r\n"));
+    assert!(sdp.contains(
+        "a=fmtp:96 packetization-mode=1; profile-level-id=64001F; sprop-parameter-sets=Z2QAH6w=,aO48gA==\r\n"
+    ));
+    assert!(sdp.contains("a=control:trackID=0\r\n"));
+    assert!(sdp.contains("m=audio 0 RTP/AVP 97\r\n"));
+    assert!(sdp.contains("a=rtpmap:97 MPEG4-GENERIC/48000/2\r\n"));
+    assert!(sdp.contains("config=1190\r\n"));
+    assert!(sdp.contains("a=control:trackID=1\r\n"));
+}
+
+#[test]
+fn large_nal_units_are_fragmented() {
+    let mut packetizer = Packetizer::new(&h264_track(), 96, 0x1234_5678);
+    let mut access_unit = vec![0, 0, 0, 1, 0x09, 0xf0, 0, 0, 0, 1, 0x65];
+    access_unit.extend(std::iter::repeat_n(0xab, 3000));
+
+    let packets = packetizer.packetize(&access_unit, 9000);
+
+    // The access unit delimiter fits in one packet, the slice needs three FU-A fragments.
+    assert_eq!(packets.len(), 4);
+    assert_eq!(
+        &packets[0][..12],
+        &[0x80, 96, 0, 0, 0, 0, 0x23, 0x28, 0x12, 0x34, 0x56, 0x78]
+    );
+    assert_eq!(&packets[0][12..], &[0x09, 0xf0]);
+    assert_eq!(&packets[1][12..14], &[0x7c, 0x85]);
+    assert_eq!(&packets[2][12..14], &[0x7c, 0x05]);
+    assert_eq!(&packets[3][12..14], &[0x7c, 0x45]);
+    // Only the last packet of the access unit carries the marker bit.
+    assert_eq!(packets[2][1], 96);
+    assert_eq!(packets[3][1], 0x80 | 96);
+    assert_eq!(u16::from_be_bytes([packets[3][2], packets[3][3]]), 3);
+    assert_eq!(packetizer.next_sequence(), 4);
+    assert_eq!(packetizer.timestamp(), 9000);
+}
+
+#[test]
+fn aac_frames_get_an_au_header() {
+    let mut packetizer = Packetizer::new(&aac_track(), 97, 1);
+
+    let packets = packetizer.packetize(&[1, 2, 3, 4, 5], 1024);
+
+    assert_eq!(packets.len(), 1);
+    assert_eq!(&packets[0][12..], &[0, 16, 0, 40, 1, 2, 3, 4, 5]);
+}
+
+#[test]
+fn dropping_the_publisher_unpublishes_the_camera() {
+    let hub = RestreamHub::new();
+    let publisher = hub.publish("front-door", vec![h264_track()]);
+    let _other = hub.publish("garage", vec![aac_track()]);
+
+    assert_eq!(hub.camera_ids(), vec!["front-door", "garage"]);
+
+    publisher.send(0, &[0, 0, 0, 1, 0x65, 1, 2, 3], 0, true);
+    drop(publisher);
+
+    assert_eq!(hub.camera_ids(), vec!["garage"]);
+}
@@ -0,0 +1,113 @@
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use otnvr::server::{
+    ByteRange, HeadTooLarge, MAX_HEAD_BYTES, MAX_HEADERS, Request, content_type, parse_range,
+    resolve_path,
+};
+
+#[test]
+fn request_read_parses_path_query_and_range() {
+    let raw = "GET /cam%20one/stream.m3u8?_HLS_msn=4&_HLS_part=1 HTTP/1.1\r\nHost: x\r\nRange: bytes=10-\r\n\r\n";
+    let mut reader = Cursor::new(raw.as_bytes());
+
+    let request = Request::read(&mut reader)
+        .expect("read request")
+        .expect("request present");
+
+    assert_eq!(request.method, "GET");
+    assert_eq!(request.path, "/cam one/stream.m3u8");
+    assert_eq!(request.query_param("_HLS_msn"), Some("4"));
+    assert_eq!(request.query_param("_HLS_part"), Some("1"));
+    assert_eq!(request.query_param("missing"), None);
+    assert_eq!(request.range.as_deref(), Some("bytes=10-"));
+    assert!(Request::read(&mut reader).expect("eof").is_none());
+}
+
+#[test]
+fn request_read_rejects_oversized_heads() {
+    // An endless request line is cut off instead of being buffered.
+    let endless = std::iter::repeat_n(b'a', MAX_HEAD_BYTES as usize * 4).collect::<Vec<_>>();
+    let error = Request::read(&mut Cursor::new(endless)).expect_err("line too long");
+    assert!(HeadTooLarge::caused(&error));
+
+    let mut raw = "GET / HTTP/1.1\r\n".to_string();
+    for index in 0..=MAX_HEADERS {
+        raw.push_str(&format!("X-Header-{index}: x\r\n"));
+    }
+    raw.push_str("\r\n");
+    let error = Request::read(&mut Cursor::new(raw.as_bytes())).expect_err("too many headers");
+    assert!(HeadTooLarge::caused(&error));
+
+    let error = Request::read(&mut Cursor::new(b"GARBAGE\r\n\r\n")).expect_err("malformed");
+    assert!(!HeadTooLarge::caused(&error));
+}
+
+#[test]
+fn parse_range_accepts_closed_open_and_suffix_ranges() {
+    assert_eq!(
+        parse_range("bytes=0-99"),
+        Some(ByteRange::From(0, Some(99)))
+    );
+    assert_eq!(parse_range("bytes=120-"), Some(ByteRange::From(120, None)));
+    assert_eq!(parse_range("bytes=-500"), Some(ByteRange::Suffix(500)));
+    assert_eq!(parse_range("bytes=9-3"), None);
+    assert_eq!(parse_range("bytes=-"), None);
+    assert_eq!(parse_range("items=0-1"), None);
+}
+
+#[test]
+fn byte_ranges_resolve_against_the_file_length() {
+    assert_eq!(ByteRange::From(0, Some(99)).resolve(50), Some((0, 49)));
+    assert_eq!(ByteRange::From(10, None).resolve(50), Some((10, 49)));
+    assert_eq!(ByteRange::From(50, None).resolve(50), None);
+    assert_eq!(ByteRange::Suffix(20).resolve(50), Some((30, 49)));
+    assert_eq!(ByteRange::Suffix(500).resolve(50), Some((0, 49)));
+    assert_eq!(ByteRange::Suffix(0).resolve(50), None);
+    assert_eq!(ByteRange::Suffix(20).resolve(0), None);
+}
+
+#[test]
+fn resolve_path_rejects_traversal() {
+    let root = Path::new("/srv/hls");
+
+    assert_eq!(
+        resolve_path(root, "/cam/stream.m3u8"),
+        Some(PathBuf::from("/srv/hls/cam/stream.m3u8"))
+    );
+    assert_eq!(resolve_path(root, "/../etc/passwd"), None);
+    assert_eq!(resolve_path(root, "/cam/../../secret"), None);
+}
+
+#[test]
+fn content_type_covers_recorder_outputs() {
+    assert_eq!(
+        content_type(Path::new("a/stream.m3u8")),
+        "application/vnd.apple.mpegurl"
+    );
+    assert_eq!(content_type(Path::new("seg.m4s")), "video/iso.segment");
+    assert_eq!(content_type(Path::new("seg.ts")), "video/mp2t");
+    assert_eq!(content_type(Path::new("latest.jpg")), "image/jpeg");
+    assert_eq!(content_type(Path::new("stream_thumbnails.vtt")), "text/vtt");
+}
+
+#[test]
+fn key_requests_require_the_configured_token() {
+    let read = |raw: &str| {
+        Request::read(&mut Cursor::new(raw.as_bytes()))
+            .expect("read request")
+            .expect("request present")
+    };
+
+    let bearer =
+        read("GET /keys/cam/stream_00000.key HTTP/1.1\r\nAuthorization: Bearer s3cret\r\n\r\n");
+    assert_eq!(bearer.authorization.as_deref(), Some("Bearer s3cret"));
+    assert!(bearer.is_authorized("s3cret"));
+    assert!(!bearer.is_authorized("other"));
+
+    let query = read("GET /keys/cam/stream_00000.key?token=s3cret HTTP/1.1\r\n\r\n");
+    assert!(query.is_authorized("s3cret"));
+
+    let anonymous = read("GET /keys/cam/stream_00000.key HTTP/1.1\r\n\r\n");
+    assert!(!anonymous.is_authorized("s3cret"));
+}